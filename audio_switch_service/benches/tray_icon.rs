@@ -0,0 +1,73 @@
+//! Rendering a tray icon before and after the base image and the icons were cached.
+
+// The service's modules are only partly used here, and their tests are built without running.
+#![allow(dead_code, unused_imports)]
+
+#[path = "../src/color.rs"]
+mod color;
+#[path = "../src/config.rs"]
+mod config;
+#[path = "../src/dyn_icon.rs"]
+mod dyn_icon;
+#[path = "../src/theme.rs"]
+mod theme;
+
+use std::hint::black_box;
+use std::io::Cursor;
+
+use criterion::{Criterion, criterion_group, criterion_main};
+use image::{Rgba, RgbaImage};
+
+use color::Color;
+use dyn_icon::{Badges, IconCache};
+use theme::Theme;
+
+/// How the tray rendered a profile's icon before the cache: decode the embedded PNG and blend
+/// it onto the color with floats at full size, for every switch.
+fn generate_icon_f32(color: Color) -> RgbaImage {
+    let mut image = image::ImageReader::new(Cursor::new(include_bytes!("../assets/app.png")));
+    image.set_format(image::ImageFormat::Png);
+    let overlay = image.decode().expect("Failed to load image.").into_rgba8();
+
+    let background = Rgba([color.r, color.g, color.b, 255]);
+    let mut tinted = RgbaImage::from_pixel(overlay.width(), overlay.height(), background);
+
+    for (x, y, overlay_pixel) in overlay.enumerate_pixels() {
+        let bg_pixel = tinted.get_pixel(x, y);
+        let alpha = overlay_pixel.0[3] as f32 / 255.0;
+
+        let mut blended = [255; 4];
+        for (c, out) in blended[..3].iter_mut().enumerate() {
+            *out = (overlay_pixel.0[c] as f32 * alpha + bg_pixel.0[c] as f32 * (1.0 - alpha))
+                .round() as u8;
+        }
+        tinted.put_pixel(x, y, Rgba(blended));
+    }
+
+    tinted
+}
+
+fn render(c: &mut Criterion) {
+    let color = Color::rgb(255, 136, 0);
+
+    c.bench_function("generate_icon f32", |b| {
+        b.iter(|| generate_icon_f32(black_box(color)))
+    });
+    c.bench_function("compose 24px", |b| {
+        b.iter(|| dyn_icon::compose(24, black_box(Some(color)), Badges::default(), Theme::Dark))
+    });
+    c.bench_function("tint all sizes", |b| {
+        b.iter(|| dyn_icon::generate_icon_set(black_box(color)))
+    });
+
+    let mut cache = IconCache::default();
+    cache
+        .get(Some(color), Badges::default(), Theme::Dark)
+        .expect("Failed to render icon.");
+    c.bench_function("IconCache::get hit", |b| {
+        b.iter(|| cache.get(black_box(Some(color)), Badges::default(), Theme::Dark))
+    });
+}
+
+criterion_group!(benches, render);
+criterion_main!(benches);
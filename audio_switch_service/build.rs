@@ -0,0 +1,9 @@
+fn main() {
+    // Embeds the package version as a version resource, the setup reads it to decide whether an
+    // installation is an upgrade or a downgrade.
+    let mut res = winres::WindowsResource::new();
+    res.set_toolkit_path(r#"C:\Program Files (x86)\Windows Kits\10\bin\10.0.26100.0\x64"#);
+    res.set("FileDescription", "AudioSwitch Service");
+    res.compile()
+        .expect("Failed to compile binary with version resource.");
+}
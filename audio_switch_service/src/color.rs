@@ -0,0 +1,435 @@
+use std::str::FromStr;
+
+use anyhow::{Context, Result, anyhow, bail};
+use serde::{Deserialize, Serialize};
+
+/// An sRGB color with alpha.
+///
+/// Parses `#RGB`, `#RRGGBB`, `#RRGGBBAA`, `rgb(…)`/`rgba(…)`, `hsl(…)`/`hsla(…)` and CSS named
+/// colors. It is always written back in the canonical `#rrggbb` form, or `#rrggbbaa` when it is
+/// not fully opaque.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
+}
+
+impl Color {
+    pub const fn rgb(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b, a: 255 }
+    }
+}
+
+impl std::fmt::Display for Color {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "#{:02x}{:02x}{:02x}", self.r, self.g, self.b)?;
+
+        if self.a != 255 {
+            write!(f, "{:02x}", self.a)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for Color {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let input = s.trim().to_ascii_lowercase();
+
+        if let Some(hex) = input.strip_prefix('#') {
+            return parse_hex(hex);
+        }
+
+        if let Some((function, args)) = input.strip_suffix(')').and_then(|it| it.split_once('(')) {
+            let args = split_args(args);
+
+            return match function.trim() {
+                "rgb" | "rgba" => parse_rgb(&args),
+                "hsl" | "hsla" => parse_hsl(&args),
+                other => Err(anyhow!("Unknown color function '{other}'.")),
+            };
+        }
+
+        NAMED_COLORS
+            .iter()
+            .find(|(name, _)| *name == input)
+            .map(|(_, color)| *color)
+            .ok_or_else(|| anyhow!("Unknown color '{}'.", s.trim()))
+    }
+}
+
+impl TryFrom<String> for Color {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> Result<Self> {
+        value.parse()
+    }
+}
+
+impl From<Color> for String {
+    fn from(value: Color) -> Self {
+        value.to_string()
+    }
+}
+
+fn parse_hex(hex: &str) -> Result<Color> {
+    if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        bail!("Invalid hex value, valid characters are: [0-9A-F].");
+    }
+
+    let digits = match hex.len() {
+        // Short form, every digit is doubled: #f80 == #ff8800
+        3 | 4 => hex.chars().flat_map(|c| [c, c]).collect::<String>(),
+        6 | 8 => hex.to_string(),
+        _ => bail!("Hex color must be 3, 4, 6 or 8 digits long."),
+    };
+
+    let channel = |i: usize| u8::from_str_radix(&digits[i..i + 2], 16);
+
+    Ok(Color {
+        r: channel(0)?,
+        g: channel(2)?,
+        b: channel(4)?,
+        a: if digits.len() == 8 { channel(6)? } else { 255 },
+    })
+}
+
+/// Splits function arguments separated by commas, whitespace or the `/` before the alpha value.
+fn split_args(args: &str) -> Vec<&str> {
+    args.split(|c: char| c == ',' || c == '/' || c.is_whitespace())
+        .filter(|it| !it.is_empty())
+        .collect()
+}
+
+fn parse_rgb(args: &[&str]) -> Result<Color> {
+    let [r, g, b, alpha @ ..] = args else {
+        bail!("rgb() needs three channel values.");
+    };
+
+    let channel = |value: &str| -> Result<u8> {
+        let value = match value.strip_suffix('%') {
+            Some(percent) => parse_number(percent)? * 2.55,
+            None => parse_number(value)?,
+        };
+        Ok(value.clamp(0.0, 255.0).round() as u8)
+    };
+
+    Ok(Color {
+        r: channel(r)?,
+        g: channel(g)?,
+        b: channel(b)?,
+        a: parse_alpha(alpha)?,
+    })
+}
+
+fn parse_hsl(args: &[&str]) -> Result<Color> {
+    let [h, s, l, alpha @ ..] = args else {
+        bail!("hsl() needs hue, saturation and lightness.");
+    };
+
+    let hue = parse_number(h.trim_end_matches("deg"))?.rem_euclid(360.0);
+    let percent = |value: &str| -> Result<f32> {
+        Ok((parse_number(value.trim_end_matches('%'))? / 100.0).clamp(0.0, 1.0))
+    };
+    let (saturation, lightness) = (percent(s)?, percent(l)?);
+
+    // https://www.w3.org/TR/css-color-3/#hsl-color
+    let chroma = (1.0 - (2.0 * lightness - 1.0).abs()) * saturation;
+    let x = chroma * (1.0 - ((hue / 60.0) % 2.0 - 1.0).abs());
+    let m = lightness - chroma / 2.0;
+
+    let (r, g, b) = match hue as u32 / 60 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+
+    let channel = |value: f32| ((value + m) * 255.0).round() as u8;
+
+    Ok(Color {
+        r: channel(r),
+        g: channel(g),
+        b: channel(b),
+        a: parse_alpha(alpha)?,
+    })
+}
+
+fn parse_alpha(alpha: &[&str]) -> Result<u8> {
+    match alpha {
+        [] => Ok(255),
+        [value] => {
+            let value = match value.strip_suffix('%') {
+                Some(percent) => parse_number(percent)? / 100.0,
+                None => parse_number(value)?,
+            };
+            Ok((value.clamp(0.0, 1.0) * 255.0).round() as u8)
+        }
+        _ => bail!("Too many color arguments."),
+    }
+}
+
+fn parse_number(value: &str) -> Result<f32> {
+    value
+        .parse::<f32>()
+        .ok()
+        .filter(|it| it.is_finite())
+        .with_context(|| format!("'{value}' is not a number."))
+}
+
+/// The CSS Color Module Level 4 named colors.
+pub const NAMED_COLORS: &[(&str, Color)] = &[
+    ("aliceblue", Color::rgb(240, 248, 255)),
+    ("antiquewhite", Color::rgb(250, 235, 215)),
+    ("aqua", Color::rgb(0, 255, 255)),
+    ("aquamarine", Color::rgb(127, 255, 212)),
+    ("azure", Color::rgb(240, 255, 255)),
+    ("beige", Color::rgb(245, 245, 220)),
+    ("bisque", Color::rgb(255, 228, 196)),
+    ("black", Color::rgb(0, 0, 0)),
+    ("blanchedalmond", Color::rgb(255, 235, 205)),
+    ("blue", Color::rgb(0, 0, 255)),
+    ("blueviolet", Color::rgb(138, 43, 226)),
+    ("brown", Color::rgb(165, 42, 42)),
+    ("burlywood", Color::rgb(222, 184, 135)),
+    ("cadetblue", Color::rgb(95, 158, 160)),
+    ("chartreuse", Color::rgb(127, 255, 0)),
+    ("chocolate", Color::rgb(210, 105, 30)),
+    ("coral", Color::rgb(255, 127, 80)),
+    ("cornflowerblue", Color::rgb(100, 149, 237)),
+    ("cornsilk", Color::rgb(255, 248, 220)),
+    ("crimson", Color::rgb(220, 20, 60)),
+    ("cyan", Color::rgb(0, 255, 255)),
+    ("darkblue", Color::rgb(0, 0, 139)),
+    ("darkcyan", Color::rgb(0, 139, 139)),
+    ("darkgoldenrod", Color::rgb(184, 134, 11)),
+    ("darkgray", Color::rgb(169, 169, 169)),
+    ("darkgreen", Color::rgb(0, 100, 0)),
+    ("darkgrey", Color::rgb(169, 169, 169)),
+    ("darkkhaki", Color::rgb(189, 183, 107)),
+    ("darkmagenta", Color::rgb(139, 0, 139)),
+    ("darkolivegreen", Color::rgb(85, 107, 47)),
+    ("darkorange", Color::rgb(255, 140, 0)),
+    ("darkorchid", Color::rgb(153, 50, 204)),
+    ("darkred", Color::rgb(139, 0, 0)),
+    ("darksalmon", Color::rgb(233, 150, 122)),
+    ("darkseagreen", Color::rgb(143, 188, 143)),
+    ("darkslateblue", Color::rgb(72, 61, 139)),
+    ("darkslategray", Color::rgb(47, 79, 79)),
+    ("darkslategrey", Color::rgb(47, 79, 79)),
+    ("darkturquoise", Color::rgb(0, 206, 209)),
+    ("darkviolet", Color::rgb(148, 0, 211)),
+    ("deeppink", Color::rgb(255, 20, 147)),
+    ("deepskyblue", Color::rgb(0, 191, 255)),
+    ("dimgray", Color::rgb(105, 105, 105)),
+    ("dimgrey", Color::rgb(105, 105, 105)),
+    ("dodgerblue", Color::rgb(30, 144, 255)),
+    ("firebrick", Color::rgb(178, 34, 34)),
+    ("floralwhite", Color::rgb(255, 250, 240)),
+    ("forestgreen", Color::rgb(34, 139, 34)),
+    ("fuchsia", Color::rgb(255, 0, 255)),
+    ("gainsboro", Color::rgb(220, 220, 220)),
+    ("ghostwhite", Color::rgb(248, 248, 255)),
+    ("gold", Color::rgb(255, 215, 0)),
+    ("goldenrod", Color::rgb(218, 165, 32)),
+    ("gray", Color::rgb(128, 128, 128)),
+    ("green", Color::rgb(0, 128, 0)),
+    ("greenyellow", Color::rgb(173, 255, 47)),
+    ("grey", Color::rgb(128, 128, 128)),
+    ("honeydew", Color::rgb(240, 255, 240)),
+    ("hotpink", Color::rgb(255, 105, 180)),
+    ("indianred", Color::rgb(205, 92, 92)),
+    ("indigo", Color::rgb(75, 0, 130)),
+    ("ivory", Color::rgb(255, 255, 240)),
+    ("khaki", Color::rgb(240, 230, 140)),
+    ("lavender", Color::rgb(230, 230, 250)),
+    ("lavenderblush", Color::rgb(255, 240, 245)),
+    ("lawngreen", Color::rgb(124, 252, 0)),
+    ("lemonchiffon", Color::rgb(255, 250, 205)),
+    ("lightblue", Color::rgb(173, 216, 230)),
+    ("lightcoral", Color::rgb(240, 128, 128)),
+    ("lightcyan", Color::rgb(224, 255, 255)),
+    ("lightgoldenrodyellow", Color::rgb(250, 250, 210)),
+    ("lightgray", Color::rgb(211, 211, 211)),
+    ("lightgreen", Color::rgb(144, 238, 144)),
+    ("lightgrey", Color::rgb(211, 211, 211)),
+    ("lightpink", Color::rgb(255, 182, 193)),
+    ("lightsalmon", Color::rgb(255, 160, 122)),
+    ("lightseagreen", Color::rgb(32, 178, 170)),
+    ("lightskyblue", Color::rgb(135, 206, 250)),
+    ("lightslategray", Color::rgb(119, 136, 153)),
+    ("lightslategrey", Color::rgb(119, 136, 153)),
+    ("lightsteelblue", Color::rgb(176, 196, 222)),
+    ("lightyellow", Color::rgb(255, 255, 224)),
+    ("lime", Color::rgb(0, 255, 0)),
+    ("limegreen", Color::rgb(50, 205, 50)),
+    ("linen", Color::rgb(250, 240, 230)),
+    ("magenta", Color::rgb(255, 0, 255)),
+    ("maroon", Color::rgb(128, 0, 0)),
+    ("mediumaquamarine", Color::rgb(102, 205, 170)),
+    ("mediumblue", Color::rgb(0, 0, 205)),
+    ("mediumorchid", Color::rgb(186, 85, 211)),
+    ("mediumpurple", Color::rgb(147, 112, 219)),
+    ("mediumseagreen", Color::rgb(60, 179, 113)),
+    ("mediumslateblue", Color::rgb(123, 104, 238)),
+    ("mediumspringgreen", Color::rgb(0, 250, 154)),
+    ("mediumturquoise", Color::rgb(72, 209, 204)),
+    ("mediumvioletred", Color::rgb(199, 21, 133)),
+    ("midnightblue", Color::rgb(25, 25, 112)),
+    ("mintcream", Color::rgb(245, 255, 250)),
+    ("mistyrose", Color::rgb(255, 228, 225)),
+    ("moccasin", Color::rgb(255, 228, 181)),
+    ("navajowhite", Color::rgb(255, 222, 173)),
+    ("navy", Color::rgb(0, 0, 128)),
+    ("oldlace", Color::rgb(253, 245, 230)),
+    ("olive", Color::rgb(128, 128, 0)),
+    ("olivedrab", Color::rgb(107, 142, 35)),
+    ("orange", Color::rgb(255, 165, 0)),
+    ("orangered", Color::rgb(255, 69, 0)),
+    ("orchid", Color::rgb(218, 112, 214)),
+    ("palegoldenrod", Color::rgb(238, 232, 170)),
+    ("palegreen", Color::rgb(152, 251, 152)),
+    ("paleturquoise", Color::rgb(175, 238, 238)),
+    ("palevioletred", Color::rgb(219, 112, 147)),
+    ("papayawhip", Color::rgb(255, 239, 213)),
+    ("peachpuff", Color::rgb(255, 218, 185)),
+    ("peru", Color::rgb(205, 133, 63)),
+    ("pink", Color::rgb(255, 192, 203)),
+    ("plum", Color::rgb(221, 160, 221)),
+    ("powderblue", Color::rgb(176, 224, 230)),
+    ("purple", Color::rgb(128, 0, 128)),
+    ("rebeccapurple", Color::rgb(102, 51, 153)),
+    ("red", Color::rgb(255, 0, 0)),
+    ("rosybrown", Color::rgb(188, 143, 143)),
+    ("royalblue", Color::rgb(65, 105, 225)),
+    ("saddlebrown", Color::rgb(139, 69, 19)),
+    ("salmon", Color::rgb(250, 128, 114)),
+    ("sandybrown", Color::rgb(244, 164, 96)),
+    ("seagreen", Color::rgb(46, 139, 87)),
+    ("seashell", Color::rgb(255, 245, 238)),
+    ("sienna", Color::rgb(160, 82, 45)),
+    ("silver", Color::rgb(192, 192, 192)),
+    ("skyblue", Color::rgb(135, 206, 235)),
+    ("slateblue", Color::rgb(106, 90, 205)),
+    ("slategray", Color::rgb(112, 128, 144)),
+    ("slategrey", Color::rgb(112, 128, 144)),
+    ("snow", Color::rgb(255, 250, 250)),
+    ("springgreen", Color::rgb(0, 255, 127)),
+    ("steelblue", Color::rgb(70, 130, 180)),
+    ("tan", Color::rgb(210, 180, 140)),
+    ("teal", Color::rgb(0, 128, 128)),
+    ("thistle", Color::rgb(216, 191, 216)),
+    ("tomato", Color::rgb(255, 99, 71)),
+    ("turquoise", Color::rgb(64, 224, 208)),
+    ("violet", Color::rgb(238, 130, 238)),
+    ("wheat", Color::rgb(245, 222, 179)),
+    ("white", Color::rgb(255, 255, 255)),
+    ("whitesmoke", Color::rgb(245, 245, 245)),
+    ("yellow", Color::rgb(255, 255, 0)),
+    ("yellowgreen", Color::rgb(154, 205, 50)),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rgba(r: u8, g: u8, b: u8, a: u8) -> Color {
+        Color { r, g, b, a }
+    }
+
+    fn assert_parses(cases: &[(&str, Color)]) {
+        for (input, expected) in cases {
+            let parsed: Color = input
+                .parse()
+                .unwrap_or_else(|e| panic!("{input:?} failed: {e}"));
+            assert_eq!(parsed, *expected, "{input:?}");
+        }
+    }
+
+    #[test]
+    fn parses_hex() {
+        assert_parses(&[
+            ("#f80", Color::rgb(255, 136, 0)),
+            ("#f808", rgba(255, 136, 0, 136)),
+            ("#FF8800", Color::rgb(255, 136, 0)),
+            ("#ff880080", rgba(255, 136, 0, 128)),
+            ("  #00Ff00 ", Color::rgb(0, 255, 0)),
+        ]);
+    }
+
+    #[test]
+    fn parses_rgb() {
+        assert_parses(&[
+            ("rgb(255, 136, 0)", Color::rgb(255, 136, 0)),
+            ("rgb(100%, 50%, 0%)", Color::rgb(255, 128, 0)),
+            ("rgb(300, -5, 0)", Color::rgb(255, 0, 0)),
+            ("rgba(0, 0, 0, 0.5)", rgba(0, 0, 0, 128)),
+            ("rgba(255 136 0 / 25%)", rgba(255, 136, 0, 64)),
+            ("RGB(1,2,3)", Color::rgb(1, 2, 3)),
+        ]);
+    }
+
+    #[test]
+    fn parses_hsl() {
+        assert_parses(&[
+            ("hsl(0, 100%, 50%)", Color::rgb(255, 0, 0)),
+            ("hsl(120deg, 100%, 25%)", Color::rgb(0, 128, 0)),
+            ("hsl(360, 100%, 50%)", Color::rgb(255, 0, 0)),
+            ("hsl(480, 100%, 50%)", Color::rgb(0, 255, 0)),
+            ("hsl(-120, 100%, 50%)", Color::rgb(0, 0, 255)),
+            ("hsla(0, 0%, 100%, 50%)", rgba(255, 255, 255, 128)),
+        ]);
+    }
+
+    #[test]
+    fn parses_named_colors() {
+        assert_parses(&[
+            ("teal", Color::rgb(0, 128, 128)),
+            ("Orange", Color::rgb(255, 165, 0)),
+            ("rebeccapurple", Color::rgb(102, 51, 153)),
+        ]);
+    }
+
+    #[test]
+    fn writes_the_canonical_form() {
+        let cases = [
+            (Color::rgb(255, 136, 0), "#ff8800"),
+            (rgba(255, 136, 0, 128), "#ff880080"),
+        ];
+
+        for (color, text) in cases {
+            assert_eq!(String::from(color), text);
+            assert_eq!(Color::try_from(text.to_string()).unwrap(), color);
+        }
+        assert_eq!("teal".parse::<Color>().unwrap().to_string(), "#008080");
+    }
+
+    #[test]
+    fn rejects_malformed_colors() {
+        let cases = [
+            "",
+            "#",
+            "#12",
+            "#12345",
+            "#ggg",
+            "rgb(1, 2)",
+            "rgb(1, 2, 3, 4, 5)",
+            "rgb(1, 2, 3",
+            "rgb(nan, 0, 0)",
+            "hsl(red, 100%, 50%)",
+            "cmyk(0, 0, 0, 0)",
+            "notacolor",
+        ];
+
+        for input in cases {
+            assert!(input.parse::<Color>().is_err(), "{input:?} was accepted");
+        }
+    }
+}
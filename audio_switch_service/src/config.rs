@@ -1,25 +1,127 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
 use serde::{Deserialize, Serialize};
 
+use crate::color::Color;
+
 #[derive(Default, Clone, Debug, Serialize, Deserialize)]
 pub struct Config {
     pub installed: bool,
+    /// Format version of this file, raised by the setup's migrations.
+    #[serde(default)]
+    pub config_version: u32,
     pub profiles: Vec<Profile>,
     pub next_profile: Option<HotkeyConfig>,
     pub previous_profile: Option<HotkeyConfig>,
+    #[serde(default)]
+    pub taskbar_theme: TaskbarTheme,
+    #[serde(default)]
+    pub notifications: NotificationConfig,
+    #[serde(default)]
+    pub log_level: LogLevel,
+    /// The devices of each profile, keyed by hostname and then profile name.
+    #[serde(default)]
+    pub bindings: BTreeMap<String, BTreeMap<String, DeviceBinding>>,
+    /// Folder shared with other machines, e.g. in OneDrive, to sync the profiles through.
+    #[serde(default)]
+    pub sync_dir: Option<PathBuf>,
 }
-#[derive(Default, Clone, Debug, Serialize, Deserialize)]
+
+impl Config {
+    /// The devices of profile `name` on this machine.
+    pub fn binding(&self, name: &str) -> Option<&DeviceBinding> {
+        self.bindings.get(&hostname())?.get(name)
+    }
+}
+
+/// Name of this machine, which keys its bindings.
+pub fn hostname() -> String {
+    std::env::var("COMPUTERNAME").unwrap_or_default()
+}
+
+/// Taskbar theme the tray icon is adjusted for. `Auto` follows the Windows setting.
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TaskbarTheme {
+    #[default]
+    Auto,
+    Light,
+    Dark,
+}
+
+/// The most verbose level the service writes to its log file.
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+    Error,
+    Warn,
+    #[default]
+    Info,
+    Debug,
+    Trace,
+}
+
+/// When and how profile switches are announced.
+///
+/// Titles and bodies are templates, `{profile}`, `{input}` and `{output}` are replaced with the
+/// profile name and its device names.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NotificationConfig {
+    pub on_success: bool,
+    pub on_failure: bool,
+    pub duration: NotificationDuration,
+    pub success_title: String,
+    pub success_body: String,
+    pub failure_title: String,
+    pub failure_body: String,
+}
+
+impl Default for NotificationConfig {
+    fn default() -> Self {
+        Self {
+            on_success: true,
+            on_failure: true,
+            duration: NotificationDuration::Short,
+            success_title: "Activated Profile {profile}".to_string(),
+            success_body: "{output} / {input}".to_string(),
+            failure_title: "Failed to activate Profile {profile}".to_string(),
+            failure_body: String::new(),
+        }
+    }
+}
+
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NotificationDuration {
+    #[default]
+    Short,
+    Long,
+}
+
+/// A profile as it is shared between machines. The devices are name patterns, where `*` matches
+/// any text and `?` any character, which are bound to the endpoints of each machine.
+#[derive(Default, Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Profile {
     pub profile_id: u64,
     pub profile_name: String,
+    pub input_pattern: String,
+    pub output_pattern: String,
+    pub hotkey: HotkeyConfig,
+    pub color: Option<Color>,
+}
+
+/// The endpoints a profile uses on one machine.
+#[derive(Default, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeviceBinding {
     pub input_id: String,
     pub input_name: String,
     pub output_id: String,
     pub output_name: String,
-    pub hotkey: HotkeyConfig,
-    pub color: Option<String>,
 }
 
-#[derive(Default, Clone, Debug, Serialize, Deserialize)]
+#[derive(Default, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct HotkeyConfig {
     pub modifier: Option<String>,
     pub hotkey: String,
@@ -30,11 +132,10 @@ impl std::fmt::Display for HotkeyConfig {
         write!(
             f,
             "{}{}",
-            &self
-                .modifier
+            self.modifier
                 .as_ref()
-                .map_or_else(|| "".to_string(), |i| format!("{}+", i.to_string())),
-            &self.hotkey
+                .map_or_else(|| "".to_string(), |i| format!("{}+", i)),
+            self.hotkey
         )
     }
 }
@@ -44,7 +145,7 @@ impl std::fmt::Display for Profile {
         write!(
             f,
             "Profile {}:\nInput Device: {}\nOutput Device: {}\nHotkey: {}\n",
-            self.profile_name, self.input_name, self.output_name, self.hotkey
+            self.profile_name, self.input_pattern, self.output_pattern, self.hotkey
         )
     }
 }
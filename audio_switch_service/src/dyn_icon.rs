@@ -1,63 +1,390 @@
+use std::collections::HashMap;
 use std::io::Cursor;
+use std::path::Path;
+use std::sync::OnceLock;
 
-use anyhow::{Context, Result, anyhow};
-use image::{GenericImageView, Rgba, RgbaImage};
+use anyhow::{Context, Result};
+use image::codecs::ico::{IcoEncoder, IcoFrame};
+use image::imageops::{self, FilterType};
+use image::{ExtendedColorType, Rgba, RgbaImage};
 use tray_icon::Icon;
+use windows::Win32::UI::HiDpi::{GetDpiForSystem, GetSystemMetricsForDpi};
+use windows::Win32::UI::WindowsAndMessaging::SM_CXSMICON;
 
-pub type HexColor = String;
+use crate::color::Color;
+use crate::theme::{Theme, icon_style};
 
-pub fn generate_icon(color: HexColor) -> Result<Icon> {
-    // Load the source image (which may have transparency)
+/// Edge lengths of the rendered tray icons. These are the small icon sizes Windows asks for
+/// at 100%, 125%, 150%, 200% and 300% display scaling.
+pub const ICON_SIZES: [u32; 5] = [16, 20, 24, 32, 48];
+
+const BASE_DPI: u32 = 96;
+
+/// A tray icon rendered at every size in [`ICON_SIZES`].
+pub struct IconSet {
+    images: Vec<RgbaImage>,
+}
+
+impl IconSet {
+    fn from_image(image: &RgbaImage) -> Self {
+        let images = ICON_SIZES
+            .iter()
+            .map(|&size| downsample(image, size))
+            .collect();
+
+        Self { images }
+    }
+
+    /// Returns the smallest image that is at least `size` pixels wide, or the largest one if
+    /// none is big enough.
+    pub fn best_for_size(&self, size: u32) -> &RgbaImage {
+        self.images
+            .iter()
+            .find(|it| it.width() >= size)
+            .or(self.images.last())
+            .expect("IconSet is never empty.")
+    }
+
+    /// Creates a tray icon with the image matching the current system DPI.
+    pub fn to_tray_icon(&self) -> Result<Icon> {
+        to_tray_icon(self.best_for_size(current_icon_size()))
+    }
+
+    /// Writes all sizes into a single multi-image `.ico` file. A file that already holds them is
+    /// left alone.
+    pub fn write_ico(&self, path: &Path) -> Result<()> {
+        let frames = self
+            .images
+            .iter()
+            .map(|it| {
+                IcoFrame::as_png(
+                    it.as_raw(),
+                    it.width(),
+                    it.height(),
+                    ExtendedColorType::Rgba8,
+                )
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut ico = Vec::new();
+        IcoEncoder::new(&mut ico)
+            .encode_images(&frames)
+            .context("Failed to encode ico file.")?;
+
+        if std::fs::read(path).is_ok_and(|it| it == ico) {
+            return Ok(());
+        }
+
+        Ok(std::fs::write(path, ico)?)
+    }
+}
+
+/// A status indicator drawn into one corner of the tray icon.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Badge {
+    /// The default recording device is muted.
+    MicMuted,
+    /// A device used by a profile is unplugged or disabled.
+    DeviceMissing,
+    /// The last profile activation failed.
+    ActivationFailed,
+    /// The service crashed repeatedly and runs without hotkeys.
+    SafeMode,
+}
+
+impl Badge {
+    const ALL: [Badge; 4] = [
+        Badge::MicMuted,
+        Badge::DeviceMissing,
+        Badge::ActivationFailed,
+        Badge::SafeMode,
+    ];
+
+    fn color(self) -> Color {
+        match self {
+            Badge::MicMuted => Color::rgb(84, 110, 122),
+            Badge::DeviceMissing => Color::rgb(251, 140, 0),
+            Badge::ActivationFailed => Color::rgb(229, 57, 53),
+            Badge::SafeMode => Color::rgb(142, 36, 170),
+        }
+    }
+
+    /// Badge center relative to the icon size, each badge has its own corner.
+    fn anchor(self) -> (f32, f32) {
+        match self {
+            Badge::MicMuted => (0.25, 0.75),
+            Badge::DeviceMissing => (0.75, 0.25),
+            Badge::ActivationFailed => (0.75, 0.75),
+            Badge::SafeMode => (0.25, 0.25),
+        }
+    }
+}
+
+/// The set of badges currently shown on the tray icon.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Badges(u8);
+
+impl Badges {
+    pub fn set(&mut self, badge: Badge, active: bool) {
+        if active {
+            self.0 |= 1 << badge as u8;
+        } else {
+            self.0 &= !(1 << badge as u8);
+        }
+    }
+
+    pub fn contains(self, badge: Badge) -> bool {
+        self.0 & (1 << badge as u8) != 0
+    }
+
+    fn iter(self) -> impl Iterator<Item = Badge> {
+        Badge::ALL.into_iter().filter(move |it| self.contains(*it))
+    }
+}
+
+/// Tray icons that were already rendered, keyed by pixel size, color, badges and taskbar theme.
+/// Switching back and forth between profiles only clones the cached icon handle, without
+/// rendering or allocating.
+#[derive(Default)]
+pub struct IconCache {
+    icons: HashMap<u32, HashMap<IconKey, Icon>>,
+}
+
+type IconKey = (Option<Color>, Badges, Theme);
+
+impl IconCache {
+    /// Returns the icon tinted with `color` (or the plain application icon for `None`) with
+    /// `badges` on top, at the size for the current DPI. It is rendered on first use.
+    pub fn get(&mut self, color: Option<Color>, badges: Badges, theme: Theme) -> Result<Icon> {
+        let size = current_icon_size();
+        let icons = self.icons.entry(size).or_default();
+
+        if let Some(icon) = icons.get(&(color, badges, theme)) {
+            return Ok(icon.clone());
+        }
+
+        let icon = to_tray_icon(&compose(size, color, badges, theme)?)?;
+        icons.insert((color, badges, theme), icon.clone());
+
+        Ok(icon)
+    }
+}
+
+/// Builds the icon for `size`: the base glyph in a color readable on `theme`, tinted with
+/// `color` if there is one, an outline if the color blends into the taskbar, and `badges` on
+/// top.
+pub fn compose(size: u32, color: Option<Color>, badges: Badges, theme: Theme) -> Result<RgbaImage> {
+    let style = icon_style(color, theme);
+    let glyph = recolor(base_icon_set()?.best_for_size(size), style.glyph);
+
+    let mut image = match color {
+        Some(color) => tint(&glyph, color),
+        None => glyph,
+    };
+
+    if let Some(outline) = style.outline {
+        draw_outline(&mut image, outline);
+    }
+
+    for badge in badges.iter() {
+        draw_badge(&mut image, badge);
+    }
+
+    Ok(image)
+}
+
+/// Renders the application icon tinted with `color` at all [`ICON_SIZES`].
+pub fn generate_icon_set(color: Color) -> Result<IconSet> {
+    let images = base_icon_set()?
+        .images
+        .iter()
+        .map(|it| tint(it, color))
+        .collect();
+
+    Ok(IconSet { images })
+}
+
+/// The untinted application icon at all [`ICON_SIZES`]. The embedded PNG is decoded and
+/// downsampled only once per process.
+pub fn base_icon_set() -> Result<&'static IconSet> {
+    static BASE_ICONS: OnceLock<IconSet> = OnceLock::new();
+
+    if let Some(icons) = BASE_ICONS.get() {
+        return Ok(icons);
+    }
+
+    let icons = IconSet::from_image(&load_base_image()?);
+    Ok(BASE_ICONS.get_or_init(|| icons))
+}
+
+fn load_base_image() -> Result<RgbaImage> {
     let img_bytes = include_bytes!("../assets/app.png");
     let mut image = image::ImageReader::new(Cursor::new(img_bytes));
     image.set_format(image::ImageFormat::Png);
-    let img = image.decode().expect("Failed to load image.");
 
-    let (width, height) = img.dimensions();
-    let (r, g, b) = hex_to_rgb(color)?;
+    Ok(image
+        .decode()
+        .context("Failed to load image.")?
+        .into_rgba8())
+}
+
+/// Composites `overlay` onto a background of `color`.
+fn tint(overlay: &RgbaImage, color: Color) -> RgbaImage {
+    let background = [color.r as u32, color.g as u32, color.b as u32];
+    let background_alpha = color.a as u32;
+
+    let mut tinted = overlay.clone();
+
+    for pixel in tinted.pixels_mut() {
+        let alpha = pixel.0[3] as u32;
 
-    let background_color = Rgba([r, g, b, 255]);
+        // Porter-Duff "over": the background shows through where the overlay is transparent.
+        let visible_background = div_255(background_alpha * (255 - alpha));
+        let out_alpha = alpha + visible_background;
 
-    let mut background = RgbaImage::from_pixel(width, height, background_color);
+        if out_alpha == 0 {
+            pixel.0 = [0; 4];
+            continue;
+        }
 
-    let overlay = img.to_rgba8();
+        for (c, bg) in pixel.0[..3].iter_mut().zip(background) {
+            *c = ((*c as u32 * alpha + bg * visible_background + out_alpha / 2) / out_alpha) as u8;
+        }
+        pixel.0[3] = out_alpha as u8;
+    }
 
-    // Composite overlay image onto the background by alpha blending.
-    for (x, y, overlay_pixel) in overlay.enumerate_pixels() {
-        let bg_pixel = background.get_pixel(x, y);
+    tinted
+}
 
-        // Normalize alpha value to [0.0, 1.0]
-        let alpha = overlay_pixel.0[3] as f32 / 255.0;
+/// Replaces the color of every pixel with `color`, keeping its alpha.
+fn recolor(image: &RgbaImage, color: Color) -> RgbaImage {
+    let mut recolored = image.clone();
 
-        // Blend each channel: result = fg * alpha + bg * (1 - alpha)
-        let blended = [
-            (overlay_pixel.0[0] as f32 * alpha + bg_pixel.0[0] as f32 * (1.0 - alpha)).round()
-                as u8,
-            (overlay_pixel.0[1] as f32 * alpha + bg_pixel.0[1] as f32 * (1.0 - alpha)).round()
-                as u8,
-            (overlay_pixel.0[2] as f32 * alpha + bg_pixel.0[2] as f32 * (1.0 - alpha)).round()
-                as u8,
-            255,
-        ];
-        background.put_pixel(x, y, Rgba(blended));
+    for pixel in recolored.pixels_mut() {
+        pixel.0[..3].copy_from_slice(&[color.r, color.g, color.b]);
     }
 
-    let (icon_width, icon_height) = background.dimensions();
-    let icon_rgba = background.into_raw();
+    recolored
+}
+
+/// Draws a border of `color` along the edges of the icon.
+fn draw_outline(image: &mut RgbaImage, color: Color) {
+    let (width, height) = image.dimensions();
+    let thickness = (width / 16).max(1);
 
-    tray_icon::Icon::from_rgba(icon_rgba, icon_width, icon_height).context("Failed to create Icon.")
+    for (x, y, pixel) in image.enumerate_pixels_mut() {
+        if x < thickness || y < thickness || x >= width - thickness || y >= height - thickness {
+            blend_over(pixel, color, 1.0);
+        }
+    }
 }
 
-fn hex_to_rgb(hex: HexColor) -> Result<(u8, u8, u8)> {
-    let hex = hex.trim_start_matches('#');
+/// Draws `badge` as a filled, anti-aliased dot with a white ring so it stands out on any
+/// background.
+fn draw_badge(image: &mut RgbaImage, badge: Badge) {
+    let size = image.width() as f32;
+    let (anchor_x, anchor_y) = badge.anchor();
+    let (center_x, center_y) = (anchor_x * size, anchor_y * size);
+    let radius = (size * 0.2).max(2.5);
+    let ring = (size / 16.0).max(1.0);
+
+    for (x, y, pixel) in image.enumerate_pixels_mut() {
+        let dx = x as f32 + 0.5 - center_x;
+        let dy = y as f32 + 0.5 - center_y;
+        let distance = (dx * dx + dy * dy).sqrt();
 
-    if hex.len() != 6 {
-        return Err(anyhow!("Hex color must be 6 digits long."));
+        let ring_coverage = (radius + ring + 0.5 - distance).clamp(0.0, 1.0);
+        let dot_coverage = (radius + 0.5 - distance).clamp(0.0, 1.0);
+
+        if ring_coverage > 0.0 {
+            blend_over(pixel, Color::rgb(255, 255, 255), ring_coverage);
+        }
+        if dot_coverage > 0.0 {
+            blend_over(pixel, badge.color(), dot_coverage);
+        }
     }
+}
 
-    let r = u8::from_str_radix(&hex[0..2], 16)?;
-    let g = u8::from_str_radix(&hex[2..4], 16)?;
-    let b = u8::from_str_radix(&hex[4..6], 16)?;
+/// Porter-Duff "over" of an opaque `color` with partial `coverage` onto `pixel`.
+fn blend_over(pixel: &mut Rgba<u8>, color: Color, coverage: f32) {
+    let alpha = (coverage * 255.0).round() as u32;
+    let dst_alpha = div_255(pixel.0[3] as u32 * (255 - alpha));
+    let out_alpha = alpha + dst_alpha;
 
-    Ok((r, g, b))
+    if out_alpha == 0 {
+        return;
+    }
+
+    for (c, src) in pixel.0[..3].iter_mut().zip([color.r, color.g, color.b]) {
+        *c = ((src as u32 * alpha + *c as u32 * dst_alpha + out_alpha / 2) / out_alpha) as u8;
+    }
+    pixel.0[3] = out_alpha as u8;
+}
+
+/// Rounded division by 255 for values up to 255 * 255, without an actual division.
+fn div_255(value: u32) -> u32 {
+    let value = value + 128;
+    (value + (value >> 8)) >> 8
+}
+
+fn to_tray_icon(image: &RgbaImage) -> Result<Icon> {
+    let (width, height) = image.dimensions();
+
+    Icon::from_rgba(image.as_raw().clone(), width, height).context("Failed to create Icon.")
+}
+
+/// Size in pixels of a small icon at the current system DPI.
+fn current_icon_size() -> u32 {
+    unsafe {
+        let dpi = GetDpiForSystem();
+        let size = GetSystemMetricsForDpi(SM_CXSMICON, dpi);
+
+        if size > 0 {
+            size as u32
+        } else {
+            16 * dpi.max(BASE_DPI) / BASE_DPI
+        }
+    }
+}
+
+/// Scales `image` down to `size`x`size`. Color channels are premultiplied with alpha while
+/// filtering, so transparent pixels don't bleed dark fringes into the edges.
+fn downsample(image: &RgbaImage, size: u32) -> RgbaImage {
+    if image.width() == size && image.height() == size {
+        return image.clone();
+    }
+
+    let mut premultiplied = image.clone();
+    for pixel in premultiplied.pixels_mut() {
+        let alpha = pixel.0[3] as u32;
+        for c in &mut pixel.0[..3] {
+            *c = div_255(*c as u32 * alpha) as u8;
+        }
+    }
+
+    let mut resized = imageops::resize(&premultiplied, size, size, FilterType::Lanczos3);
+    for pixel in resized.pixels_mut() {
+        let alpha = pixel.0[3] as u32;
+        if alpha == 0 {
+            continue;
+        }
+        for c in &mut pixel.0[..3] {
+            *c = ((*c as u32 * 255 + alpha / 2) / alpha).min(255) as u8;
+        }
+    }
+
+    resized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn div_255_rounds_like_float_division() {
+        for value in 0..=255 * 255 {
+            let expected = (value as f32 / 255.0).round() as u32;
+            assert_eq!(div_255(value), expected, "div_255({value})");
+        }
+    }
 }
@@ -0,0 +1,132 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::os::windows::io::{FromRawHandle, RawHandle};
+use std::sync::mpsc::Sender;
+use std::thread;
+
+use anyhow::Result;
+use tracing::{error, info, warn};
+use windows::Win32::Foundation::{
+    CloseHandle, ERROR_ALREADY_EXISTS, ERROR_PIPE_CONNECTED, GetLastError, HANDLE,
+};
+use windows::Win32::Storage::FileSystem::PIPE_ACCESS_DUPLEX;
+use windows::Win32::System::Pipes::{
+    ConnectNamedPipe, CreateNamedPipeW, PIPE_READMODE_BYTE, PIPE_REJECT_REMOTE_CLIENTS,
+    PIPE_TYPE_BYTE, PIPE_UNLIMITED_INSTANCES, PIPE_WAIT,
+};
+use windows::Win32::System::Threading::CreateMutexW;
+use windows::core::HSTRING;
+
+use crate::ipc::{PIPE_NAME, Request};
+use crate::shutdown::Shutdown;
+use crate::switcher::Command;
+
+/// Marks the running service.
+pub const SERVICE_MUTEX: &str = "Local\\AudioSwitchService";
+
+/// Marks the running supervisor.
+pub const SUPERVISOR_MUTEX: &str = "Local\\AudioSwitchSupervisor";
+
+/// Holds a named mutex for as long as it lives.
+pub struct InstanceGuard(HANDLE);
+
+impl Drop for InstanceGuard {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = CloseHandle(self.0);
+        }
+    }
+}
+
+/// Claims the single instance marked by the mutex `name` for this session. Returns `None` if
+/// another process already holds it.
+pub fn acquire(name: &str) -> Result<Option<InstanceGuard>> {
+    unsafe {
+        let handle = CreateMutexW(None, true, &HSTRING::from(name))?;
+
+        if let Err(err) = GetLastError()
+            && err.code() == ERROR_ALREADY_EXISTS.to_hresult()
+        {
+            let _ = CloseHandle(handle);
+            return Ok(None);
+        }
+
+        Ok(Some(InstanceGuard(handle)))
+    }
+}
+
+/// Answers requests from other processes on [`PIPE_NAME`] and forwards them to the switcher,
+/// or to `shutdown`.
+pub fn spawn_ipc_server(switcher: Sender<Command>, shutdown: Shutdown) {
+    thread::spawn(move || {
+        loop {
+            let pipe = match accept() {
+                Ok(pipe) => pipe,
+                Err(err) => {
+                    error!("Failed to listen on {PIPE_NAME}: {err:?}");
+                    break;
+                }
+            };
+
+            if let Err(err) = handle_client(&pipe, &switcher, &shutdown) {
+                warn!("Failed to answer request: {err:?}");
+            }
+        }
+    });
+}
+
+/// Creates a pipe instance and waits for a client to connect to it.
+fn accept() -> Result<File> {
+    unsafe {
+        let handle = CreateNamedPipeW(
+            &HSTRING::from(PIPE_NAME),
+            PIPE_ACCESS_DUPLEX,
+            PIPE_TYPE_BYTE | PIPE_READMODE_BYTE | PIPE_WAIT | PIPE_REJECT_REMOTE_CLIENTS,
+            PIPE_UNLIMITED_INSTANCES,
+            512,
+            512,
+            0,
+            None,
+        );
+
+        if handle.is_invalid() {
+            return Err(windows::core::Error::from_win32().into());
+        }
+
+        let pipe = File::from_raw_handle(handle.0 as RawHandle);
+
+        match ConnectNamedPipe(handle, None) {
+            Err(err) if err.code() != ERROR_PIPE_CONNECTED.to_hresult() => Err(err.into()),
+            _ => Ok(pipe),
+        }
+    }
+}
+
+fn handle_client(mut pipe: &File, switcher: &Sender<Command>, shutdown: &Shutdown) -> Result<()> {
+    let mut line = String::new();
+    BufReader::new(pipe).read_line(&mut line)?;
+
+    let response = match line.trim().parse::<Request>() {
+        Ok(request) => {
+            info!(%request, "Received request.");
+            match request {
+                Request::ShowStatus => {
+                    let _ = switcher.send(Command::ShowStatus);
+                }
+                Request::Activate(name) => {
+                    let _ = switcher.send(Command::ActivateNamed(name));
+                }
+                Request::Shutdown => shutdown.request("IPC request"),
+            }
+            "ok".to_string()
+        }
+        Err(err) => format!("error {err}"),
+    };
+
+    writeln!(pipe, "{response}")?;
+
+    // Waits until the client has read the response, closing the pipe earlier would discard it.
+    pipe.sync_all()?;
+
+    Ok(())
+}
@@ -0,0 +1,78 @@
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::str::FromStr;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result, anyhow, bail};
+
+/// Named pipe the running service accepts requests on.
+pub const PIPE_NAME: &str = r"\\.\pipe\AudioSwitchService";
+
+/// How long to wait for the service to accept a connection.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// What another process asks the running service to do. Sent as a single line of text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Request {
+    /// Show a notification with the active profile.
+    ShowStatus,
+    /// Activate the profile with this name.
+    Activate(String),
+    /// Unregister the hotkeys, remove the tray icon and exit.
+    Shutdown,
+}
+
+impl std::fmt::Display for Request {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Request::ShowStatus => write!(f, "status"),
+            Request::Activate(name) => write!(f, "activate {name}"),
+            Request::Shutdown => write!(f, "shutdown"),
+        }
+    }
+}
+
+impl FromStr for Request {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (command, argument) = s.split_once(' ').unwrap_or((s, ""));
+
+        match (command, argument) {
+            ("status", "") => Ok(Request::ShowStatus),
+            ("activate", name) if !name.is_empty() => Ok(Request::Activate(name.to_string())),
+            ("shutdown", "") => Ok(Request::Shutdown),
+            _ => Err(anyhow!("Unknown request \"{s}\".")),
+        }
+    }
+}
+
+/// Sends `request` to the running service and waits until it was accepted.
+pub fn send(request: &Request) -> Result<()> {
+    let pipe = connect()?;
+    writeln!(&pipe, "{request}")?;
+
+    let mut response = String::new();
+    BufReader::new(&pipe).read_line(&mut response)?;
+
+    match response.trim() {
+        "ok" => Ok(()),
+        response => bail!("{}", response.strip_prefix("error ").unwrap_or(response)),
+    }
+}
+
+/// Opens the pipe, retrying while the service is busy with another client.
+fn connect() -> Result<File> {
+    let started = Instant::now();
+
+    loop {
+        match OpenOptions::new().read(true).write(true).open(PIPE_NAME) {
+            Ok(pipe) => return Ok(pipe),
+            Err(_) if started.elapsed() < CONNECT_TIMEOUT => {
+                thread::sleep(Duration::from_millis(50));
+            }
+            Err(err) => return Err(err).context("The service is not running or not responding."),
+        }
+    }
+}
@@ -0,0 +1,47 @@
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use tracing::level_filters::LevelFilter;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+
+use crate::config::LogLevel;
+use crate::paths;
+
+/// Number of daily log files kept before the oldest is deleted.
+const MAX_LOG_FILES: usize = 7;
+
+/// Directory the service writes its log files to, next to the installed executables.
+pub fn log_dir() -> Result<PathBuf> {
+    Ok(paths::data_dir()?.join("logs"))
+}
+
+/// Sends all `tracing` events up to `level` to a log file that rotates daily.
+pub fn init(level: LogLevel) -> Result<()> {
+    let appender = RollingFileAppender::builder()
+        .rotation(Rotation::DAILY)
+        .filename_prefix("service")
+        .filename_suffix("log")
+        .max_log_files(MAX_LOG_FILES)
+        .build(log_dir()?)
+        .context("Failed to create the log file.")?;
+
+    tracing_subscriber::fmt()
+        .with_writer(appender)
+        .with_ansi(false)
+        .with_max_level(LevelFilter::from(level))
+        .init();
+
+    Ok(())
+}
+
+impl From<LogLevel> for LevelFilter {
+    fn from(level: LogLevel) -> Self {
+        match level {
+            LogLevel::Error => LevelFilter::ERROR,
+            LogLevel::Warn => LevelFilter::WARN,
+            LogLevel::Info => LevelFilter::INFO,
+            LogLevel::Debug => LevelFilter::DEBUG,
+            LogLevel::Trace => LevelFilter::TRACE,
+        }
+    }
+}
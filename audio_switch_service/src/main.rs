@@ -1,132 +1,416 @@
 #![windows_subsystem = "windows"]
 
+mod color;
 mod config;
 mod dyn_icon;
+mod instance;
+mod ipc;
+mod logging;
+mod monitor;
+mod notify;
+mod paths;
+mod shared;
+mod shutdown;
+mod supervisor;
+mod switcher;
+mod theme;
 mod tray;
 
-use anyhow::Result;
-use com_policy_config::{IPolicyConfig, PolicyConfigClient};
-use std::thread;
-use tauri_winrt_notification::{Duration, Toast};
+use anyhow::{Result, bail};
+use std::backtrace::Backtrace;
+use std::collections::BTreeSet;
+use std::path::Path;
+use std::sync::mpsc::{self, Sender};
+use std::thread::{self, JoinHandle};
+use tracing::{error, info, warn};
 use win_hotkey::keys::{ModifiersKey, VirtualKey};
-use win_hotkey::{HotkeyManager, HotkeyManagerImpl};
-use windows::Win32::Media::Audio::{
-    DEVICE_STATE_ACTIVE, IMMDeviceEnumerator, MMDeviceEnumerator, eCapture, eConsole, eRender,
-};
-use windows::Win32::System::Com::{
-    CLSCTX_ALL, COINIT_MULTITHREADED, CoCreateInstance, CoInitializeEx, CoUninitialize,
-};
-use windows::core::PCWSTR;
-use winit::event_loop::{EventLoop, EventLoopProxy};
+use win_hotkey::{HotkeyManager, HotkeyManagerImpl, InterruptHandle};
+use windows::Win32::System::Com::{COINIT_MULTITHREADED, CoInitializeEx};
+use winit::event_loop::EventLoop;
 
+use config::{Config, DeviceBinding, HotkeyConfig, Profile};
+use shutdown::Shutdown;
+use switcher::Command;
 use tray::UserEvent;
 
 pub fn main() -> Result<()> {
-    let cfg: config::Config = confy::load("AudioSwitch", None).expect("Failed to open config.");
-    let event_loop = EventLoop::<UserEvent>::with_user_event().build().unwrap();
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
 
-    let mut proxies = vec![];
+    // `--config <path>` uses another config file, the supervisor passes it on to the service.
+    if let Some(index) = args.iter().position(|it| it == paths::CONFIG_ARG)
+        && index + 1 < args.len()
+    {
+        let path = args.remove(index + 1);
+        args.remove(index);
+        paths::set_config_path(Path::new(&path))?;
+    }
 
-    for _ in 0..cfg.profiles.len() {
-        proxies.push(event_loop.create_proxy());
+    // `--export-icon <path> [color]` writes the tray icon as a multi-size .ico file and exits.
+    if let [flag, path, rest @ ..] = args.as_slice()
+        && flag == "--export-icon"
+    {
+        return match rest.first() {
+            Some(color) => dyn_icon::generate_icon_set(color.parse()?)?.write_ico(Path::new(path)),
+            None => dyn_icon::base_icon_set()?.write_ico(Path::new(path)),
+        };
     }
 
-    setup_hotkey_handler(proxies);
-    tray::create_tray(event_loop);
+    // `--supervise` runs the service as a child process and restarts it when it crashes.
+    if args.first().is_some_and(|it| it == "--supervise") {
+        return run_supervisor();
+    }
 
-    Ok(())
-}
+    // `--safe-mode` is passed by the supervisor after repeated crashes.
+    let safe_mode = args.iter().any(|it| it == "--safe-mode");
 
-fn setup_hotkey_handler(proxies: Vec<EventLoopProxy<UserEvent>>) {
-    thread::spawn(|| {
-        unsafe {
-            CoInitializeEx(None, COINIT_MULTITHREADED).expect("Failed to initialize Thread.");
+    // `--activate <profile>` activates a profile and `--shutdown` stops the running service,
+    // otherwise a second launch shows the status.
+    let request = match args.as_slice() {
+        [flag, name] if flag == "--activate" => ipc::Request::Activate(name.clone()),
+        [flag] if flag == "--shutdown" => ipc::Request::Shutdown,
+        _ => ipc::Request::ShowStatus,
+    };
+
+    let instance = instance::acquire(instance::SERVICE_MUTEX);
+    if request == ipc::Request::Shutdown && !matches!(instance, Ok(None)) {
+        return Ok(());
+    }
+    if let Ok(None) = instance {
+        if let Err(err) = ipc::send(&request) {
+            notify::show_message(
+                "AudioSwitch is already running",
+                &format!("It did not respond: {err:#}"),
+            );
         }
+        return Ok(());
+    }
 
-        let config: config::Config =
-            confy::load("AudioSwitch", None).expect("Failed to open config.");
-        let mut hkm = HotkeyManager::new();
+    let (mut cfg, config_error) = match paths::load_config::<Config>() {
+        Ok(cfg) => (cfg, None),
+        Err(err) => (Config::default(), Some(err)),
+    };
 
-        for (p, proxy) in config.profiles.into_iter().zip(proxies) {
-            let vk = VirtualKey::from_keyname(
-                &p.hotkey
-                    .hotkey
-                    .strip_prefix("VK_")
-                    .unwrap_or(&p.hotkey.hotkey),
-            )
-            .expect("Invalid Key.");
-            let vk_mod = p
-                .hotkey
-                .modifier
-                .map(|i| vec![ModifiersKey::from_keyname(&i).unwrap()]);
-
-            hkm.register(
-                vk,
-                vk_mod.as_ref().map(|v| v.as_slice()),
-                Some(move || {
-                    match set_profile(p.input_id.clone(), p.output_id.clone()) {
-                        Ok(_) => {
-                            send_toast(format!("Activated Profile {}", p.profile_name.clone()))
-                                .expect("Failed to send notification.");
-                            
-                            if let Some(color) = p.color.clone() {
-                                let _ = proxy.clone().send_event(UserEvent::ColorChange(color));
-                            }
-                        }
-
-                        Err(_) => {
-                            send_toast(format!(
-                                "Failed to activate Profile {}",
-                                p.profile_name.clone()
-                            ))
-                            .expect("Failed to send notification.");
-                        }
-                    }
-                }),
-            )
-            .expect("Failed to register hotkey.");
+    // Without a log file the service still works, there is just nowhere to report to.
+    let _ = logging::init(cfg.log_level);
+    install_panic_hook();
+    info!(
+        version = env!("CARGO_PKG_VERSION"),
+        profiles = cfg.profiles.len(),
+        portable = paths::is_portable(),
+        "Starting AudioSwitch service."
+    );
+
+    if let Err(err) = notify::register_app_id() {
+        warn!("Failed to register AppUserModelID: {err:?}");
+    }
+
+    if let Err(err) = &instance {
+        warn!("Failed to check for a running service: {err:?}");
+    }
+
+    if let Some(err) = config_error {
+        error!("Failed to open config, starting without profiles: {err}");
+        notify::show_message(
+            "AudioSwitch could not read its config",
+            &format!("{err}\nOpen the setup to fix it."),
+        );
+    }
+
+    match overlay_synced(&mut cfg) {
+        Ok(None) => {}
+        Ok(Some(reason)) => {
+            warn!("Not using the synced profiles: {reason}");
+            notify::show_message("AudioSwitch profiles need merging", &reason);
         }
+        Err(err) => warn!("Failed to read the synced profiles: {err:#}"),
+    }
 
-        hkm.event_loop();
+    skip_invalid_settings(&mut cfg);
+
+    let event_loop = EventLoop::<UserEvent>::with_user_event()
+        .build()
+        .inspect_err(|err| error!("Failed to create the event loop, running headless: {err:?}"))
+        .ok();
+    let proxy = event_loop.as_ref().map(|it| it.create_proxy());
+
+    if let Some(proxy) = &proxy {
+        monitor::spawn_status_monitor(proxy.clone(), monitored_devices(&cfg), cfg.taskbar_theme);
+    }
+
+    let theme = theme::Theme::resolve(cfg.taskbar_theme);
+    let bindings = if safe_mode {
+        warn!("Running in safe mode, hotkeys are disabled.");
+        notify::show_message(
+            "AudioSwitch is in safe mode",
+            &format!(
+                "The service crashed repeatedly, so hotkeys are disabled. The log in {} has the \
+                 details, open the setup to view it.",
+                logging::log_dir().map_or_else(
+                    |_| "the data directory".to_string(),
+                    |it| it.display().to_string()
+                )
+            ),
+        );
+        vec![]
+    } else {
+        hotkey_bindings(&cfg)
+    };
+
+    let (headless_sender, headless_receiver) = mpsc::channel();
+    let shutdown = match &proxy {
+        Some(proxy) => Shutdown::Tray(proxy.clone()),
+        None => Shutdown::Headless(headless_sender),
+    };
+
+    let switcher = switcher::spawn_switcher(cfg, proxy);
+    let (hotkeys, hotkeys_interrupt) = setup_hotkey_handler(switcher.clone(), bindings);
+    instance::spawn_ipc_server(switcher.clone(), shutdown.clone());
+
+    // Ctrl-C, closing the console, logging off and shutting Windows down.
+    let ctrlc_shutdown = shutdown.clone();
+    if let Err(err) = ctrlc::set_handler(move || ctrlc_shutdown.request("console signal")) {
+        warn!("Failed to install the console signal handler: {err:?}");
+    }
+
+    if let ipc::Request::Activate(name) = request {
+        let _ = switcher.send(Command::ActivateNamed(name));
+    }
+
+    match event_loop {
+        Some(event_loop) => tray::create_tray(event_loop, theme, switcher, safe_mode),
+        None => {
+            let _ = headless_receiver.recv();
+        }
+    }
+
+    if let Some(interrupt) = hotkeys_interrupt {
+        interrupt.interrupt();
+        let _ = hotkeys.join();
+    }
+
+    info!("AudioSwitch service stopped.");
+
+    Ok(())
+}
+
+/// Runs the service as a child process until it exits on its own. Restarts it with backoff when
+/// it crashes, and in safe mode when it keeps crashing.
+fn run_supervisor() -> Result<()> {
+    let Some(_guard) = instance::acquire(instance::SUPERVISOR_MUTEX)? else {
+        return Ok(());
+    };
+
+    let log_level = paths::load_config::<Config>()
+        .map(|it| it.log_level)
+        .unwrap_or_default();
+    let _ = logging::init(log_level);
+    install_panic_hook();
+    info!(
+        version = env!("CARGO_PKG_VERSION"),
+        "Starting AudioSwitch supervisor."
+    );
+
+    supervisor::supervise(
+        &mut supervisor::ProcessLauncher::new()?,
+        supervisor::Policy::default(),
+    );
+
+    Ok(())
+}
+
+/// Logs panics with a backtrace, tells the user and ends the process, so a panicking
+/// background thread doesn't leave the service or its supervisor half working.
+fn install_panic_hook() {
+    std::panic::set_hook(Box::new(|info| {
+        error!(backtrace = %Backtrace::force_capture(), "{info}");
 
         unsafe {
-            CoUninitialize();
+            let _ = CoInitializeEx(None, COINIT_MULTITHREADED);
         }
-    });
+        notify::show_message(
+            "AudioSwitch stopped unexpectedly",
+            "The service log has the details, open the setup to view it.",
+        );
+
+        std::process::exit(101);
+    }));
 }
 
-fn set_profile(input_id: String, output_id: String) -> Result<()> {
-    unsafe {
-        let policy_config: IPolicyConfig = CoCreateInstance(&PolicyConfigClient, None, CLSCTX_ALL)?;
+/// Replaces the profiles of `config` with the ones in the sync folder if another machine changed
+/// them and this machine has no changes of its own. Returns why the setup must merge them
+/// otherwise.
+fn overlay_synced(config: &mut Config) -> Result<Option<String>> {
+    let Some(dir) = config.sync_dir.clone() else {
+        return Ok(None);
+    };
+    let Some(remote) = shared::read(&dir.join(shared::SHARED_FILE))? else {
+        return Ok(None);
+    };
+
+    if !shared::conflict_copies(&dir)?.is_empty() {
+        return Ok(Some(
+            "Two machines changed the profiles at once. Open the setup to merge the changes."
+                .to_string(),
+        ));
+    }
+
+    let base = shared::read(&shared::base_path()?)?.unwrap_or_default();
+    if remote.revision == base.revision {
+        return Ok(None);
+    }
+
+    if shared::Portable::of(config) != base.portable {
+        return Ok(Some(format!(
+            "The profiles were changed here and on {}. Open the setup to merge the changes.",
+            remote.written_by
+        )));
+    }
+
+    remote.portable.apply_to(config);
 
-        let enumerator: IMMDeviceEnumerator =
-            CoCreateInstance(&MMDeviceEnumerator, None, CLSCTX_ALL)?;
-        let _capture_collection = enumerator.EnumAudioEndpoints(eCapture, DEVICE_STATE_ACTIVE)?;
+    Ok(None)
+}
 
-        let mut input_id_u16 = input_id.encode_utf16().collect::<Vec<u16>>();
-        let input_id_raw_ptr = input_id_u16.as_mut_ptr();
-        let input_id_pcwstr = PCWSTR(input_id_raw_ptr);
+/// Removes profiles and hotkeys the service can't use from `cfg`, and reports them.
+fn skip_invalid_settings(cfg: &mut Config) {
+    let mut skipped = vec![];
 
-        policy_config.SetDefaultEndpoint(input_id_pcwstr, eConsole)?;
+    let mut profiles = std::mem::take(&mut cfg.profiles);
+    profiles.retain(
+        |p| match validate_profile(p, cfg.binding(&p.profile_name)) {
+            Ok(()) => true,
+            Err(err) => {
+                warn!(profile = %p.profile_name, "Skipping invalid profile: {err:#}");
+                skipped.push(format!("Profile {}: {err:#}", p.profile_name));
+                false
+            }
+        },
+    );
+    cfg.profiles = profiles;
 
-        let _render_collection = enumerator.EnumAudioEndpoints(eRender, DEVICE_STATE_ACTIVE)?;
+    for (name, hotkey) in [
+        ("Next profile", &mut cfg.next_profile),
+        ("Previous profile", &mut cfg.previous_profile),
+    ] {
+        if let Some(h) = hotkey
+            && let Err(err) = parse_hotkey(h)
+        {
+            warn!(hotkey = %h, "Skipping invalid {name} hotkey: {err:#}");
+            skipped.push(format!("{name} hotkey: {err:#}"));
+            *hotkey = None;
+        }
+    }
 
-        let mut output_id_u16 = output_id.encode_utf16().collect::<Vec<u16>>();
-        let output_id_raw_ptr = output_id_u16.as_mut_ptr();
-        let output_id_pcwstr = PCWSTR(output_id_raw_ptr);
+    if !skipped.is_empty() {
+        notify::show_message("AudioSwitch skipped invalid settings", &skipped.join("\n"));
+    }
+}
 
-        policy_config.SetDefaultEndpoint(output_id_pcwstr, eConsole)?;
+fn validate_profile(profile: &Profile, devices: Option<&DeviceBinding>) -> Result<()> {
+    let Some(devices) = devices else {
+        bail!("No devices on this machine yet, open the setup to choose them.");
+    };
+    if devices.input_id.is_empty() || devices.output_id.is_empty() {
+        bail!("No input or output device.");
     }
 
+    parse_hotkey(&profile.hotkey)?;
+
     Ok(())
 }
 
-fn send_toast(msg: String) -> Result<()> {
-    Toast::new(Toast::POWERSHELL_APP_ID)
-        .title(&msg)
-        .duration(Duration::Short)
-        .show()
-        .expect("Toast failed.");
+/// Endpoint IDs of the profiles left by [`skip_invalid_settings`], each once. A skipped profile
+/// can't be activated, so its devices don't matter.
+fn monitored_devices(cfg: &Config) -> Vec<String> {
+    let ids: BTreeSet<&str> = cfg
+        .profiles
+        .iter()
+        .filter_map(|p| cfg.binding(&p.profile_name))
+        .flat_map(|d| [d.input_id.as_str(), d.output_id.as_str()])
+        .collect();
 
-    Ok(())
+    ids.into_iter().map(str::to_string).collect()
+}
+
+/// Pairs every configured hotkey with the command it triggers.
+fn hotkey_bindings(cfg: &Config) -> Vec<(HotkeyConfig, Command)> {
+    cfg.profiles
+        .iter()
+        .enumerate()
+        .map(|(i, p)| (Some(&p.hotkey), Command::Activate(i)))
+        .chain([
+            (cfg.next_profile.as_ref(), Command::Next),
+            (cfg.previous_profile.as_ref(), Command::Previous),
+        ])
+        .filter_map(|(hotkey, command)| Some((hotkey?.clone(), command)))
+        .collect()
+}
+
+/// Registers the hotkeys on a new thread. Interrupting the returned handle unregisters them
+/// and ends the thread.
+fn setup_hotkey_handler(
+    switcher: Sender<Command>,
+    bindings: Vec<(HotkeyConfig, Command)>,
+) -> (JoinHandle<()>, Option<InterruptHandle>) {
+    let (interrupt_sender, interrupt_receiver) = mpsc::channel();
+
+    let thread = thread::spawn(move || {
+        let mut hkm = HotkeyManager::new();
+        let mut failed = vec![];
+        let _ = interrupt_sender.send(hkm.interrupt_handle());
+
+        for (hotkey, command) in bindings {
+            let switcher = switcher.clone();
+            let hotkey_command = command.clone();
+
+            let result = parse_hotkey(&hotkey).and_then(|(vk, vk_mod)| {
+                hkm.register(
+                    vk,
+                    vk_mod.as_deref(),
+                    Some(move || {
+                        let _ = switcher.send(hotkey_command.clone());
+                    }),
+                )
+                .map_err(anyhow::Error::from)
+            });
+
+            match result {
+                Ok(_) => info!(%hotkey, ?command, "Registered hotkey."),
+                Err(err) => {
+                    warn!(%hotkey, ?command, "Failed to register hotkey: {err:#}");
+                    failed.push(hotkey.to_string());
+                }
+            }
+        }
+
+        if !failed.is_empty() {
+            notify::show_message(
+                "AudioSwitch could not register hotkeys",
+                &format!(
+                    "{} may already be used by another program.",
+                    failed.join(", ")
+                ),
+            );
+        }
+
+        hkm.event_loop();
+
+        if let Err(err) = hkm.unregister_all() {
+            warn!("Failed to unregister hotkeys: {err:?}");
+        }
+        info!("Unregistered hotkeys.");
+    });
+
+    (thread, interrupt_receiver.recv().ok())
+}
+
+fn parse_hotkey(hotkey: &HotkeyConfig) -> Result<(VirtualKey, Option<Vec<ModifiersKey>>)> {
+    let vk = VirtualKey::from_keyname(hotkey.hotkey.strip_prefix("VK_").unwrap_or(&hotkey.hotkey))?;
+    let vk_mod = hotkey
+        .modifier
+        .as_ref()
+        .map(|i| ModifiersKey::from_keyname(i).map(|it| vec![it]))
+        .transpose()?;
+
+    Ok((vk, vk_mod))
 }
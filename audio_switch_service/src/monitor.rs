@@ -0,0 +1,113 @@
+use std::thread;
+use std::time::Duration;
+
+use anyhow::Result;
+use tracing::{error, info, warn};
+use windows::Win32::Media::Audio::Endpoints::IAudioEndpointVolume;
+use windows::Win32::Media::Audio::{
+    DEVICE_STATE_ACTIVE, IMMDeviceEnumerator, MMDeviceEnumerator, eCapture, eConsole,
+};
+use windows::Win32::System::Com::{
+    CLSCTX_ALL, COINIT_MULTITHREADED, CoCreateInstance, CoInitializeEx, CoUninitialize,
+};
+use windows::core::HSTRING;
+use winit::event_loop::EventLoopProxy;
+
+use crate::config::TaskbarTheme;
+use crate::dyn_icon::Badge;
+use crate::theme::Theme;
+use crate::tray::UserEvent;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Watches the microphone mute state, the devices used by the profiles and the taskbar theme,
+/// and sends a `UserEvent` whenever one of them changes.
+pub fn spawn_status_monitor(
+    proxy: EventLoopProxy<UserEvent>,
+    device_ids: Vec<String>,
+    taskbar_theme: TaskbarTheme,
+) {
+    thread::spawn(move || {
+        unsafe {
+            if let Err(err) = CoInitializeEx(None, COINIT_MULTITHREADED) {
+                error!("Failed to initialize COM: {err:?}");
+            }
+        }
+
+        let mut mic_muted = None;
+        let mut device_missing = None;
+        let mut theme = Some(Theme::resolve(taskbar_theme));
+
+        loop {
+            let muted = is_default_mic_muted().unwrap_or(false);
+            let missing = device_ids
+                .iter()
+                .any(|id| !is_device_active(id).unwrap_or(false));
+
+            if mic_muted != Some(muted) {
+                mic_muted = Some(muted);
+                info!(muted, "Default microphone mute state changed.");
+                if proxy
+                    .send_event(UserEvent::BadgeChange(Badge::MicMuted, muted))
+                    .is_err()
+                {
+                    break;
+                }
+            }
+
+            if device_missing != Some(missing) {
+                device_missing = Some(missing);
+                if missing {
+                    warn!("A profile device is unplugged or disabled.");
+                } else {
+                    info!("All profile devices are available.");
+                }
+                if proxy
+                    .send_event(UserEvent::BadgeChange(Badge::DeviceMissing, missing))
+                    .is_err()
+                {
+                    break;
+                }
+            }
+
+            let current_theme = Theme::resolve(taskbar_theme);
+            if theme != Some(current_theme) {
+                theme = Some(current_theme);
+                info!(theme = ?current_theme, "Taskbar theme changed.");
+                if proxy
+                    .send_event(UserEvent::ThemeChange(current_theme))
+                    .is_err()
+                {
+                    break;
+                }
+            }
+
+            thread::sleep(POLL_INTERVAL);
+        }
+
+        unsafe {
+            CoUninitialize();
+        }
+    });
+}
+
+fn is_default_mic_muted() -> Result<bool> {
+    unsafe {
+        let enumerator: IMMDeviceEnumerator =
+            CoCreateInstance(&MMDeviceEnumerator, None, CLSCTX_ALL)?;
+        let device = enumerator.GetDefaultAudioEndpoint(eCapture, eConsole)?;
+        let volume: IAudioEndpointVolume = device.Activate(CLSCTX_ALL, None)?;
+
+        Ok(volume.GetMute()?.as_bool())
+    }
+}
+
+fn is_device_active(device_id: &str) -> Result<bool> {
+    unsafe {
+        let enumerator: IMMDeviceEnumerator =
+            CoCreateInstance(&MMDeviceEnumerator, None, CLSCTX_ALL)?;
+        let device = enumerator.GetDevice(&HSTRING::from(device_id))?;
+
+        Ok(device.GetState()? == DEVICE_STATE_ACTIVE)
+    }
+}
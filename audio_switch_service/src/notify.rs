@@ -0,0 +1,229 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use tracing::warn;
+
+use crate::config::{DeviceBinding, NotificationConfig, NotificationDuration, Profile};
+use crate::switcher::ActivationError;
+
+/// AppUserModelID the service registers, so notifications are attributed to AudioSwitch.
+pub const APP_ID: &str = "AudioSwitch";
+
+/// A message shown to the user after a profile switch or about a problem with the service.
+#[derive(Debug, Clone)]
+pub struct Notification {
+    pub title: String,
+    pub body: String,
+    pub duration: NotificationDuration,
+    /// Reason and suggested fix of a failed switch, empty otherwise.
+    pub detail: String,
+    pub actions: Vec<NotificationAction>,
+}
+
+impl Notification {
+    /// The non-empty lines below the title.
+    fn lines(&self) -> impl Iterator<Item = &str> {
+        [self.body.as_str(), self.detail.as_str()]
+            .into_iter()
+            .filter(|it| !it.is_empty())
+    }
+}
+
+/// A button on a notification.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NotificationAction {
+    /// Switch back to the profile that was active before.
+    Undo,
+    /// Switch to the profile after the current one.
+    NextProfile,
+}
+
+impl NotificationAction {
+    const ALL: [NotificationAction; 2] =
+        [NotificationAction::Undo, NotificationAction::NextProfile];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            NotificationAction::Undo => "Undo",
+            NotificationAction::NextProfile => "Next profile",
+        }
+    }
+
+    /// Identifier the notification backend reports back when the button is clicked.
+    pub fn id(self) -> &'static str {
+        match self {
+            NotificationAction::Undo => "undo",
+            NotificationAction::NextProfile => "next",
+        }
+    }
+
+    pub fn from_id(id: &str) -> Option<NotificationAction> {
+        Self::ALL.into_iter().find(|it| it.id() == id)
+    }
+}
+
+/// Called with the action the user clicked on a notification.
+pub type ActionHandler = Arc<dyn Fn(NotificationAction) + Send + Sync>;
+
+/// A backend that can display notifications.
+pub trait Notifier: Send + Sync {
+    fn notify(&self, notification: &Notification) -> Result<()>;
+}
+
+/// Builds a [`ToastNotifier`], or a [`NoopNotifier`] if notifications are disabled.
+/// Clicked notification buttons are passed to `on_action`.
+pub fn from_config(config: &NotificationConfig, on_action: ActionHandler) -> Box<dyn Notifier> {
+    if !config.on_success && !config.on_failure {
+        return Box::new(NoopNotifier);
+    }
+
+    Box::new(ToastNotifier { on_action })
+}
+
+/// Shows a message from the service itself, regardless of the notification settings.
+pub fn show_message(title: &str, body: &str) {
+    let notification = Notification {
+        title: title.to_string(),
+        body: body.to_string(),
+        duration: NotificationDuration::Long,
+        detail: String::new(),
+        actions: Vec::new(),
+    };
+
+    let notifier = ToastNotifier {
+        on_action: Arc::new(|_| {}),
+    };
+    if let Err(err) = notifier.notify(&notification) {
+        warn!("Failed to show notification: {err:?}");
+    }
+}
+
+/// Creates the notification for a profile switch, or `None` if the config disables it.
+/// `actions` are only offered on success, a failure shows the reason and how to fix it instead.
+pub fn activation_notification(
+    config: &NotificationConfig,
+    profile: &Profile,
+    devices: &DeviceBinding,
+    error: Option<&ActivationError>,
+    actions: Vec<NotificationAction>,
+) -> Option<Notification> {
+    let success = error.is_none();
+    let (enabled, title, body) = if success {
+        (
+            config.on_success,
+            &config.success_title,
+            &config.success_body,
+        )
+    } else {
+        (
+            config.on_failure,
+            &config.failure_title,
+            &config.failure_body,
+        )
+    };
+
+    enabled.then(|| Notification {
+        title: render(title, profile, devices),
+        body: render(body, profile, devices),
+        duration: config.duration,
+        detail: error
+            .map(|err| format!("{err} {}", err.suggestion()))
+            .unwrap_or_default(),
+        actions: if success { actions } else { Vec::new() },
+    })
+}
+
+/// Replaces the `{profile}`, `{input}` and `{output}` placeholders in `template`.
+pub fn render(template: &str, profile: &Profile, devices: &DeviceBinding) -> String {
+    template
+        .replace("{profile}", &profile.profile_name)
+        .replace("{input}", &devices.input_name)
+        .replace("{output}", &devices.output_name)
+}
+
+/// Shows nothing.
+pub struct NoopNotifier;
+
+impl Notifier for NoopNotifier {
+    fn notify(&self, _notification: &Notification) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Windows toast notifications.
+pub struct ToastNotifier {
+    on_action: ActionHandler,
+}
+
+impl Notifier for ToastNotifier {
+    fn notify(&self, notification: &Notification) -> Result<()> {
+        use tauri_winrt_notification::{Duration, Toast};
+
+        let duration = match notification.duration {
+            NotificationDuration::Short => Duration::Short,
+            NotificationDuration::Long => Duration::Long,
+        };
+
+        let on_action = self.on_action.clone();
+        let mut toast = Toast::new(APP_ID)
+            .title(&notification.title)
+            .duration(duration)
+            .on_activated(move |action| {
+                if let Some(action) = action.as_deref().and_then(NotificationAction::from_id) {
+                    on_action(action);
+                }
+                Ok(())
+            });
+
+        let mut lines = notification.lines();
+        if let Some(line) = lines.next() {
+            toast = toast.text1(line);
+        }
+        if let Some(line) = lines.next() {
+            toast = toast.text2(line);
+        }
+
+        for action in &notification.actions {
+            toast = toast.add_button(action.label(), action.id());
+        }
+
+        toast.show()?;
+
+        Ok(())
+    }
+}
+
+/// Registers [`APP_ID`] for the current user with the AudioSwitch name and icon and makes it the
+/// AppUserModelID of this process. Toasts of an unregistered ID are not shown.
+pub fn register_app_id() -> Result<()> {
+    use windows::Win32::System::Registry::{HKEY_CURRENT_USER, REG_SZ, RegSetKeyValueW};
+    use windows::Win32::UI::Shell::SetCurrentProcessExplicitAppUserModelID;
+    use windows::core::HSTRING;
+
+    let icon_path = std::env::current_exe()?.with_file_name("AudioSwitch.ico");
+    crate::dyn_icon::base_icon_set()?.write_ico(&icon_path)?;
+
+    let key = HSTRING::from(format!("Software\\Classes\\AppUserModelId\\{APP_ID}"));
+    let values = [
+        ("DisplayName", "AudioSwitch".to_owned()),
+        ("IconUri", icon_path.display().to_string()),
+    ];
+
+    unsafe {
+        for (name, value) in values {
+            let data = value.encode_utf16().chain([0]).collect::<Vec<u16>>();
+            RegSetKeyValueW(
+                HKEY_CURRENT_USER,
+                &key,
+                &HSTRING::from(name),
+                REG_SZ.0,
+                Some(data.as_ptr().cast()),
+                (data.len() * size_of::<u16>()) as u32,
+            )?;
+        }
+
+        SetCurrentProcessExplicitAppUserModelID(&HSTRING::from(APP_ID))?;
+    }
+
+    Ok(())
+}
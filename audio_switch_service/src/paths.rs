@@ -0,0 +1,82 @@
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+/// Argument that sets the config file for the service and the setup.
+pub const CONFIG_ARG: &str = "--config";
+
+/// Environment variable that sets the config file when there is no `--config`.
+pub const CONFIG_ENV: &str = "AUDIOSWITCH_CONFIG";
+
+/// File next to the executables that keeps the config, logs and state beside them, e.g. on a
+/// USB stick.
+pub const PORTABLE_MARKER: &str = "AudioSwitch.portable";
+
+/// Name of the config file in portable mode.
+const PORTABLE_CONFIG: &str = "config.toml";
+
+static CONFIG_PATH: OnceLock<PathBuf> = OnceLock::new();
+
+/// Uses the config file at `path` for the rest of the process, as given by `--config`.
+pub fn set_config_path(path: &Path) -> Result<()> {
+    let path = std::path::absolute(path)
+        .with_context(|| format!("Failed to resolve {}.", path.display()))?;
+    let _ = CONFIG_PATH.set(path);
+
+    Ok(())
+}
+
+/// The config file given by `--config` or `AUDIOSWITCH_CONFIG`, `None` if none was given.
+pub fn config_override() -> Option<PathBuf> {
+    CONFIG_PATH.get().cloned().or_else(|| {
+        std::env::var_os(CONFIG_ENV)
+            .filter(|it| !it.is_empty())
+            .and_then(|it| std::path::absolute(PathBuf::from(it)).ok())
+    })
+}
+
+/// Directory of the running executable if it has the portable marker next to it.
+fn portable_dir() -> Option<PathBuf> {
+    let dir = std::env::current_exe().ok()?.parent()?.to_path_buf();
+    dir.join(PORTABLE_MARKER).exists().then_some(dir)
+}
+
+/// Whether AudioSwitch keeps everything beside its executables.
+pub fn is_portable() -> bool {
+    portable_dir().is_some()
+}
+
+/// The config file: the one given by `--config` or `AUDIOSWITCH_CONFIG`, the one beside the
+/// executables in portable mode, or the user's config directory.
+pub fn config_path() -> Result<PathBuf> {
+    if let Some(path) = config_override() {
+        return Ok(path);
+    }
+
+    if let Some(dir) = portable_dir() {
+        return Ok(dir.join(PORTABLE_CONFIG));
+    }
+
+    Ok(confy::get_configuration_file_path("AudioSwitch", None)?)
+}
+
+/// Directory of the executables, logs and state: beside the running executable in portable
+/// mode, the user's local data directory otherwise.
+pub fn data_dir() -> Result<PathBuf> {
+    if let Some(dir) = portable_dir() {
+        return Ok(dir);
+    }
+
+    Ok(directories::BaseDirs::new()
+        .context("Failed to find the user's data directory.")?
+        .data_local_dir()
+        .join("AudioSwitch"))
+}
+
+/// Reads the config file, creating it with the defaults if there is none.
+pub fn load_config<T: Serialize + DeserializeOwned + Default>() -> Result<T> {
+    Ok(confy::load_path(config_path()?)?)
+}
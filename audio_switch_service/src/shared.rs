@@ -0,0 +1,109 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::config::{Config, DeviceBinding, HotkeyConfig, Profile};
+use crate::paths;
+
+/// Name of the profile file in the sync folder.
+pub const SHARED_FILE: &str = "AudioSwitch.toml";
+
+/// The part of the config that is shared between machines: the profiles, the next/previous
+/// hotkeys and the device bindings of every machine.
+#[derive(Default, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Portable {
+    #[serde(default)]
+    pub profiles: Vec<Profile>,
+    pub next_profile: Option<HotkeyConfig>,
+    pub previous_profile: Option<HotkeyConfig>,
+    #[serde(default)]
+    pub bindings: BTreeMap<String, BTreeMap<String, DeviceBinding>>,
+}
+
+impl Portable {
+    pub fn of(config: &Config) -> Self {
+        Self {
+            profiles: config.profiles.clone(),
+            next_profile: config.next_profile.clone(),
+            previous_profile: config.previous_profile.clone(),
+            bindings: config.bindings.clone(),
+        }
+    }
+
+    pub fn apply_to(self, config: &mut Config) {
+        config.profiles = self.profiles;
+        config.next_profile = self.next_profile;
+        config.previous_profile = self.previous_profile;
+        config.bindings = self.bindings;
+    }
+}
+
+/// The profile file in the sync folder.
+#[derive(Default, Clone, Debug, Serialize, Deserialize)]
+pub struct Shared {
+    /// Raised by every write. A machine that last synced another revision knows the file was
+    /// changed elsewhere.
+    pub revision: u64,
+    /// The revision the writer had synced before, the common ancestor of conflicting copies a
+    /// sync tool keeps when two machines wrote at once.
+    #[serde(default)]
+    pub parent: u64,
+    #[serde(default)]
+    pub written_by: String,
+    #[serde(default)]
+    pub config_version: u32,
+    #[serde(flatten)]
+    pub portable: Portable,
+    #[serde(default)]
+    pub changed: Changed,
+}
+
+/// The revision each entry last changed at. Removed entries stay listed, so a copy that still
+/// has them can't bring them back.
+#[derive(Default, Clone, Debug, Serialize, Deserialize)]
+pub struct Changed {
+    #[serde(default)]
+    pub profiles: BTreeMap<String, u64>,
+    #[serde(default)]
+    pub next_profile: u64,
+    #[serde(default)]
+    pub previous_profile: u64,
+    #[serde(default)]
+    pub bindings: BTreeMap<String, BTreeMap<String, u64>>,
+}
+
+/// The sync folder's file as this machine last synced it, kept next to the config.
+pub fn base_path() -> Result<PathBuf> {
+    Ok(paths::config_path()?.with_file_name("sync-base.toml"))
+}
+
+/// Reads a profile file, `None` if there is none yet.
+pub fn read(path: &Path) -> Result<Option<Shared>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let content = std::fs::read_to_string(path)?;
+    let shared =
+        toml::from_str(&content).with_context(|| format!("Failed to read {}.", path.display()))?;
+
+    Ok(Some(shared))
+}
+
+/// Copies of the profile file that sync tools keep when two machines changed it at once, like
+/// `AudioSwitch.sync-conflict-….toml` or `AudioSwitch-DESKTOP.toml`.
+pub fn conflict_copies(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut copies = vec![];
+
+    for entry in std::fs::read_dir(dir)? {
+        let name = entry?.file_name().to_string_lossy().into_owned();
+        if name != SHARED_FILE && name.starts_with("AudioSwitch") && name.ends_with(".toml") {
+            copies.push(dir.join(name));
+        }
+    }
+
+    copies.sort();
+    Ok(copies)
+}
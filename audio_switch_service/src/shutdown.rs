@@ -0,0 +1,29 @@
+use std::sync::mpsc::Sender;
+
+use tracing::info;
+use winit::event_loop::EventLoopProxy;
+
+use crate::tray::UserEvent;
+
+/// Asks the service to stop. With a tray the event loop removes the icon and exits, without one
+/// `main` waits on the receiving end of the channel.
+#[derive(Clone)]
+pub enum Shutdown {
+    Tray(EventLoopProxy<UserEvent>),
+    Headless(Sender<()>),
+}
+
+impl Shutdown {
+    pub fn request(&self, reason: &str) {
+        info!(reason, "Shutdown requested.");
+
+        match self {
+            Shutdown::Tray(proxy) => {
+                let _ = proxy.send_event(UserEvent::Shutdown);
+            }
+            Shutdown::Headless(sender) => {
+                let _ = sender.send(());
+            }
+        }
+    }
+}
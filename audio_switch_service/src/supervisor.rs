@@ -0,0 +1,319 @@
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::process::Command;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use tracing::{error, info, warn};
+
+use crate::paths;
+
+/// When to restart a crashed worker and when to give up and fall back to safe mode.
+#[derive(Debug, Clone, Copy)]
+pub struct Policy {
+    /// This many crashes within `crash_window` start the worker in safe mode.
+    pub max_crashes: usize,
+    pub crash_window: Duration,
+    /// Delay before the first restart, doubled after every further crash.
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    /// A worker that ran at least this long resets the backoff.
+    pub stable_after: Duration,
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        Self {
+            max_crashes: 5,
+            crash_window: Duration::from_secs(10 * 60),
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(60),
+            stable_after: Duration::from_secs(60),
+        }
+    }
+}
+
+/// How a worker run ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Exit {
+    /// The worker exited on its own, e.g. from Quit or a shutdown request.
+    Clean,
+    /// The worker crashed, was killed or couldn't be started.
+    Crashed,
+}
+
+/// What the supervisor does after a worker exited.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decision {
+    Restart { after: Duration },
+    SafeMode,
+    Stop,
+}
+
+/// Decides how to react to worker exits.
+#[derive(Debug)]
+pub struct Supervisor {
+    policy: Policy,
+    crashes: VecDeque<Instant>,
+    backoff: Duration,
+    safe_mode: bool,
+}
+
+impl Supervisor {
+    pub fn new(policy: Policy) -> Self {
+        Self {
+            policy,
+            crashes: VecDeque::new(),
+            backoff: policy.initial_backoff,
+            safe_mode: false,
+        }
+    }
+
+    /// Whether the next worker should start in safe mode.
+    pub fn safe_mode(&self) -> bool {
+        self.safe_mode
+    }
+
+    /// Records that a worker which ran for `uptime` ended with `exit` at `now`.
+    pub fn on_exit(&mut self, exit: Exit, uptime: Duration, now: Instant) -> Decision {
+        // Nothing helps a worker that even crashes in safe mode, and a clean exit is intended.
+        if exit == Exit::Clean || self.safe_mode {
+            return Decision::Stop;
+        }
+
+        if uptime >= self.policy.stable_after {
+            self.backoff = self.policy.initial_backoff;
+        }
+
+        self.crashes.push_back(now);
+        while let Some(&oldest) = self.crashes.front()
+            && now.duration_since(oldest) > self.policy.crash_window
+        {
+            self.crashes.pop_front();
+        }
+
+        if self.crashes.len() >= self.policy.max_crashes {
+            self.safe_mode = true;
+            return Decision::SafeMode;
+        }
+
+        let after = self.backoff;
+        self.backoff = (self.backoff * 2).min(self.policy.max_backoff);
+
+        Decision::Restart { after }
+    }
+}
+
+/// Starts worker processes and keeps time. Abstracted so the supervisor can run against fakes.
+pub trait Launcher {
+    /// Starts a worker and waits for it to exit.
+    fn run_worker(&mut self, safe_mode: bool) -> Exit;
+    fn sleep(&mut self, duration: Duration);
+    fn now(&self) -> Instant;
+}
+
+/// Runs workers until one exits cleanly or the safe mode worker exits.
+pub fn supervise(launcher: &mut impl Launcher, policy: Policy) {
+    let mut supervisor = Supervisor::new(policy);
+
+    loop {
+        let started = launcher.now();
+        let exit = launcher.run_worker(supervisor.safe_mode());
+        let now = launcher.now();
+
+        match supervisor.on_exit(exit, now.duration_since(started), now) {
+            Decision::Restart { after } => {
+                warn!(?after, "Worker crashed, restarting.");
+                launcher.sleep(after);
+            }
+            Decision::SafeMode => {
+                error!(
+                    crashes = policy.max_crashes,
+                    window = ?policy.crash_window,
+                    "Worker keeps crashing, restarting in safe mode."
+                );
+            }
+            Decision::Stop => {
+                info!(?exit, "Worker exited, stopping supervisor.");
+                return;
+            }
+        }
+    }
+}
+
+/// Runs the service executable itself as the worker process.
+pub struct ProcessLauncher {
+    executable: PathBuf,
+}
+
+impl ProcessLauncher {
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            executable: std::env::current_exe()?,
+        })
+    }
+}
+
+impl Launcher for ProcessLauncher {
+    fn run_worker(&mut self, safe_mode: bool) -> Exit {
+        let mut command = Command::new(&self.executable);
+        if let Some(path) = paths::config_override() {
+            command.arg(paths::CONFIG_ARG).arg(path);
+        }
+        if safe_mode {
+            command.arg("--safe-mode");
+        }
+
+        match command.status() {
+            Ok(status) if status.success() => Exit::Clean,
+            Ok(status) => {
+                warn!(%status, "Worker exited with an error.");
+                Exit::Crashed
+            }
+            Err(err) => {
+                error!("Failed to start worker: {err:?}");
+                Exit::Crashed
+            }
+        }
+    }
+
+    fn sleep(&mut self, duration: Duration) {
+        thread::sleep(duration);
+    }
+
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Replays scripted worker runs and advances a fake clock instead of sleeping.
+    struct FakeLauncher {
+        runs: VecDeque<(Exit, Duration)>,
+        now: Instant,
+        starts: Vec<bool>,
+        sleeps: Vec<Duration>,
+    }
+
+    impl FakeLauncher {
+        fn new(runs: &[(Exit, Duration)]) -> Self {
+            Self {
+                runs: runs.iter().copied().collect(),
+                now: Instant::now(),
+                starts: vec![],
+                sleeps: vec![],
+            }
+        }
+    }
+
+    impl Launcher for FakeLauncher {
+        fn run_worker(&mut self, safe_mode: bool) -> Exit {
+            self.starts.push(safe_mode);
+            let (exit, uptime) = self.runs.pop_front().expect("Worker started too often.");
+            self.now += uptime;
+            exit
+        }
+
+        fn sleep(&mut self, duration: Duration) {
+            self.sleeps.push(duration);
+            self.now += duration;
+        }
+
+        fn now(&self) -> Instant {
+            self.now
+        }
+    }
+
+    const SECOND: Duration = Duration::from_secs(1);
+    const HOUR: Duration = Duration::from_secs(60 * 60);
+
+    fn crash(uptime: Duration) -> (Exit, Duration) {
+        (Exit::Crashed, uptime)
+    }
+
+    fn clean(uptime: Duration) -> (Exit, Duration) {
+        (Exit::Clean, uptime)
+    }
+
+    #[test]
+    fn clean_exit_stops_without_restart() {
+        let mut launcher = FakeLauncher::new(&[clean(HOUR)]);
+        supervise(&mut launcher, Policy::default());
+
+        assert_eq!(launcher.starts, [false]);
+        assert!(launcher.sleeps.is_empty());
+    }
+
+    #[test]
+    fn crashes_restart_with_exponential_backoff() {
+        let policy = Policy {
+            max_crashes: 100,
+            ..Policy::default()
+        };
+        let mut launcher = FakeLauncher::new(&[
+            crash(SECOND),
+            crash(SECOND),
+            crash(SECOND),
+            crash(SECOND),
+            crash(SECOND),
+            crash(SECOND),
+            crash(SECOND),
+            crash(SECOND),
+            clean(SECOND),
+        ]);
+        supervise(&mut launcher, policy);
+
+        let expected: Vec<Duration> = [1, 2, 4, 8, 16, 32, 60, 60]
+            .into_iter()
+            .map(Duration::from_secs)
+            .collect();
+        assert_eq!(launcher.sleeps, expected);
+        assert!(launcher.starts.iter().all(|safe_mode| !safe_mode));
+    }
+
+    #[test]
+    fn stable_run_resets_backoff() {
+        let mut launcher =
+            FakeLauncher::new(&[crash(SECOND), crash(SECOND), crash(HOUR), clean(SECOND)]);
+        supervise(&mut launcher, Policy::default());
+
+        assert_eq!(launcher.sleeps, [SECOND, 2 * SECOND, SECOND]);
+    }
+
+    #[test]
+    fn crash_loop_enters_safe_mode_and_stops_after_it() {
+        let mut launcher = FakeLauncher::new(&[
+            crash(SECOND),
+            crash(SECOND),
+            crash(SECOND),
+            crash(SECOND),
+            crash(SECOND),
+            crash(HOUR),
+        ]);
+        supervise(&mut launcher, Policy::default());
+
+        assert_eq!(launcher.starts, [false, false, false, false, false, true]);
+        assert!(launcher.runs.is_empty());
+    }
+
+    #[test]
+    fn crashes_outside_the_window_do_not_count() {
+        let mut launcher = FakeLauncher::new(&[
+            crash(HOUR),
+            crash(HOUR),
+            crash(HOUR),
+            crash(HOUR),
+            crash(HOUR),
+            crash(HOUR),
+            clean(SECOND),
+        ]);
+        supervise(&mut launcher, Policy::default());
+
+        assert!(launcher.starts.iter().all(|safe_mode| !safe_mode));
+        assert_eq!(launcher.sleeps, [SECOND; 6]);
+    }
+}
@@ -0,0 +1,344 @@
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Instant;
+
+use com_policy_config::{IPolicyConfig, PolicyConfigClient};
+use tracing::{debug, error, info, warn};
+use windows::Win32::Foundation::{E_ACCESSDENIED, ERROR_NOT_FOUND};
+use windows::Win32::Media::Audio::{
+    AUDCLNT_E_DEVICE_INVALIDATED, DEVICE_STATE_ACTIVE, DEVICE_STATE_DISABLED,
+    DEVICE_STATE_UNPLUGGED, IMMDeviceEnumerator, MMDeviceEnumerator, eConsole,
+};
+use windows::Win32::System::Com::{
+    CLSCTX_ALL, COINIT_MULTITHREADED, CoCreateInstance, CoInitializeEx, CoUninitialize,
+};
+use windows::core::HSTRING;
+use winit::event_loop::EventLoopProxy;
+
+use crate::config::{Config, DeviceBinding, NotificationConfig, Profile};
+use crate::dyn_icon::Badge;
+use crate::notify::{self, NotificationAction, Notifier};
+use crate::tray::UserEvent;
+
+/// A request to change or report the active profile.
+#[derive(Debug, Clone)]
+pub enum Command {
+    /// Activate the profile at this index.
+    Activate(usize),
+    /// Activate the profile with this name, ignoring case.
+    ActivateNamed(String),
+    /// Go back to the profile that was active before the current one.
+    Undo,
+    Next,
+    Previous,
+    /// Show a notification with the active profile.
+    ShowStatus,
+}
+
+/// Keeps track of the active profile and switches the audio devices.
+struct Switcher {
+    /// The profiles with their devices on this machine.
+    profiles: Vec<(Profile, DeviceBinding)>,
+    notifications: NotificationConfig,
+    notifier: Box<dyn Notifier>,
+    proxy: Option<EventLoopProxy<UserEvent>>,
+    current: Option<usize>,
+    previous: Option<usize>,
+}
+
+impl Switcher {
+    fn handle(&mut self, command: Command) {
+        debug!(?command, "Received command.");
+
+        let count = self.profiles.len();
+
+        let index = match command {
+            Command::ShowStatus => {
+                self.show_status();
+                return;
+            }
+            _ if count == 0 => return,
+            Command::Activate(index) => Some(index),
+            Command::ActivateNamed(name) => {
+                let index = self
+                    .profiles
+                    .iter()
+                    .position(|(p, _)| p.profile_name.eq_ignore_ascii_case(&name));
+
+                if index.is_none() {
+                    warn!(profile = %name, "No profile with this name.");
+                    notify::show_message(
+                        "AudioSwitch",
+                        &format!("There is no profile named {name}."),
+                    );
+                }
+                index
+            }
+            Command::Undo => self.previous,
+            Command::Next => Some(self.current.map_or(0, |i| (i + 1) % count)),
+            Command::Previous => Some(self.current.map_or(count - 1, |i| (i + count - 1) % count)),
+        };
+
+        if let Some(index) = index.filter(|&it| it < count) {
+            self.activate(index);
+        }
+    }
+
+    fn activate(&mut self, index: usize) {
+        let (profile, devices) = &self.profiles[index];
+        let started = Instant::now();
+        let result = set_profile(devices);
+        let elapsed_ms = started.elapsed().as_millis() as u64;
+
+        match &result {
+            Ok(_) => info!(profile = %profile.profile_name, elapsed_ms, "Activated profile."),
+            Err(err) => error!(
+                profile = %profile.profile_name,
+                elapsed_ms,
+                suggestion = err.suggestion(),
+                "Failed to activate profile: {err}"
+            ),
+        }
+
+        if result.is_ok() && self.current != Some(index) {
+            self.previous = self.current;
+            self.current = Some(index);
+        }
+
+        let mut actions = vec![];
+        if self.previous.is_some() {
+            actions.push(NotificationAction::Undo);
+        }
+        if self.profiles.len() > 1 {
+            actions.push(NotificationAction::NextProfile);
+        }
+
+        if let Some(notification) = notify::activation_notification(
+            &self.notifications,
+            profile,
+            devices,
+            result.as_ref().err(),
+            actions,
+        ) && let Err(err) = self.notifier.notify(&notification)
+        {
+            warn!("Failed to show notification: {err:?}");
+        }
+
+        if result.is_ok()
+            && let Some(color) = profile.color
+        {
+            self.send_event(UserEvent::ColorChange(color));
+        }
+        self.send_event(UserEvent::BadgeChange(
+            Badge::ActivationFailed,
+            result.is_err(),
+        ));
+    }
+
+    fn show_status(&self) {
+        let body = match self.current {
+            Some(index) => format!("Active profile: {}", self.profiles[index].0.profile_name),
+            None => format!(
+                "{} profiles, none activated since the service started.",
+                self.profiles.len()
+            ),
+        };
+
+        notify::show_message("AudioSwitch is running", &body);
+    }
+
+    /// Updates the tray icon, if there is one.
+    fn send_event(&self, event: UserEvent) {
+        if let Some(proxy) = &self.proxy {
+            let _ = proxy.send_event(event);
+        }
+    }
+}
+
+/// Starts the thread that activates profiles and returns the channel to send it commands.
+/// Clicked notification buttons are sent to the event loop behind `proxy`, or straight back to
+/// the switcher when running without one.
+pub fn spawn_switcher(config: Config, proxy: Option<EventLoopProxy<UserEvent>>) -> Sender<Command> {
+    let (sender, receiver) = mpsc::channel();
+    let action_sender = sender.clone();
+
+    thread::spawn(move || {
+        unsafe {
+            if let Err(err) = CoInitializeEx(None, COINIT_MULTITHREADED) {
+                error!("Failed to initialize COM: {err:?}");
+            }
+        }
+
+        let action_proxy = Mutex::new(proxy.clone());
+        let notifier = notify::from_config(
+            &config.notifications,
+            Arc::new(move |action| match action_proxy.lock().as_deref() {
+                Ok(Some(proxy)) => {
+                    let _ = proxy.send_event(match action {
+                        NotificationAction::Undo => UserEvent::UndoProfile,
+                        NotificationAction::NextProfile => UserEvent::NextProfile,
+                    });
+                }
+                _ => {
+                    let _ = action_sender.send(match action {
+                        NotificationAction::Undo => Command::Undo,
+                        NotificationAction::NextProfile => Command::Next,
+                    });
+                }
+            }),
+        );
+
+        // Unbound profiles were already skipped, see `skip_invalid_settings`.
+        let profiles = config
+            .profiles
+            .iter()
+            .filter_map(|p| Some((p.clone(), config.binding(&p.profile_name)?.clone())))
+            .collect();
+
+        let mut switcher = Switcher {
+            profiles,
+            notifications: config.notifications,
+            notifier,
+            proxy,
+            current: None,
+            previous: None,
+        };
+
+        for command in receiver {
+            switcher.handle(command);
+        }
+
+        unsafe {
+            CoUninitialize();
+        }
+    });
+
+    sender
+}
+
+/// Why a profile could not be activated.
+#[derive(Debug)]
+pub enum ActivationError {
+    /// Windows doesn't know the device any more.
+    DeviceNotFound { device: String },
+    /// The device exists but is disabled, unplugged or not present.
+    DeviceDisabled { device: String, state: u32 },
+    /// Windows refused to change the default device.
+    AccessDenied { device: String },
+    /// Any other failed COM call.
+    Com(windows::core::Error),
+    /// The input device was switched, but switching the output device failed.
+    PartialSuccess(Box<ActivationError>),
+}
+
+impl ActivationError {
+    /// What the user can do about the error.
+    pub fn suggestion(&self) -> &'static str {
+        match self {
+            ActivationError::DeviceNotFound { .. } => {
+                "Reconnect the device, or recreate the profile in the setup if it was replaced."
+            }
+            ActivationError::DeviceDisabled { .. } => {
+                "Plug in the device or enable it in the Windows sound settings."
+            }
+            ActivationError::AccessDenied { .. } => {
+                "Restart AudioSwitch, or reinstall it from the setup if this keeps happening."
+            }
+            ActivationError::Com(_) => {
+                "Try again. If the error persists, restart the Windows Audio service."
+            }
+            ActivationError::PartialSuccess(err) => err.suggestion(),
+        }
+    }
+
+    fn from_com(err: windows::core::Error, device: &str) -> ActivationError {
+        let device = device.to_owned();
+
+        match err.code() {
+            E_ACCESSDENIED => ActivationError::AccessDenied { device },
+            code if code == ERROR_NOT_FOUND.to_hresult()
+                || code == AUDCLNT_E_DEVICE_INVALIDATED =>
+            {
+                ActivationError::DeviceNotFound { device }
+            }
+            _ => ActivationError::Com(err),
+        }
+    }
+}
+
+impl std::fmt::Display for ActivationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ActivationError::DeviceNotFound { device } => {
+                write!(f, "The device \"{device}\" was not found.")
+            }
+            ActivationError::DeviceDisabled { device, state } => {
+                let state = match *state {
+                    DEVICE_STATE_DISABLED => "disabled",
+                    DEVICE_STATE_UNPLUGGED => "unplugged",
+                    _ => "not present",
+                };
+                write!(f, "The device \"{device}\" is {state}.")
+            }
+            ActivationError::AccessDenied { device } => {
+                write!(f, "Windows denied switching to \"{device}\".")
+            }
+            ActivationError::Com(err) => {
+                write!(
+                    f,
+                    "Windows reported error {:#010X}: {}",
+                    err.code().0 as u32,
+                    err.message().to_string().trim()
+                )
+            }
+            ActivationError::PartialSuccess(err) => {
+                write!(f, "Only the input device was switched. {err}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ActivationError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ActivationError::Com(err) => Some(err),
+            ActivationError::PartialSuccess(err) => Some(err.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+/// Makes the profile's devices the default input and output. The input is switched first.
+fn set_profile(devices: &DeviceBinding) -> Result<(), ActivationError> {
+    set_default_device(&devices.input_id, &devices.input_name)?;
+    set_default_device(&devices.output_id, &devices.output_name)
+        .map_err(|err| ActivationError::PartialSuccess(Box::new(err)))
+}
+
+fn set_default_device(device_id: &str, device_name: &str) -> Result<(), ActivationError> {
+    let com_error = |err| ActivationError::from_com(err, device_name);
+    let device_id = HSTRING::from(device_id);
+
+    unsafe {
+        let enumerator: IMMDeviceEnumerator =
+            CoCreateInstance(&MMDeviceEnumerator, None, CLSCTX_ALL).map_err(com_error)?;
+        let state = enumerator
+            .GetDevice(&device_id)
+            .and_then(|device| device.GetState())
+            .map_err(com_error)?;
+
+        if state != DEVICE_STATE_ACTIVE {
+            return Err(ActivationError::DeviceDisabled {
+                device: device_name.to_owned(),
+                state,
+            });
+        }
+
+        let policy_config: IPolicyConfig =
+            CoCreateInstance(&PolicyConfigClient, None, CLSCTX_ALL).map_err(com_error)?;
+        policy_config
+            .SetDefaultEndpoint(&device_id, eConsole)
+            .map_err(com_error)
+    }
+}
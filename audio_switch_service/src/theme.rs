@@ -0,0 +1,238 @@
+use anyhow::Result;
+use windows::Win32::System::Registry::{HKEY_CURRENT_USER, RRF_RT_REG_DWORD, RegGetValueW};
+use windows::core::w;
+
+use crate::color::Color;
+use crate::config::TaskbarTheme;
+
+/// WCAG 2.1 minimum contrast for graphical objects. Icons below this get an outline.
+pub const MIN_CONTRAST: f32 = 3.0;
+
+/// The theme of the taskbar the tray icon is drawn on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Theme {
+    Light,
+    Dark,
+}
+
+impl Theme {
+    /// Resolves the configured setting, reading the system theme for `TaskbarTheme::Auto`.
+    pub fn resolve(setting: TaskbarTheme) -> Theme {
+        match setting {
+            TaskbarTheme::Auto => system_theme().unwrap_or(Theme::Dark),
+            TaskbarTheme::Light => Theme::Light,
+            TaskbarTheme::Dark => Theme::Dark,
+        }
+    }
+
+    /// Approximate taskbar background color of the Windows 11 themes.
+    pub fn taskbar_color(self) -> Color {
+        match self {
+            Theme::Light => Color::rgb(243, 243, 243),
+            Theme::Dark => Color::rgb(32, 32, 32),
+        }
+    }
+}
+
+/// Colors used to draw the glyph and the optional outline of a tray icon.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IconStyle {
+    pub glyph: Color,
+    pub outline: Option<Color>,
+}
+
+/// Picks glyph and outline colors so the icon stays readable on the `theme` taskbar.
+///
+/// The glyph is drawn onto `background`, or directly onto the taskbar for the untinted icon, and
+/// gets whichever of black or white contrasts more with it. A background that blends into the
+/// taskbar gets an outline that contrasts with the taskbar.
+pub fn icon_style(background: Option<Color>, theme: Theme) -> IconStyle {
+    const CANDIDATES: [Color; 2] = [Color::rgb(255, 255, 255), Color::rgb(0, 0, 0)];
+
+    let taskbar = theme.taskbar_color();
+
+    let Some(background) = background.map(|it| composite(it, taskbar)) else {
+        return IconStyle {
+            glyph: most_contrasting(taskbar, CANDIDATES),
+            outline: None,
+        };
+    };
+
+    IconStyle {
+        glyph: most_contrasting(background, CANDIDATES),
+        outline: (contrast_ratio(background, taskbar) < MIN_CONTRAST)
+            .then(|| most_contrasting(taskbar, CANDIDATES)),
+    }
+}
+
+/// Relative luminance as defined by WCAG 2.1, from 0.0 (black) to 1.0 (white).
+pub fn relative_luminance(color: Color) -> f32 {
+    let linear = |channel: u8| {
+        let c = channel as f32 / 255.0;
+        if c <= 0.04045 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    };
+
+    0.2126 * linear(color.r) + 0.7152 * linear(color.g) + 0.0722 * linear(color.b)
+}
+
+/// WCAG 2.1 contrast ratio between two colors, from 1.0 (identical) to 21.0 (black on white).
+pub fn contrast_ratio(a: Color, b: Color) -> f32 {
+    let (la, lb) = (relative_luminance(a), relative_luminance(b));
+    let (lighter, darker) = if la > lb { (la, lb) } else { (lb, la) };
+
+    (lighter + 0.05) / (darker + 0.05)
+}
+
+/// Returns whichever of the `candidates` has the higher contrast ratio against `background`.
+fn most_contrasting(background: Color, candidates: [Color; 2]) -> Color {
+    let [a, b] = candidates;
+
+    if contrast_ratio(background, a) >= contrast_ratio(background, b) {
+        a
+    } else {
+        b
+    }
+}
+
+/// The color a translucent `color` appears as on top of the opaque `background`.
+fn composite(color: Color, background: Color) -> Color {
+    let alpha = color.a as u32;
+    let mix = |fg: u8, bg: u8| ((fg as u32 * alpha + bg as u32 * (255 - alpha) + 127) / 255) as u8;
+
+    Color::rgb(
+        mix(color.r, background.r),
+        mix(color.g, background.g),
+        mix(color.b, background.b),
+    )
+}
+
+/// Reads whether the taskbar uses the light or dark theme.
+fn system_theme() -> Result<Theme> {
+    let mut value: u32 = 0;
+    let mut size = std::mem::size_of::<u32>() as u32;
+
+    unsafe {
+        RegGetValueW(
+            HKEY_CURRENT_USER,
+            w!("Software\\Microsoft\\Windows\\CurrentVersion\\Themes\\Personalize"),
+            w!("SystemUsesLightTheme"),
+            RRF_RT_REG_DWORD,
+            None,
+            Some(&mut value as *mut u32 as *mut _),
+            Some(&mut size),
+        )?;
+    }
+
+    Ok(if value == 1 {
+        Theme::Light
+    } else {
+        Theme::Dark
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BLACK: Color = Color::rgb(0, 0, 0);
+    const WHITE: Color = Color::rgb(255, 255, 255);
+
+    fn gray(value: u8) -> Color {
+        Color::rgb(value, value, value)
+    }
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < 1e-4,
+            "expected {expected}, got {actual}"
+        );
+    }
+
+    #[test]
+    fn luminance_spans_black_to_white() {
+        assert_close(relative_luminance(BLACK), 0.0);
+        assert_close(relative_luminance(WHITE), 1.0);
+    }
+
+    #[test]
+    fn luminance_switches_to_the_power_curve_above_the_knee() {
+        // 10 / 255 is just below 0.04045, 11 / 255 just above.
+        assert_close(relative_luminance(gray(10)), 10.0 / 255.0 / 12.92);
+        assert_close(
+            relative_luminance(gray(11)),
+            ((11.0 / 255.0 + 0.055) / 1.055_f32).powf(2.4),
+        );
+        assert!(relative_luminance(gray(10)) < relative_luminance(gray(11)));
+    }
+
+    #[test]
+    fn contrast_of_black_and_white_is_21() {
+        assert_close(contrast_ratio(BLACK, WHITE), 21.0);
+    }
+
+    #[test]
+    fn contrast_is_symmetric() {
+        let (a, b) = (Color::rgb(255, 136, 0), Color::rgb(32, 32, 32));
+        assert_close(contrast_ratio(a, b), contrast_ratio(b, a));
+    }
+
+    #[test]
+    fn equal_colors_have_contrast_1() {
+        assert_close(contrast_ratio(gray(128), gray(128)), 1.0);
+    }
+
+    #[test]
+    fn composite_mixes_by_alpha() {
+        let red = |a| Color {
+            a,
+            ..Color::rgb(255, 0, 0)
+        };
+
+        assert_eq!(composite(red(128), BLACK), Color::rgb(128, 0, 0));
+        assert_eq!(composite(red(128), WHITE), Color::rgb(255, 127, 127));
+        assert_eq!(composite(red(0), WHITE), WHITE);
+        assert_eq!(composite(red(255), WHITE), Color::rgb(255, 0, 0));
+    }
+
+    #[test]
+    fn most_contrasting_picks_the_farther_color() {
+        assert_eq!(most_contrasting(gray(40), [WHITE, BLACK]), WHITE);
+        assert_eq!(most_contrasting(gray(220), [WHITE, BLACK]), BLACK);
+    }
+
+    #[test]
+    fn untinted_icon_contrasts_with_the_taskbar() {
+        let light = icon_style(None, Theme::Light);
+        assert_eq!(light.glyph, BLACK);
+        assert_eq!(light.outline, None);
+
+        let dark = icon_style(None, Theme::Dark);
+        assert_eq!(dark.glyph, WHITE);
+        assert_eq!(dark.outline, None);
+    }
+
+    #[test]
+    fn light_color_on_light_taskbar_gets_a_dark_glyph_and_outline() {
+        let style = icon_style(Some(WHITE), Theme::Light);
+        assert_eq!(style.glyph, BLACK);
+        assert_eq!(style.outline, Some(BLACK));
+    }
+
+    #[test]
+    fn dark_color_on_dark_taskbar_gets_a_light_glyph_and_outline() {
+        let style = icon_style(Some(gray(20)), Theme::Dark);
+        assert_eq!(style.glyph, WHITE);
+        assert_eq!(style.outline, Some(WHITE));
+    }
+
+    #[test]
+    fn contrasting_color_needs_no_outline() {
+        let style = icon_style(Some(Color::rgb(255, 255, 0)), Theme::Dark);
+        assert_eq!(style.glyph, BLACK);
+        assert_eq!(style.outline, None);
+    }
+}
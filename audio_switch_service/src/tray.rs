@@ -1,56 +1,122 @@
-use std::{env::current_exe, io::Cursor, process::Command};
+use std::sync::mpsc::Sender;
+use std::{env::current_exe, process::Command};
 
+use anyhow::Result;
+use tracing::{error, info};
 use tray_icon::{
     TrayIcon, TrayIconBuilder, TrayIconEvent,
-    menu::{Menu, MenuEvent, MenuItem},
+    menu::{Menu, MenuEvent, MenuId, MenuItem},
 };
 use winit::{application::ApplicationHandler, event_loop::EventLoop};
 
-use crate::dyn_icon::{HexColor, generate_icon};
-
-pub const ICON: &[u8] = include_bytes!("../assets/app.png");
+use crate::color::Color;
+use crate::dyn_icon::{Badge, Badges, IconCache, base_icon_set};
+use crate::switcher;
+use crate::theme::Theme;
 
 #[derive(Debug)]
 pub enum UserEvent {
     TrayIconEvent,
     MenuEvent(MenuEvent),
-    ColorChange(HexColor),
+    ColorChange(Color),
+    BadgeChange(Badge, bool),
+    ThemeChange(Theme),
+    /// The Undo button of a notification was clicked.
+    UndoProfile,
+    /// The Next profile button of a notification was clicked.
+    NextProfile,
+    /// Remove the tray icon and leave the event loop.
+    Shutdown,
 }
 
 struct Application {
     tray_icon: Option<TrayIcon>,
+    icons: IconCache,
+    color: Option<Color>,
+    badges: Badges,
+    theme: Theme,
+    switcher: Sender<switcher::Command>,
+    safe_mode: bool,
+    /// Ids of the Open Setup and Quit entries, known once the menu exists.
+    setup_id: Option<MenuId>,
+    quit_id: Option<MenuId>,
 }
 
 impl Application {
-    pub fn new() -> Application {
-        Application { tray_icon: None }
+    pub fn new(theme: Theme, switcher: Sender<switcher::Command>, safe_mode: bool) -> Application {
+        let mut badges = Badges::default();
+        badges.set(Badge::SafeMode, safe_mode);
+
+        Application {
+            tray_icon: None,
+            icons: IconCache::default(),
+            color: None,
+            badges,
+            theme,
+            switcher,
+            safe_mode,
+            setup_id: None,
+            quit_id: None,
+        }
     }
 
-    fn new_tray_icon() -> TrayIcon {
-        let icon = load_icon();
+    /// Redraws the tray icon from the current profile color and badges.
+    fn refresh_icon(&mut self) {
+        let Some(tray_icon) = self.tray_icon.as_mut() else {
+            return;
+        };
+
+        // On failure the previous icon stays.
+        let result = self
+            .icons
+            .get(self.color, self.badges, self.theme)
+            .and_then(|icon| Ok(tray_icon.set_icon(Some(icon))?));
+
+        if let Err(err) = result {
+            error!("Failed to update tray icon: {err:?}");
+        }
+    }
 
-        TrayIconBuilder::new()
-            .with_menu(Box::new(Self::new_tray_menu()))
-            .with_tooltip("AudioSwitch - Change Audio Endpoints")
+    fn new_tray_icon(&mut self) -> Result<TrayIcon> {
+        let icon = base_icon_set()?.to_tray_icon()?;
+        let tooltip = if self.safe_mode {
+            "AudioSwitch - Safe mode, hotkeys are disabled"
+        } else {
+            "AudioSwitch - Change Audio Endpoints"
+        };
+
+        Ok(TrayIconBuilder::new()
+            .with_menu(Box::new(self.new_tray_menu()))
+            .with_tooltip(tooltip)
             .with_icon(icon)
             .with_title("AudioSwitch")
-            .build()
-            .unwrap()
+            .build()?)
     }
 
-    fn new_tray_menu() -> Menu {
+    fn new_tray_menu(&mut self) -> Menu {
         let menu = Menu::new();
+
+        if self.safe_mode {
+            let warning = MenuItem::new("Safe mode: crashed repeatedly, see the log", false, None);
+            if let Err(err) = menu.append(&warning) {
+                error!("Failed to add menu entry: {err:?}");
+            }
+        }
+
         let setup_entry = MenuItem::new("Open Setup", true, None);
         let exit_entry = MenuItem::new("Quit", true, None);
 
         if let Err(err) = menu.append(&setup_entry) {
-            println!("{err:?}");
+            error!("Failed to add menu entry: {err:?}");
         }
 
         if let Err(err) = menu.append(&exit_entry) {
-            println!("{err:?}");
+            error!("Failed to add menu entry: {err:?}");
         }
 
+        self.setup_id = Some(setup_entry.id().clone());
+        self.quit_id = Some(exit_entry.id().clone());
+
         menu
     }
 }
@@ -72,7 +138,13 @@ impl ApplicationHandler<UserEvent> for Application {
         cause: winit::event::StartCause,
     ) {
         if winit::event::StartCause::Init == cause {
-            self.tray_icon = Some(Self::new_tray_icon());
+            match self.new_tray_icon() {
+                Ok(tray_icon) => {
+                    self.tray_icon = Some(tray_icon);
+                    self.refresh_icon();
+                }
+                Err(err) => error!("Failed to create tray icon, running headless: {err:?}"),
+            }
         }
     }
 
@@ -81,29 +153,52 @@ impl ApplicationHandler<UserEvent> for Application {
             UserEvent::MenuEvent(e) => {
                 let MenuEvent { id } = e;
 
-                if &id.0 == "1001" {
+                if self.setup_id.as_ref() == Some(&id) {
                     if let Ok(mut exe) = current_exe() {
                         exe.set_file_name("AudioSwitchSetup.exe");
-                        let _ = Command::new(exe).spawn();
+                        if let Err(err) = Command::new(exe).spawn() {
+                            error!("Failed to open setup: {err:?}");
+                        }
                     }
-                } else if &id.0 == "1002" {
+                } else if self.quit_id.as_ref() == Some(&id) {
+                    info!("Quit from the tray menu.");
+                    self.tray_icon = None;
                     event_loop.exit();
                 }
             }
             UserEvent::ColorChange(color) => {
-                let icon = generate_icon(color).unwrap_or(load_icon());
-                self.tray_icon
-                    .as_mut()
-                    .unwrap()
-                    .set_icon(Some(icon))
-                    .expect("Failed to set tray icon.");
+                self.color = Some(color);
+                self.refresh_icon();
+            }
+            UserEvent::BadgeChange(badge, active) if self.badges.contains(badge) != active => {
+                self.badges.set(badge, active);
+                self.refresh_icon();
+            }
+            UserEvent::ThemeChange(theme) if self.theme != theme => {
+                self.theme = theme;
+                self.refresh_icon();
+            }
+            UserEvent::Shutdown => {
+                self.tray_icon = None;
+                event_loop.exit();
+            }
+            UserEvent::UndoProfile => {
+                let _ = self.switcher.send(switcher::Command::Undo);
+            }
+            UserEvent::NextProfile => {
+                let _ = self.switcher.send(switcher::Command::Next);
             }
             _ => {}
         }
     }
 }
 
-pub fn create_tray(event_loop: EventLoop<UserEvent>) {
+pub fn create_tray(
+    event_loop: EventLoop<UserEvent>,
+    theme: Theme,
+    switcher: Sender<switcher::Command>,
+    safe_mode: bool,
+) {
     // set a tray event handler that forwards the event and wakes up the event loop
     let proxy = event_loop.create_proxy();
     TrayIconEvent::set_event_handler(Some(move |_event| {
@@ -114,25 +209,12 @@ pub fn create_tray(event_loop: EventLoop<UserEvent>) {
         let _ = proxy.send_event(UserEvent::MenuEvent(event));
     }));
 
-    let mut app = Application::new();
+    let mut app = Application::new(theme, switcher, safe_mode);
 
     let _menu_channel = MenuEvent::receiver();
     let _tray_channel = TrayIconEvent::receiver();
 
     if let Err(err) = event_loop.run_app(&mut app) {
-        println!("Error: {:?}", err);
+        error!("Event loop failed: {err:?}");
     }
 }
-
-fn load_icon() -> tray_icon::Icon {
-    let (icon_rgba, icon_width, icon_height) = {
-        let img_bytes = ICON;
-        let mut image = image::ImageReader::new(Cursor::new(img_bytes));
-        image.set_format(image::ImageFormat::Png);
-        let rgb_img = image.decode().expect("Failed to load image.").into_rgba8();
-        let (width, height) = rgb_img.dimensions();
-        let rgba = rgb_img.into_raw();
-        (rgba, width, height)
-    };
-    tray_icon::Icon::from_rgba(icon_rgba, icon_width, icon_height).expect("Failed to open icon")
-}
@@ -0,0 +1,593 @@
+use std::path::Path;
+
+use anyhow::{Context, Result, bail};
+use serde::Deserialize;
+
+use crate::bundle::Binding;
+use crate::color::Color;
+use crate::config::{Config, DeviceBinding, HotkeyConfig, Profile};
+use crate::devices::{self, Device, DeviceType, enumerate_devices};
+use crate::hotkeys;
+use crate::paths;
+
+/// The complete set of profiles a machine should have. Profiles missing from the file are
+/// removed, as are the next/previous hotkeys if they aren't set.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Desired {
+    #[serde(default)]
+    profiles: Vec<DesiredProfile>,
+    next_profile: Option<HotkeyConfig>,
+    previous_profile: Option<HotkeyConfig>,
+}
+
+/// A profile whose devices are given as name patterns, so the same file works on machines
+/// with different endpoint IDs.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DesiredProfile {
+    name: String,
+    color: Option<Color>,
+    hotkey: HotkeyConfig,
+    /// Device name, `*` matches any text and `?` any character, case is ignored.
+    input: String,
+    output: String,
+}
+
+/// A profile with its devices on this machine, if it is bound here.
+#[derive(Clone)]
+struct Entry {
+    profile: Profile,
+    devices: Option<DeviceBinding>,
+}
+
+impl Entry {
+    fn new(config: &Config, profile: &Profile) -> Self {
+        Self {
+            profile: profile.clone(),
+            devices: config.binding(&profile.profile_name).cloned(),
+        }
+    }
+
+    /// The pattern of the device of `device_type`, and the ID and name of the endpoint it is
+    /// bound to.
+    fn device(&self, device_type: DeviceType) -> (&str, Option<(&str, &str)>) {
+        let devices = self.devices.as_ref();
+        match device_type {
+            DeviceType::Output => (
+                &self.profile.output_pattern,
+                devices.map(|it| (it.output_id.as_str(), it.output_name.as_str())),
+            ),
+            DeviceType::Input => (
+                &self.profile.input_pattern,
+                devices.map(|it| (it.input_id.as_str(), it.input_name.as_str())),
+            ),
+        }
+    }
+
+    fn device_text(&self, device_type: DeviceType) -> String {
+        match self.device(device_type) {
+            (pattern, Some((_, name))) => format!("{name} (\"{pattern}\")"),
+            (pattern, None) => format!("not bound (\"{pattern}\")"),
+        }
+    }
+}
+
+/// One difference between the current and the desired configuration.
+enum Change {
+    Add(Entry),
+    Update {
+        before: Box<Entry>,
+        after: Box<Entry>,
+    },
+    Remove(Entry),
+    Order {
+        before: Vec<String>,
+        after: Vec<String>,
+    },
+    Binding {
+        binding: Binding,
+        before: Option<HotkeyConfig>,
+        after: Option<HotkeyConfig>,
+    },
+}
+
+impl std::fmt::Display for Change {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Change::Add(entry) => {
+                writeln!(f, "    + profile \"{}\"", entry.profile.profile_name)?;
+                writeln!(f, "        hotkey: {}", entry.profile.hotkey)?;
+                writeln!(
+                    f,
+                    "        output: {}",
+                    entry.device_text(DeviceType::Output)
+                )?;
+                writeln!(
+                    f,
+                    "        input:  {}",
+                    entry.device_text(DeviceType::Input)
+                )?;
+                write!(f, "        color:  {}", color_text(entry.profile.color))
+            }
+            Change::Update { before, after } => {
+                write!(f, "    ~ profile \"{}\"", after.profile.profile_name)?;
+                if before.profile.hotkey != after.profile.hotkey {
+                    write!(
+                        f,
+                        "\n        hotkey: {} -> {}",
+                        before.profile.hotkey, after.profile.hotkey
+                    )?;
+                }
+                for (label, device_type) in [
+                    ("output:", DeviceType::Output),
+                    ("input: ", DeviceType::Input),
+                ] {
+                    if before.device(device_type) != after.device(device_type) {
+                        write!(
+                            f,
+                            "\n        {label} {} -> {}",
+                            before.device_text(device_type),
+                            after.device_text(device_type)
+                        )?;
+                    }
+                }
+                if before.profile.color != after.profile.color {
+                    write!(
+                        f,
+                        "\n        color:  {} -> {}",
+                        color_text(before.profile.color),
+                        color_text(after.profile.color)
+                    )?;
+                }
+                Ok(())
+            }
+            Change::Remove(entry) => {
+                write!(f, "    - profile \"{}\"", entry.profile.profile_name)
+            }
+            Change::Order { before, after } => write!(
+                f,
+                "    ~ profile order: {} -> {}",
+                before.join(", "),
+                after.join(", ")
+            ),
+            Change::Binding {
+                binding,
+                before,
+                after,
+            } => {
+                let (sign, before, after) = match (before, after) {
+                    (None, Some(after)) => ("+", "none".to_string(), after.to_string()),
+                    (Some(before), None) => ("-", before.to_string(), "none".to_string()),
+                    (before, after) => (
+                        "~",
+                        before
+                            .as_ref()
+                            .map_or("none".to_string(), |it| it.to_string()),
+                        after
+                            .as_ref()
+                            .map_or("none".to_string(), |it| it.to_string()),
+                    ),
+                };
+                write!(f, "    {sign} {binding} hotkey: {before} -> {after}")
+            }
+        }
+    }
+}
+
+/// Compares the profiles in `path` with the current configuration, prints the changes and
+/// applies them. Applying the same file again changes nothing. Returns whether the
+/// configuration was changed.
+pub fn run(path: &Path, dry_run: bool, yes: bool) -> Result<bool> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}.", path.display()))?;
+    let desired: Desired =
+        toml::from_str(&content).with_context(|| format!("Failed to read {}.", path.display()))?;
+
+    let config: Config = paths::load_config()?;
+    let target = target_config(&config, desired, &enumerate_devices()?)?;
+    let changes = plan(&config, &target);
+
+    if changes.is_empty() {
+        println!(
+            "    > No changes, the configuration matches {}.",
+            path.display()
+        );
+        return Ok(false);
+    }
+
+    for change in &changes {
+        println!("{change}");
+    }
+
+    let count = |matches: fn(&Change) -> bool| changes.iter().filter(|it| matches(it)).count();
+    println!(
+        "    Plan: {} to add, {} to change, {} to remove.",
+        count(|it| matches!(it, Change::Add(_) | Change::Binding { before: None, .. })),
+        count(|it| {
+            matches!(
+                it,
+                Change::Update { .. }
+                    | Change::Order { .. }
+                    | Change::Binding {
+                        before: Some(_),
+                        after: Some(_),
+                        ..
+                    }
+            )
+        }),
+        count(|it| matches!(it, Change::Remove(_) | Change::Binding { after: None, .. })),
+    );
+
+    if dry_run {
+        return Ok(false);
+    }
+
+    if !yes && !inquire::Confirm::new("Apply this plan? (y/n)").prompt()? {
+        return Ok(false);
+    }
+
+    target.store()?;
+    println!("    Applying the plan... Done ✔");
+
+    Ok(true)
+}
+
+/// The current configuration with the profiles and hotkeys replaced by the desired ones.
+/// Every problem of the file is reported at once instead of stopping at the first.
+fn target_config(
+    config: &Config,
+    desired: Desired,
+    available_devices: &[Device],
+) -> Result<Config> {
+    let mut errors = vec![];
+    let mut names = vec![];
+    let mut target = config.clone();
+    target.profiles = vec![];
+
+    for profile in desired.profiles {
+        let name = profile.name;
+        if name.is_empty() {
+            errors.push("A profile has no name.".to_string());
+            continue;
+        }
+        if names.contains(&name) {
+            errors.push(format!("Profile \"{name}\" is listed twice."));
+            continue;
+        }
+        names.push(name.clone());
+
+        let current = config.profiles.iter().find(|it| it.profile_name == name);
+        let bound = config.binding(&name);
+        let hotkey = hotkeys::normalize(&profile.hotkey);
+        let output = resolve_device(
+            &profile.output,
+            DeviceType::Output,
+            available_devices,
+            bound.map(|it| it.output_id.as_str()),
+        );
+        let input = resolve_device(
+            &profile.input,
+            DeviceType::Input,
+            available_devices,
+            bound.map(|it| it.input_id.as_str()),
+        );
+
+        let (hotkey, output, input) = match (hotkey, output, input) {
+            (Ok(hotkey), Ok(output), Ok(input)) => (hotkey, output, input),
+            (hotkey, output, input) => {
+                for err in [hotkey.err(), output.err(), input.err()]
+                    .into_iter()
+                    .flatten()
+                {
+                    errors.push(format!("Profile \"{name}\": {err}"));
+                }
+                continue;
+            }
+        };
+
+        let mut new_profile = Profile::default()
+            .set_profile_id(current.map_or(0, |it| it.profile_id))
+            .set_profile_name(&name)
+            .set_input_pattern(&profile.input)
+            .set_output_pattern(&profile.output)
+            .set_hotkey(hotkey.modifier, hotkey.hotkey);
+        new_profile.color = profile.color;
+
+        target.profiles.push(new_profile);
+        target.bind(&name, devices::binding(input, output));
+    }
+
+    // Removed profiles take their bindings on other machines with them.
+    for bindings in target.bindings.values_mut() {
+        bindings.retain(|name, _| names.contains(name));
+    }
+
+    for (binding, hotkey) in [
+        (Binding::Next, desired.next_profile),
+        (Binding::Previous, desired.previous_profile),
+    ] {
+        match hotkey.as_ref().map(hotkeys::normalize).transpose() {
+            Ok(hotkey) => *binding.slot(&mut target) = hotkey,
+            Err(err) => errors.push(format!("The {binding} hotkey: {err}")),
+        }
+    }
+
+    let mut used: Vec<(String, &HotkeyConfig)> = vec![];
+    let holders = target
+        .profiles
+        .iter()
+        .map(|it| (format!("profile \"{}\"", it.profile_name), Some(&it.hotkey)))
+        .chain([
+            (
+                format!("the {}", Binding::Next),
+                target.next_profile.as_ref(),
+            ),
+            (
+                format!("the {}", Binding::Previous),
+                target.previous_profile.as_ref(),
+            ),
+        ]);
+    for (holder, hotkey) in holders {
+        let Some(hotkey) = hotkey else {
+            continue;
+        };
+        match used.iter().find(|(_, it)| *it == hotkey) {
+            Some((other, _)) => {
+                errors.push(format!("Hotkey {hotkey} is used by {other} and {holder}."))
+            }
+            None => used.push((holder, hotkey)),
+        }
+    }
+
+    if !errors.is_empty() {
+        for err in &errors {
+            println!("    ✘ {err}");
+        }
+        bail!("The desired configuration can't be applied on this machine.");
+    }
+
+    Ok(target)
+}
+
+/// Finds the one device of `device_type` whose name matches `pattern`. If several match, the
+/// profile's current device is kept, so a broad pattern doesn't flip between runs.
+fn resolve_device<'a>(
+    pattern: &str,
+    device_type: DeviceType,
+    available_devices: &'a [Device],
+    current_id: Option<&str>,
+) -> Result<&'a Device> {
+    let candidates: Vec<&Device> = available_devices
+        .iter()
+        .filter(|it| it.device_type == device_type && devices::matches_pattern(pattern, &it.name))
+        .collect();
+
+    if let Some(current) = candidates
+        .iter()
+        .find(|it| Some(it.device_id.as_str()) == current_id)
+    {
+        return Ok(current);
+    }
+
+    match candidates.as_slice() {
+        [device] => Ok(device),
+        [] => bail!("no {device_type} device matches \"{pattern}\"."),
+        candidates => bail!(
+            "{} {device_type} devices match \"{pattern}\": {}.",
+            candidates.len(),
+            candidates
+                .iter()
+                .map(|it| format!("\"{}\"", it.name))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    }
+}
+
+/// The changes that turn `config` into `target`.
+fn plan(config: &Config, target: &Config) -> Vec<Change> {
+    let mut changes = vec![];
+
+    for profile in &target.profiles {
+        match config
+            .profiles
+            .iter()
+            .find(|it| it.profile_name == profile.profile_name)
+        {
+            None => changes.push(Change::Add(Entry::new(target, profile))),
+            Some(current) => {
+                let (before, after) = (Entry::new(config, current), Entry::new(target, profile));
+                if before.profile.hotkey != after.profile.hotkey
+                    || before.profile.color != after.profile.color
+                    || before.device(DeviceType::Output) != after.device(DeviceType::Output)
+                    || before.device(DeviceType::Input) != after.device(DeviceType::Input)
+                {
+                    changes.push(Change::Update {
+                        before: Box::new(before),
+                        after: Box::new(after),
+                    });
+                }
+            }
+        }
+    }
+
+    let kept = |from: &Config, other: &Config| -> Vec<String> {
+        from.profiles
+            .iter()
+            .map(|it| it.profile_name.clone())
+            .filter(|name| other.profiles.iter().any(|it| it.profile_name == *name))
+            .collect()
+    };
+
+    for profile in &config.profiles {
+        if !target
+            .profiles
+            .iter()
+            .any(|it| it.profile_name == profile.profile_name)
+        {
+            changes.push(Change::Remove(Entry::new(config, profile)));
+        }
+    }
+
+    // The order is the order the next/previous hotkeys cycle through.
+    let (before, after) = (kept(config, target), kept(target, config));
+    if before != after {
+        changes.push(Change::Order { before, after });
+    }
+
+    for (binding, before, after) in [
+        (Binding::Next, &config.next_profile, &target.next_profile),
+        (
+            Binding::Previous,
+            &config.previous_profile,
+            &target.previous_profile,
+        ),
+    ] {
+        if before != after {
+            changes.push(Change::Binding {
+                binding,
+                before: before.clone(),
+                after: after.clone(),
+            });
+        }
+    }
+
+    changes
+}
+
+fn color_text(color: Option<Color>) -> String {
+    color.map_or("none".to_string(), |it| it.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADSET: &str = r#"
+        [[profiles]]
+        name = "Headset"
+        hotkey = { modifier = "ALT", hotkey = "F1" }
+        input = "headset*"
+        output = "headset*"
+    "#;
+
+    const SPEAKERS: &str = r#"
+        [[profiles]]
+        name = "Speakers"
+        hotkey = { modifier = "ALT", hotkey = "F2" }
+        input = "Webcam Microphone"
+        output = "Speakers*"
+    "#;
+
+    fn device(device_type: DeviceType, name: &str, device_id: &str) -> Device {
+        Device {
+            device_type,
+            name: name.to_string(),
+            device_id: device_id.to_string(),
+        }
+    }
+
+    fn devices() -> Vec<Device> {
+        vec![
+            device(DeviceType::Output, "Speakers (Realtek Audio)", "speakers"),
+            device(DeviceType::Output, "Headset Earphone", "headset-out"),
+            device(DeviceType::Input, "Headset Microphone", "headset-in"),
+            device(DeviceType::Input, "Webcam Microphone", "webcam"),
+        ]
+    }
+
+    fn target(config: &Config, desired: &str) -> Config {
+        target_config(config, toml::from_str(desired).unwrap(), &devices()).unwrap()
+    }
+
+    fn resolve(pattern: &str, device_type: DeviceType, current_id: Option<&str>) -> Result<String> {
+        resolve_device(pattern, device_type, &devices(), current_id).map(|it| it.device_id.clone())
+    }
+
+    #[test]
+    fn resolves_the_one_device_matching_the_pattern() {
+        assert_eq!(
+            resolve("Headset Earphone", DeviceType::Output, None).unwrap(),
+            "headset-out"
+        );
+        assert_eq!(
+            resolve("speakers*", DeviceType::Output, None).unwrap(),
+            "speakers"
+        );
+        assert_eq!(
+            resolve("headset*", DeviceType::Input, None).unwrap(),
+            "headset-in"
+        );
+    }
+
+    #[test]
+    fn fails_when_no_device_matches() {
+        let err = resolve("Monitor*", DeviceType::Output, None).unwrap_err();
+        assert_eq!(err.to_string(), "no output device matches \"Monitor*\".");
+    }
+
+    #[test]
+    fn keeps_the_current_device_when_several_match() {
+        let err = resolve("*Microphone", DeviceType::Input, None).unwrap_err();
+        assert!(err.to_string().starts_with("2 input devices match"));
+
+        let current = resolve("*Microphone", DeviceType::Input, Some("webcam")).unwrap();
+        assert_eq!(current, "webcam");
+    }
+
+    #[test]
+    fn binds_the_desired_profiles_to_the_resolved_devices() {
+        let target = target(&Config::default(), HEADSET);
+
+        let binding = target.binding("Headset").unwrap();
+        assert_eq!(binding.output_id, "headset-out");
+        assert_eq!(binding.input_id, "headset-in");
+        assert_eq!(target.profiles[0].hotkey.hotkey, "VK_F1");
+    }
+
+    #[test]
+    fn rejects_a_profile_listed_twice() {
+        let desired = toml::from_str(&format!("{HEADSET}{HEADSET}")).unwrap();
+        assert!(target_config(&Config::default(), desired, &devices()).is_err());
+    }
+
+    #[test]
+    fn plans_to_add_a_profile() {
+        let config = target(&Config::default(), HEADSET);
+        let target = target(&config, &format!("{HEADSET}{SPEAKERS}"));
+
+        let changes = plan(&config, &target);
+        assert!(matches!(changes.as_slice(), [Change::Add(entry)]
+            if entry.profile.profile_name == "Speakers"));
+    }
+
+    #[test]
+    fn plans_to_change_a_profile() {
+        let config = target(&Config::default(), HEADSET);
+        let target = target(&config, &HEADSET.replace("F1", "F3"));
+
+        let changes = plan(&config, &target);
+        assert!(matches!(changes.as_slice(), [Change::Update { after, .. }]
+            if after.profile.hotkey.hotkey == "VK_F3"));
+    }
+
+    #[test]
+    fn plans_to_remove_a_profile() {
+        let config = target(&Config::default(), &format!("{HEADSET}{SPEAKERS}"));
+        let target = target(&config, SPEAKERS);
+
+        let changes = plan(&config, &target);
+        assert!(matches!(changes.as_slice(), [Change::Remove(entry)]
+            if entry.profile.profile_name == "Headset"));
+        assert!(target.binding("Headset").is_none());
+    }
+
+    #[test]
+    fn plans_nothing_when_the_config_is_applied() {
+        let desired = format!("{HEADSET}{SPEAKERS}");
+        let config = target(&Config::default(), &desired);
+
+        assert!(plan(&config, &target(&config, &desired)).is_empty());
+    }
+}
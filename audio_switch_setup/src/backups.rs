@@ -0,0 +1,324 @@
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::Result;
+use inquire::{Confirm, Select};
+
+use crate::config::Config;
+use crate::{migrations, paths};
+
+/// Number of snapshots kept before the oldest is deleted.
+const MAX_BACKUPS: usize = 20;
+
+/// Directory the snapshots are kept in, next to the config file.
+fn backup_dir() -> Result<PathBuf> {
+    Ok(paths::config_path()?.with_file_name("backups"))
+}
+
+/// Copies the config file into the backup directory before it is overwritten. Nothing is copied
+/// if there is no config yet or the newest snapshot already has the same contents.
+pub fn snapshot() -> Result<()> {
+    let path = paths::config_path()?;
+    let Ok(contents) = std::fs::read(&path) else {
+        return Ok(());
+    };
+
+    let dir = backup_dir()?;
+    std::fs::create_dir_all(&dir)?;
+
+    let backups = list(&dir)?;
+    if let Some(newest) = backups.first()
+        && std::fs::read(&newest.path).is_ok_and(|it| it == contents)
+    {
+        return Ok(());
+    }
+
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?;
+    let name = format!(
+        "config-{}-{:03}.toml",
+        timestamp(now.as_secs()),
+        now.subsec_millis()
+    );
+    std::fs::write(dir.join(name), contents)?;
+
+    for old in list(&dir)?.iter().skip(MAX_BACKUPS) {
+        let _ = std::fs::remove_file(&old.path);
+    }
+
+    Ok(())
+}
+
+/// A snapshot in the backup directory.
+struct Backup {
+    path: PathBuf,
+    /// When it was taken, as `YYYYMMDD-HHMMSS` in UTC.
+    taken: String,
+    /// Names of its profiles, `None` if it can't be read.
+    profiles: Option<Vec<String>>,
+}
+
+/// The snapshots in `dir`, newest first.
+fn list(dir: &Path) -> Result<Vec<Backup>> {
+    let mut backups = vec![];
+
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        let Some(taken) = path
+            .file_name()
+            .and_then(|it| it.to_str())
+            .and_then(|it| it.strip_prefix("config-"))
+            .and_then(|it| it.strip_suffix(".toml"))
+            .and_then(|it| it.get(..15))
+        else {
+            continue;
+        };
+
+        backups.push(Backup {
+            taken: taken.to_string(),
+            path,
+            profiles: None,
+        });
+    }
+
+    // The timestamps sort like the file names, which also keep apart snapshots of one second.
+    backups.sort_by(|a, b| b.path.cmp(&a.path));
+    Ok(backups)
+}
+
+impl std::fmt::Display for Backup {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let t = &self.taken;
+        write!(
+            f,
+            "{}-{}-{} {}:{}:{} UTC, ",
+            &t[..4],
+            &t[4..6],
+            &t[6..8],
+            &t[9..11],
+            &t[11..13],
+            &t[13..15]
+        )?;
+
+        match self.profiles.as_deref() {
+            Some([]) => write!(f, "no profiles"),
+            Some([name]) => write!(f, "1 profile: {name}"),
+            Some(names) => write!(f, "{} profiles: {}", names.len(), names.join(", ")),
+            None => write!(f, "unreadable"),
+        }
+    }
+}
+
+/// Formats seconds since the Unix epoch as `YYYYMMDD-HHMMSS` in UTC.
+fn timestamp(secs: u64) -> String {
+    let (days, secs) = (secs / 86400, secs % 86400);
+
+    // Converts days since 1970-01-01 to a civil date, see
+    // https://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{year:04}{month:02}{day:02}-{:02}{:02}{:02}",
+        secs / 3600,
+        secs % 3600 / 60,
+        secs % 60
+    )
+}
+
+/// Lines of `new` that are not in `old` prefixed with `+`, and the other way round with `-`.
+/// The table header above a change is shown with it, changed blank lines are left out.
+fn diff(old: &str, new: &str) -> Vec<String> {
+    let (old, new): (Vec<_>, Vec<_>) = (old.lines().collect(), new.lines().collect());
+
+    // Length of the longest common subsequence of the remaining lines.
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut lines = vec![];
+    // Array tables repeat their header, so headers are told apart by where they are.
+    let mut header = None;
+    let mut shown_header = None;
+
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        let (sign, line) = if i < old.len() && j < new.len() && old[i] == new[j] {
+            i += 1;
+            j += 1;
+            (' ', old[i - 1])
+        } else if i < old.len() && (j == new.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            i += 1;
+            ('-', old[i - 1])
+        } else {
+            j += 1;
+            ('+', new[j - 1])
+        };
+
+        // Blank lines only separate the tables.
+        if sign != ' ' && !line.trim().is_empty() {
+            // An added or removed header is shown as the change itself.
+            if header != shown_header
+                && !line.starts_with('[')
+                && let Some((_, header)) = header
+            {
+                lines.push(format!("  {header}"));
+            }
+            shown_header = header;
+            lines.push(format!("{sign} {line}"));
+        }
+        if line.starts_with('[') {
+            header = Some((i + j, line));
+            if sign != ' ' {
+                shown_header = header;
+            }
+        }
+    }
+
+    lines
+}
+
+/// Lists the snapshots, shows how the chosen one differs from the current config and restores
+/// it. Returns whether the config changed.
+pub fn restore() -> Result<bool> {
+    let dir = backup_dir()?;
+    let mut backups = if dir.exists() { list(&dir)? } else { vec![] };
+    if backups.is_empty() {
+        println!("    There are no backups yet.");
+        return Ok(false);
+    }
+
+    for backup in &mut backups {
+        backup.profiles = migrations::load_file(&backup.path).ok().map(|config| {
+            config
+                .profiles
+                .into_iter()
+                .map(|it| it.profile_name)
+                .collect()
+        });
+    }
+
+    let Some(backup) = Select::new("Restore the configuration from:", backups)
+        .with_help_message("Backups are taken before every change, newest first.")
+        .prompt_skippable()?
+    else {
+        return Ok(false);
+    };
+
+    let restored = migrations::load_file(&backup.path)?;
+    let current: Config = paths::load_config()?;
+
+    // The backup may be from before an uninstall or install, which must not be undone.
+    let restored = Config {
+        installed: current.installed,
+        ..restored
+    };
+
+    let changes = diff(
+        &toml::to_string_pretty(&current)?,
+        &toml::to_string_pretty(&restored)?,
+    );
+    if changes.is_empty() {
+        println!("    The backup is the same as the current configuration.");
+        return Ok(false);
+    }
+
+    println!("    Restoring changes the configuration like this:");
+    for line in &changes {
+        println!("    {line}");
+    }
+
+    if !Confirm::new("Restore this backup? (y/n)").prompt()? {
+        return Ok(false);
+    }
+
+    restored.store()?;
+    println!("    Restoring the Backup... Done ✔");
+
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROFILES: &str = r#"installed = true
+
+[[profiles]]
+profile_name = "a"
+hotkey = "A"
+
+[[profiles]]
+profile_name = "b"
+hotkey = "B"
+"#;
+
+    #[test]
+    fn identical_files_have_no_changes() {
+        assert!(diff(PROFILES, PROFILES).is_empty());
+    }
+
+    #[test]
+    fn shows_the_header_of_each_changed_table() {
+        let new = PROFILES
+            .replace(r#"hotkey = "A""#, r#"hotkey = "F1""#)
+            .replace(r#"hotkey = "B""#, r#"hotkey = "F2""#);
+
+        assert_eq!(
+            diff(PROFILES, &new),
+            [
+                "  [[profiles]]",
+                r#"- hotkey = "A""#,
+                r#"+ hotkey = "F1""#,
+                "  [[profiles]]",
+                r#"- hotkey = "B""#,
+                r#"+ hotkey = "F2""#,
+            ]
+        );
+    }
+
+    #[test]
+    fn shows_the_header_once_for_changes_in_one_table() {
+        let new = PROFILES
+            .replace(r#"profile_name = "b""#, r#"profile_name = "c""#)
+            .replace(r#"hotkey = "B""#, r#"hotkey = "F2""#);
+
+        assert_eq!(
+            diff(PROFILES, &new),
+            [
+                "  [[profiles]]",
+                r#"- profile_name = "b""#,
+                r#"- hotkey = "B""#,
+                r#"+ profile_name = "c""#,
+                r#"+ hotkey = "F2""#,
+            ]
+        );
+    }
+
+    #[test]
+    fn added_and_removed_headers_are_the_change() {
+        let new = format!("{PROFILES}\n[next_profile]\nhotkey = \"N\"\n");
+
+        assert_eq!(
+            diff(PROFILES, &new),
+            ["+ [next_profile]", r#"+ hotkey = "N""#]
+        );
+        assert_eq!(
+            diff(&new, PROFILES),
+            ["- [next_profile]", r#"- hotkey = "N""#]
+        );
+    }
+}
@@ -0,0 +1,717 @@
+use std::path::Path;
+
+use anyhow::{Context, Result, bail};
+use clap::ValueEnum;
+use inquire::{MultiSelect, Select};
+use serde::{Deserialize, Serialize};
+
+use crate::color::Color;
+use crate::config::{Config, HotkeyConfig, Profile};
+use crate::devices::{self, Device, DeviceType, enumerate_devices};
+use crate::hotkeys;
+use crate::paths;
+
+/// Format version of bundles written by this setup.
+pub const BUNDLE_VERSION: u32 = 1;
+
+/// Profiles shared between machines, with the next/previous hotkeys of the exporting machine.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Bundle {
+    pub bundle_version: u32,
+    pub profiles: Vec<BundleProfile>,
+    pub next_profile: Option<HotkeyConfig>,
+    pub previous_profile: Option<HotkeyConfig>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BundleProfile {
+    pub name: String,
+    pub color: Option<Color>,
+    pub hotkey: HotkeyConfig,
+    pub input: DeviceFingerprint,
+    pub output: DeviceFingerprint,
+}
+
+/// Identifies a device on another machine. The endpoint ID only matches on the machine the
+/// bundle was exported from, elsewhere the name, a pattern like the profile's, is used. Both are
+/// empty if the source didn't name a device.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeviceFingerprint {
+    pub name: String,
+    pub id: String,
+}
+
+/// What to do with an imported profile whose name is already used.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum NameConflict {
+    Ask,
+    /// Don't import the profile.
+    Skip,
+    /// Replace the existing profile.
+    Replace,
+    /// Import the profile under a new name.
+    Rename,
+}
+
+/// What to do with an imported hotkey that is already used.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum HotkeyConflict {
+    Ask,
+    /// Don't import the profile or binding.
+    Skip,
+    /// Delete the existing profile that uses the hotkey, or clear the next/previous binding.
+    Replace,
+}
+
+/// What to do with a profile whose device isn't found on this machine.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum MissingDevice {
+    /// Let the user pick a local device.
+    Ask,
+    /// Don't import the profile.
+    Skip,
+}
+
+/// How [`import`] resolves conflicts without asking.
+#[derive(Debug, Clone, Copy)]
+pub struct ImportPolicy {
+    pub on_name_conflict: NameConflict,
+    pub on_hotkey_conflict: HotkeyConflict,
+    pub on_missing_device: MissingDevice,
+}
+
+impl Default for ImportPolicy {
+    fn default() -> Self {
+        Self {
+            on_name_conflict: NameConflict::Ask,
+            on_hotkey_conflict: HotkeyConflict::Ask,
+            on_missing_device: MissingDevice::Ask,
+        }
+    }
+}
+
+/// Writes the profiles called `names` to a bundle at `path`. Asks which profiles to export if
+/// `names` is empty.
+pub fn export(path: &Path, names: &[String]) -> Result<()> {
+    let config: Config = paths::load_config()?;
+
+    let names = if names.is_empty() {
+        MultiSelect::new(
+            "Select the profiles to export:",
+            config
+                .profiles
+                .iter()
+                .map(|it| it.profile_name.clone())
+                .collect(),
+        )
+        .prompt()?
+    } else {
+        names.to_vec()
+    };
+
+    if let Some(unknown) = names
+        .iter()
+        .find(|name| !config.profiles.iter().any(|it| &it.profile_name == *name))
+    {
+        bail!("There is no profile named \"{unknown}\".");
+    }
+
+    let bundle = Bundle {
+        bundle_version: BUNDLE_VERSION,
+        profiles: config
+            .profiles
+            .iter()
+            .filter(|it| names.contains(&it.profile_name))
+            .map(|it| {
+                let binding = config.binding(&it.profile_name);
+                BundleProfile {
+                    name: it.profile_name.clone(),
+                    color: it.color,
+                    hotkey: it.hotkey.clone(),
+                    input: DeviceFingerprint {
+                        name: it.input_pattern.clone(),
+                        id: binding.map(|it| it.input_id.clone()).unwrap_or_default(),
+                    },
+                    output: DeviceFingerprint {
+                        name: it.output_pattern.clone(),
+                        id: binding.map(|it| it.output_id.clone()).unwrap_or_default(),
+                    },
+                }
+            })
+            .collect(),
+        next_profile: config.next_profile,
+        previous_profile: config.previous_profile,
+    };
+
+    std::fs::write(path, toml::to_string_pretty(&bundle)?)?;
+
+    println!(
+        "    Exported {} profiles to {}... Done ✔",
+        bundle.profiles.len(),
+        path.display()
+    );
+
+    Ok(())
+}
+
+/// Adds the profiles of the bundle at `path`, matched to the devices of this machine.
+pub fn import(path: &Path, policy: ImportPolicy) -> Result<()> {
+    let content = std::fs::read_to_string(path)?;
+    let bundle: Bundle =
+        toml::from_str(&content).with_context(|| format!("Failed to read {}.", path.display()))?;
+
+    if bundle.bundle_version > BUNDLE_VERSION {
+        bail!("The bundle was written by a newer version of AudioSwitch.");
+    }
+
+    import_bundle(bundle, policy)
+}
+
+/// Adds the profiles of `bundle`, matched to the devices of this machine.
+pub fn import_bundle(bundle: Bundle, policy: ImportPolicy) -> Result<()> {
+    let devices = enumerate_devices()?;
+    let mut config: Config = paths::load_config()?;
+    let mut imported = 0;
+
+    for profile in bundle.profiles {
+        let Some(output) = resolve_device(&profile.output, DeviceType::Output, &devices, policy)?
+        else {
+            println!(
+                "    Skipped profile {}: output device \"{}\" not found.",
+                profile.name, profile.output.name
+            );
+            continue;
+        };
+        let Some(input) = resolve_device(&profile.input, DeviceType::Input, &devices, policy)?
+        else {
+            println!(
+                "    Skipped profile {}: input device \"{}\" not found.",
+                profile.name, profile.input.name
+            );
+            continue;
+        };
+
+        // Resolving the name may already remove a profile it replaces, which must be undone if
+        // the hotkey conflict then skips the import.
+        let before = config.clone();
+
+        let Some(name) = resolve_name(&mut config, &profile.name, policy)? else {
+            println!("    Skipped profile {}: name already used.", profile.name);
+            continue;
+        };
+
+        let Some(hotkey) = resolve_hotkey(&mut config, &profile.hotkey, &name, policy)? else {
+            config = before;
+            println!(
+                "    Skipped profile {name}: hotkey {} already used.",
+                profile.hotkey
+            );
+            continue;
+        };
+
+        // A profile without a device in the source gets the chosen one as its pattern.
+        let pattern = |fingerprint: &DeviceFingerprint, device: &Device| {
+            if fingerprint.name.is_empty() {
+                device.name.clone()
+            } else {
+                fingerprint.name.clone()
+            }
+        };
+
+        let mut new_profile = Profile::default()
+            .set_profile_id(0)
+            .set_profile_name(&name)
+            .set_input_pattern(&pattern(&profile.input, input))
+            .set_output_pattern(&pattern(&profile.output, output))
+            .set_hotkey(hotkey.modifier, hotkey.hotkey);
+        new_profile.color = profile.color;
+
+        config.profiles.push(new_profile);
+        config.bind(&name, devices::binding(input, output));
+        imported += 1;
+    }
+
+    for (binding, hotkey) in [
+        (Binding::Next, bundle.next_profile),
+        (Binding::Previous, bundle.previous_profile),
+    ] {
+        if let Some(hotkey) = hotkey {
+            import_binding(&mut config, binding, hotkey, policy)?;
+        }
+    }
+
+    config.store()?;
+
+    println!("    Imported {imported} profiles... Done ✔");
+
+    Ok(())
+}
+
+/// Finds the local device a fingerprint refers to, by endpoint ID first, then by name and then by
+/// name pattern.
+fn resolve_device<'a>(
+    fingerprint: &DeviceFingerprint,
+    device_type: DeviceType,
+    devices: &'a [Device],
+    policy: ImportPolicy,
+) -> Result<Option<&'a Device>> {
+    let candidates: Vec<&Device> = devices
+        .iter()
+        .filter(|it| it.device_type == device_type)
+        .collect();
+
+    let found = candidates
+        .iter()
+        .find(|it| it.device_id == fingerprint.id)
+        .or_else(|| candidates.iter().find(|it| it.name == fingerprint.name))
+        .or_else(|| {
+            candidates
+                .iter()
+                .find(|it| it.name.eq_ignore_ascii_case(&fingerprint.name))
+        })
+        .or_else(|| {
+            candidates
+                .iter()
+                .find(|it| devices::matches_pattern(&fingerprint.name, &it.name))
+        });
+
+    if let Some(device) = found {
+        return Ok(Some(device));
+    }
+
+    if policy.on_missing_device == MissingDevice::Skip || candidates.is_empty() {
+        return Ok(None);
+    }
+
+    let message = if fingerprint.name.is_empty() {
+        format!("Select the {device_type} device:")
+    } else {
+        format!(
+            "Device \"{}\" was not found. Select a replacement:",
+            fingerprint.name
+        )
+    };
+
+    Ok(Select::new(&message, candidates).prompt_skippable()?)
+}
+
+/// Returns the name to import a profile under, `None` to skip it.
+fn resolve_name(config: &mut Config, name: &str, policy: ImportPolicy) -> Result<Option<String>> {
+    if !config.profiles.iter().any(|it| it.profile_name == name) {
+        return Ok(Some(name.to_string()));
+    }
+
+    let renamed = (2..)
+        .map(|n| format!("{name} ({n})"))
+        .find(|candidate| {
+            !config
+                .profiles
+                .iter()
+                .any(|it| &it.profile_name == candidate)
+        })
+        .expect("Ran out of profile names.");
+
+    let resolution = match policy.on_name_conflict {
+        NameConflict::Ask => {
+            let options = vec![
+                "Skip this profile.".to_string(),
+                "Replace the existing profile.".to_string(),
+                format!("Import it as \"{renamed}\"."),
+            ];
+            let choice = Select::new(
+                &format!("A profile named \"{name}\" already exists:"),
+                options.clone(),
+            )
+            .prompt()?;
+
+            [
+                NameConflict::Skip,
+                NameConflict::Replace,
+                NameConflict::Rename,
+            ][options.iter().position(|it| *it == choice).unwrap_or(0)]
+        }
+        resolution => resolution,
+    };
+
+    Ok(match resolution {
+        NameConflict::Replace => {
+            config.remove_profile(name);
+            Some(name.to_string())
+        }
+        NameConflict::Rename => Some(renamed),
+        _ => None,
+    })
+}
+
+/// Who already uses a hotkey.
+enum HotkeyHolder {
+    Profile(usize),
+    Next,
+    Previous,
+}
+
+impl HotkeyHolder {
+    fn find(config: &Config, hotkey: &HotkeyConfig) -> Option<Self> {
+        if let Some(index) = config.profiles.iter().position(|it| it.hotkey == *hotkey) {
+            return Some(HotkeyHolder::Profile(index));
+        }
+        if config.next_profile.as_ref() == Some(hotkey) {
+            return Some(HotkeyHolder::Next);
+        }
+        if config.previous_profile.as_ref() == Some(hotkey) {
+            return Some(HotkeyHolder::Previous);
+        }
+
+        None
+    }
+
+    fn describe(&self, config: &Config) -> String {
+        match self {
+            HotkeyHolder::Profile(index) => {
+                format!("profile \"{}\"", config.profiles[*index].profile_name)
+            }
+            HotkeyHolder::Next => "the next profile switch".to_string(),
+            HotkeyHolder::Previous => "the previous profile switch".to_string(),
+        }
+    }
+
+    /// What [`HotkeyHolder::remove`] does, to confirm it. A profile can't exist without its
+    /// hotkey, so it is deleted.
+    fn describe_removal(&self, config: &Config) -> String {
+        match self {
+            HotkeyHolder::Profile(_) => format!("delete {}", self.describe(config)),
+            HotkeyHolder::Next | HotkeyHolder::Previous => {
+                format!("clear {}", self.describe(config))
+            }
+        }
+    }
+
+    fn remove(self, config: &mut Config) {
+        match self {
+            HotkeyHolder::Profile(index) => {
+                let name = config.profiles[index].profile_name.clone();
+                config.remove_profile(&name);
+            }
+            HotkeyHolder::Next => config.next_profile = None,
+            HotkeyHolder::Previous => config.previous_profile = None,
+        }
+    }
+}
+
+/// Returns the hotkey to import profile `name` with, `None` to skip it.
+fn resolve_hotkey(
+    config: &mut Config,
+    hotkey: &HotkeyConfig,
+    name: &str,
+    policy: ImportPolicy,
+) -> Result<Option<HotkeyConfig>> {
+    let Some(holder) = HotkeyHolder::find(config, hotkey) else {
+        return Ok(Some(hotkey.clone()));
+    };
+
+    match policy.on_hotkey_conflict {
+        HotkeyConflict::Skip => Ok(None),
+        HotkeyConflict::Replace => {
+            holder.remove(config);
+            Ok(Some(hotkey.clone()))
+        }
+        HotkeyConflict::Ask => {
+            let holder_name = holder.describe(config);
+            let options = vec![
+                "Skip this profile.".to_string(),
+                "Choose another hotkey.".to_string(),
+                format!("Import it and {}.", holder.describe_removal(config)),
+            ];
+            let choice = Select::new(
+                &format!("Hotkey {hotkey} of profile \"{name}\" is used by {holder_name}:"),
+                options.clone(),
+            )
+            .prompt()?;
+
+            if choice == options[1] {
+                return choose_free_hotkey(config).map(Some);
+            }
+            if choice == options[2] {
+                holder.remove(config);
+                return Ok(Some(hotkey.clone()));
+            }
+
+            Ok(None)
+        }
+    }
+}
+
+/// The hotkeys that cycle through the profiles.
+#[derive(Debug, Clone, Copy)]
+pub enum Binding {
+    Next,
+    Previous,
+}
+
+impl Binding {
+    pub fn slot(self, config: &mut Config) -> &mut Option<HotkeyConfig> {
+        match self {
+            Binding::Next => &mut config.next_profile,
+            Binding::Previous => &mut config.previous_profile,
+        }
+    }
+}
+
+impl std::fmt::Display for Binding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Binding::Next => write!(f, "next profile"),
+            Binding::Previous => write!(f, "previous profile"),
+        }
+    }
+}
+
+/// Imports a next/previous binding unless it conflicts and the policy says to keep the
+/// existing one.
+fn import_binding(
+    config: &mut Config,
+    binding: Binding,
+    hotkey: HotkeyConfig,
+    policy: ImportPolicy,
+) -> Result<()> {
+    let current = binding.slot(config).clone();
+
+    if current.as_ref() == Some(&hotkey) {
+        return Ok(());
+    }
+
+    let holder = HotkeyHolder::find(config, &hotkey);
+    let replace = match (current, policy.on_hotkey_conflict) {
+        (None, _) if holder.is_none() => true,
+        (_, HotkeyConflict::Skip) => false,
+        (_, HotkeyConflict::Replace) => true,
+        (_, HotkeyConflict::Ask) => inquire::Confirm::new(&format!(
+            "Use {hotkey} for the {binding} switch{}? (y/n)",
+            holder
+                .as_ref()
+                .map(|it| format!(" and {}", it.describe_removal(config)))
+                .unwrap_or_default()
+        ))
+        .prompt()?,
+    };
+
+    if !replace {
+        println!("    Skipped the {binding} binding {hotkey}.");
+        return Ok(());
+    }
+
+    if let Some(holder) = holder {
+        holder.remove(config);
+    }
+
+    *binding.slot(config) = Some(hotkey);
+
+    Ok(())
+}
+
+fn choose_free_hotkey(config: &Config) -> Result<HotkeyConfig> {
+    loop {
+        let hotkey = hotkeys::create_hotkey()?;
+        let hotkey = HotkeyConfig {
+            modifier: hotkey.modifier.map(|it| it.to_string()),
+            hotkey: hotkey.main_key.to_string(),
+        };
+
+        match HotkeyHolder::find(config, &hotkey) {
+            Some(holder) => println!(
+                "Hotkey: {hotkey} already in use for {}.",
+                holder.describe(config)
+            ),
+            None => return Ok(hotkey),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Resolves every conflict without asking.
+    fn policy(on_name_conflict: NameConflict, on_hotkey_conflict: HotkeyConflict) -> ImportPolicy {
+        ImportPolicy {
+            on_name_conflict,
+            on_hotkey_conflict,
+            on_missing_device: MissingDevice::Skip,
+        }
+    }
+
+    fn device(device_type: DeviceType, name: &str, device_id: &str) -> Device {
+        Device {
+            device_type,
+            name: name.to_string(),
+            device_id: device_id.to_string(),
+        }
+    }
+
+    fn fingerprint(name: &str, id: &str) -> DeviceFingerprint {
+        DeviceFingerprint {
+            name: name.to_string(),
+            id: id.to_string(),
+        }
+    }
+
+    fn hotkey(key: &str) -> HotkeyConfig {
+        HotkeyConfig {
+            modifier: Some("ALT".to_string()),
+            hotkey: key.to_string(),
+        }
+    }
+
+    fn config(profiles: &[(&str, &str)]) -> Config {
+        Config {
+            profiles: profiles
+                .iter()
+                .map(|(name, key)| {
+                    Profile::default()
+                        .set_profile_name(name)
+                        .set_hotkey(Some("ALT".to_string()), key.to_string())
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    fn names(config: &Config) -> Vec<&str> {
+        config
+            .profiles
+            .iter()
+            .map(|it| it.profile_name.as_str())
+            .collect()
+    }
+
+    fn resolve<'a>(fingerprint: &DeviceFingerprint, devices: &'a [Device]) -> Option<&'a str> {
+        let policy = policy(NameConflict::Skip, HotkeyConflict::Skip);
+        resolve_device(fingerprint, DeviceType::Output, devices, policy)
+            .unwrap()
+            .map(|it| it.device_id.as_str())
+    }
+
+    #[test]
+    fn resolves_a_device_by_id_first() {
+        let devices = [
+            device(DeviceType::Output, "Speakers", "speakers"),
+            device(DeviceType::Output, "Speakers (2)", "renamed"),
+        ];
+
+        assert_eq!(
+            resolve(&fingerprint("Speakers", "renamed"), &devices),
+            Some("renamed")
+        );
+    }
+
+    #[test]
+    fn resolves_a_device_by_name_then_by_pattern() {
+        let devices = [
+            device(DeviceType::Output, "Headset Earphone", "headset"),
+            device(DeviceType::Output, "Speakers", "speakers"),
+            device(DeviceType::Output, "speakers", "lowercase"),
+        ];
+
+        assert_eq!(
+            resolve(&fingerprint("speakers", "other"), &devices),
+            Some("lowercase")
+        );
+        assert_eq!(
+            resolve(&fingerprint("SPEAKERS", ""), &devices),
+            Some("speakers")
+        );
+        assert_eq!(
+            resolve(&fingerprint("Headset*", ""), &devices),
+            Some("headset")
+        );
+    }
+
+    #[test]
+    fn resolves_only_devices_of_the_type() {
+        let devices = [device(DeviceType::Input, "Speakers", "speakers")];
+
+        assert_eq!(
+            resolve(&fingerprint("Speakers", "speakers"), &devices),
+            None
+        );
+    }
+
+    #[test]
+    fn skips_a_missing_device() {
+        let devices = [device(DeviceType::Output, "Speakers", "speakers")];
+
+        assert_eq!(resolve(&fingerprint("Headset", "headset"), &devices), None);
+    }
+
+    #[test]
+    fn keeps_an_unused_name() {
+        let mut config = config(&[("Headset", "F1")]);
+        let policy = policy(NameConflict::Skip, HotkeyConflict::Skip);
+
+        let name = resolve_name(&mut config, "Speakers", policy).unwrap();
+        assert_eq!(name.as_deref(), Some("Speakers"));
+    }
+
+    #[test]
+    fn resolves_a_used_name_by_the_policy() {
+        let mut config = config(&[("Headset", "F1"), ("Headset (2)", "F2")]);
+
+        let skip = policy(NameConflict::Skip, HotkeyConflict::Skip);
+        assert_eq!(resolve_name(&mut config, "Headset", skip).unwrap(), None);
+
+        let rename = policy(NameConflict::Rename, HotkeyConflict::Skip);
+        let name = resolve_name(&mut config, "Headset", rename).unwrap();
+        assert_eq!(name.as_deref(), Some("Headset (3)"));
+        assert_eq!(names(&config), ["Headset", "Headset (2)"]);
+
+        let replace = policy(NameConflict::Replace, HotkeyConflict::Skip);
+        let name = resolve_name(&mut config, "Headset", replace).unwrap();
+        assert_eq!(name.as_deref(), Some("Headset"));
+        assert_eq!(names(&config), ["Headset (2)"]);
+    }
+
+    #[test]
+    fn finds_who_holds_a_hotkey() {
+        let mut config = config(&[("Headset", "F1"), ("Speakers", "F2")]);
+        config.next_profile = Some(hotkey("F3"));
+        config.previous_profile = Some(hotkey("F4"));
+
+        let describe =
+            |key: &str| HotkeyHolder::find(&config, &hotkey(key)).map(|it| it.describe(&config));
+        assert_eq!(describe("F2").as_deref(), Some("profile \"Speakers\""));
+        assert_eq!(describe("F3").as_deref(), Some("the next profile switch"));
+        assert_eq!(
+            describe("F4").as_deref(),
+            Some("the previous profile switch")
+        );
+        assert_eq!(describe("F5"), None);
+
+        let removal = |key: &str| {
+            HotkeyHolder::find(&config, &hotkey(key)).map(|it| it.describe_removal(&config))
+        };
+        assert_eq!(removal("F1").as_deref(), Some("delete profile \"Headset\""));
+        assert_eq!(
+            removal("F3").as_deref(),
+            Some("clear the next profile switch")
+        );
+    }
+
+    #[test]
+    fn resolves_a_used_hotkey_by_the_policy() {
+        let mut config = config(&[("Headset", "F1")]);
+        config.next_profile = Some(hotkey("F2"));
+
+        let skip = policy(NameConflict::Skip, HotkeyConflict::Skip);
+        let resolved = resolve_hotkey(&mut config, &hotkey("F2"), "Speakers", skip).unwrap();
+        assert_eq!(resolved, None);
+        assert_eq!(config.next_profile, Some(hotkey("F2")));
+
+        let replace = policy(NameConflict::Skip, HotkeyConflict::Replace);
+        let resolved = resolve_hotkey(&mut config, &hotkey("F2"), "Speakers", replace).unwrap();
+        assert_eq!(resolved, Some(hotkey("F2")));
+        assert_eq!(config.next_profile, None);
+
+        let resolved = resolve_hotkey(&mut config, &hotkey("F3"), "Speakers", skip).unwrap();
+        assert_eq!(resolved, Some(hotkey("F3")));
+    }
+}
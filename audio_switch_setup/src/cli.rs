@@ -0,0 +1,149 @@
+use std::path::PathBuf;
+
+use clap::{Args, Parser, Subcommand};
+
+use crate::bundle::{HotkeyConflict, ImportPolicy, MissingDevice, NameConflict};
+use crate::config_export::ConfigChoice;
+
+/// Installs and configures AudioSwitch. Without a command, the interactive menu is shown.
+#[derive(Debug, Parser)]
+#[command(name = "AudioSwitchSetup", version)]
+pub struct Cli {
+    /// Allow installing over a newer version.
+    #[arg(long, global = true)]
+    pub force: bool,
+
+    /// Use this config file instead of the default one, also for the installed service. Can
+    /// also be set with AUDIOSWITCH_CONFIG.
+    #[arg(long, global = true, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+
+    #[command(subcommand)]
+    pub command: Option<CliCommand>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum CliCommand {
+    /// Install AudioSwitch, or upgrade an existing installation.
+    Install {
+        /// Use the profiles and settings of an exported configuration.
+        #[arg(long, value_name = "PATH")]
+        import_config: Option<PathBuf>,
+    },
+    /// Uninstall AudioSwitch without asking.
+    Uninstall(UninstallArgs),
+    /// Check the installation and offer to repair problems.
+    #[command(alias = "repair")]
+    Doctor,
+    /// Share profiles with other machines.
+    #[command(subcommand)]
+    Profile(ProfileCommand),
+    /// Import the profiles of another audio switcher.
+    ImportFrom {
+        /// Format of the settings, e.g. soundswitch.
+        format: String,
+        /// Settings file, the program's default location when omitted.
+        path: Option<PathBuf>,
+        #[command(flatten)]
+        policy: ImportArgs,
+    },
+    /// Make the profiles match a desired profile file, showing the planned changes first.
+    Apply {
+        /// TOML file listing every profile, with device name patterns like "*Jabra*".
+        path: PathBuf,
+        /// Only show the plan.
+        #[arg(long)]
+        dry_run: bool,
+        /// Apply without asking.
+        #[arg(long, short)]
+        yes: bool,
+    },
+    /// Sync the profiles through a folder shared with other machines, e.g. in OneDrive.
+    Sync {
+        /// Folder to sync through from now on. Syncs with the current folder when omitted.
+        #[arg(conflicts_with = "stop")]
+        folder: Option<PathBuf>,
+        /// Stop syncing, the profiles on this machine are kept.
+        #[arg(long)]
+        stop: bool,
+    },
+    /// Print the JSON Schema of the config file.
+    Schema,
+    /// Work with config files.
+    #[command(subcommand)]
+    Config(ConfigCommand),
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ConfigCommand {
+    /// Check a config file against the schema, reporting problems with line and column.
+    Lint {
+        /// Config file to check, the current one when omitted.
+        path: Option<PathBuf>,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ProfileCommand {
+    /// Write profiles and the next/previous hotkeys to a bundle file.
+    Export {
+        path: PathBuf,
+        /// Profile to export, can be repeated. Asks when omitted.
+        #[arg(long = "profile", value_name = "NAME")]
+        profiles: Vec<String>,
+    },
+    /// Add the profiles of a bundle file, matched to this machine's devices.
+    Import {
+        path: PathBuf,
+        #[command(flatten)]
+        policy: ImportArgs,
+    },
+}
+
+#[derive(Debug, Args)]
+pub struct ImportArgs {
+    /// What to do when a profile name is already used.
+    #[arg(long, value_enum, default_value_t = NameConflict::Ask)]
+    on_name_conflict: NameConflict,
+    /// What to do when a hotkey is already used.
+    #[arg(long, value_enum, default_value_t = HotkeyConflict::Ask)]
+    on_hotkey_conflict: HotkeyConflict,
+    /// What to do when a device isn't found on this machine.
+    #[arg(long, value_enum, default_value_t = MissingDevice::Ask)]
+    on_missing_device: MissingDevice,
+}
+
+impl ImportArgs {
+    pub fn policy(&self) -> ImportPolicy {
+        ImportPolicy {
+            on_name_conflict: self.on_name_conflict,
+            on_hotkey_conflict: self.on_hotkey_conflict,
+            on_missing_device: self.on_missing_device,
+        }
+    }
+}
+
+/// What happens to the configuration on uninstall.
+#[derive(Debug, Args)]
+#[group(required = true, multiple = false)]
+pub struct UninstallArgs {
+    /// Leave the configuration in place for a later install.
+    #[arg(long)]
+    keep_config: bool,
+    /// Save the configuration to PATH before removing it.
+    #[arg(long, value_name = "PATH")]
+    export_config: Option<PathBuf>,
+    /// Remove the configuration.
+    #[arg(long)]
+    delete_config: bool,
+}
+
+impl UninstallArgs {
+    pub fn config_choice(&self) -> ConfigChoice {
+        match &self.export_config {
+            Some(path) => ConfigChoice::Export(path.clone()),
+            None if self.keep_config => ConfigChoice::Keep,
+            None => ConfigChoice::Delete,
+        }
+    }
+}
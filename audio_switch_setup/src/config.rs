@@ -1,27 +1,177 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-#[derive(Default, Clone, Debug, Serialize, Deserialize)]
+use crate::color::Color;
+use crate::schema::{KeyName, ModifierName};
+use crate::{backups, paths};
+
+#[derive(Default, Clone, Debug, Serialize, Deserialize, JsonSchema)]
+#[schemars(deny_unknown_fields)]
 pub struct Config {
     pub installed: bool,
+    /// Format version of this file, raised by the setup's migrations.
+    #[serde(default)]
+    pub config_version: u32,
     pub profiles: Vec<Profile>,
     pub next_profile: Option<HotkeyConfig>,
     pub previous_profile: Option<HotkeyConfig>,
+    #[serde(default)]
+    pub taskbar_theme: TaskbarTheme,
+    #[serde(default)]
+    pub notifications: NotificationConfig,
+    #[serde(default)]
+    pub log_level: LogLevel,
+    /// The devices of each profile, keyed by hostname and then profile name.
+    #[serde(default)]
+    pub bindings: BTreeMap<String, BTreeMap<String, DeviceBinding>>,
+    /// Folder shared with other machines, e.g. in OneDrive, to sync the profiles through.
+    #[serde(default)]
+    pub sync_dir: Option<PathBuf>,
+}
+
+impl Config {
+    /// Writes the config file, see [`paths::config_path`]. The previous one is kept as backup.
+    /// The file is written next to it and renamed over it, so the service never reads half of
+    /// it.
+    pub fn store(&self) -> Result<()> {
+        backups::snapshot()?;
+        self.write_to(&paths::config_path()?)
+    }
+
+    /// Writes the config to `path` like [`Config::store`], without a backup.
+    pub fn write_to(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+
+        let staging = path.with_extension("toml.new");
+        std::fs::write(&staging, toml::to_string_pretty(self)?)?;
+        std::fs::rename(&staging, path)?;
+
+        Ok(())
+    }
+
+    /// The devices of profile `name` on this machine.
+    pub fn binding(&self, name: &str) -> Option<&DeviceBinding> {
+        self.bindings.get(&hostname())?.get(name)
+    }
+
+    /// Binds profile `name` to `binding` on this machine.
+    pub fn bind(&mut self, name: &str, binding: DeviceBinding) {
+        self.bindings
+            .entry(hostname())
+            .or_default()
+            .insert(name.to_string(), binding);
+    }
+
+    /// Removes profile `name` together with its bindings on all machines.
+    pub fn remove_profile(&mut self, name: &str) {
+        self.profiles.retain(|it| it.profile_name != name);
+        for bindings in self.bindings.values_mut() {
+            bindings.remove(name);
+        }
+    }
+}
+
+/// Name of this machine, which keys its bindings.
+pub fn hostname() -> String {
+    std::env::var("COMPUTERNAME").unwrap_or_default()
 }
-#[derive(Default, Clone, Debug, Serialize, Deserialize)]
+
+/// Taskbar theme the tray icon is adjusted for. `Auto` follows the Windows setting.
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum TaskbarTheme {
+    #[default]
+    Auto,
+    Light,
+    Dark,
+}
+
+/// The most verbose level the service writes to its log file.
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+    Error,
+    Warn,
+    #[default]
+    Info,
+    Debug,
+    Trace,
+}
+
+/// When and how profile switches are announced.
+///
+/// Titles and bodies are templates, `{profile}`, `{input}` and `{output}` are replaced with the
+/// profile name and its device names.
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+#[schemars(deny_unknown_fields)]
+pub struct NotificationConfig {
+    pub on_success: bool,
+    pub on_failure: bool,
+    pub duration: NotificationDuration,
+    pub success_title: String,
+    pub success_body: String,
+    pub failure_title: String,
+    pub failure_body: String,
+}
+
+impl Default for NotificationConfig {
+    fn default() -> Self {
+        Self {
+            on_success: true,
+            on_failure: true,
+            duration: NotificationDuration::Short,
+            success_title: "Activated Profile {profile}".to_string(),
+            success_body: "{output} / {input}".to_string(),
+            failure_title: "Failed to activate Profile {profile}".to_string(),
+            failure_body: String::new(),
+        }
+    }
+}
+
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum NotificationDuration {
+    #[default]
+    Short,
+    Long,
+}
+
+/// A profile as it is shared between machines. The devices are name patterns, where `*` matches
+/// any text and `?` any character, which are bound to the endpoints of each machine.
+#[derive(Default, Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[schemars(deny_unknown_fields)]
 pub struct Profile {
     pub profile_id: u64,
     pub profile_name: String,
+    pub input_pattern: String,
+    pub output_pattern: String,
+    pub hotkey: HotkeyConfig,
+    pub color: Option<Color>,
+}
+
+/// The endpoints a profile uses on one machine.
+#[derive(Default, Clone, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[schemars(deny_unknown_fields)]
+pub struct DeviceBinding {
     pub input_id: String,
     pub input_name: String,
     pub output_id: String,
     pub output_name: String,
-    pub hotkey: HotkeyConfig,
-    pub color: Option<String>,
 }
 
-#[derive(Default, Clone, Debug, Serialize, Deserialize)]
+#[derive(Default, Clone, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[schemars(deny_unknown_fields)]
 pub struct HotkeyConfig {
+    #[schemars(with = "Option<ModifierName>")]
     pub modifier: Option<String>,
+    #[schemars(with = "KeyName")]
     pub hotkey: String,
 }
 
@@ -30,11 +180,10 @@ impl std::fmt::Display for HotkeyConfig {
         write!(
             f,
             "{}{}",
-            &self
-                .modifier
+            self.modifier
                 .as_ref()
-                .map_or_else(|| "".to_string(), |i| format!("{}+", i.to_string())),
-            &self.hotkey
+                .map_or_else(|| "".to_string(), |i| format!("{}+", i)),
+            self.hotkey
         )
     }
 }
@@ -44,7 +193,7 @@ impl std::fmt::Display for Profile {
         write!(
             f,
             "Profile {}:\nInput Device: {}\nOutput Device: {}\nHotkey: {}\n",
-            self.profile_name, self.input_name, self.output_name, self.hotkey
+            self.profile_name, self.input_pattern, self.output_pattern, self.hotkey
         )
     }
 }
@@ -60,15 +209,13 @@ impl Profile {
         self
     }
 
-    pub fn set_input_device(mut self, id: &str, name: &str) -> Self {
-        self.input_id = id.to_string();
-        self.input_name = name.to_string();
+    pub fn set_input_pattern(mut self, pattern: &str) -> Self {
+        self.input_pattern = pattern.to_string();
         self
     }
 
-    pub fn set_output_device(mut self, id: &str, name: &str) -> Self {
-        self.output_id = id.to_string();
-        self.output_name = name.to_string();
+    pub fn set_output_pattern(mut self, pattern: &str) -> Self {
+        self.output_pattern = pattern.to_string();
         self
     }
 
@@ -78,7 +225,7 @@ impl Profile {
         self
     }
 
-    pub fn set_profile_color(mut self, color: String) -> Self {
+    pub fn set_profile_color(mut self, color: Color) -> Self {
         self.color = Some(color);
         self
     }
@@ -0,0 +1,107 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use inquire::{Select, Text};
+
+use crate::config::Config;
+use crate::migrations;
+
+/// File name an uninstall suggests for exports, and a later install looks for.
+const EXPORT_FILE_NAME: &str = "AudioSwitch Config.toml";
+
+/// What happens to the configuration on uninstall.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigChoice {
+    /// Leave it in place, a later install picks it up.
+    Keep,
+    /// Save it to a file, then remove it.
+    Export(PathBuf),
+    Delete,
+}
+
+/// Asks what to do with the configuration on uninstall.
+pub fn ask_choice() -> Result<ConfigChoice> {
+    let options = vec![
+        "Keep them for a later installation.",
+        "Export them to a file.",
+        "Delete them.",
+    ];
+
+    let choice = Select::new(
+        "What should happen to your profiles and settings?",
+        options.clone(),
+    )
+    .prompt()?;
+
+    if choice == options[0] {
+        return Ok(ConfigChoice::Keep);
+    }
+    if choice == options[2] {
+        return Ok(ConfigChoice::Delete);
+    }
+
+    let mut prompt = Text::new("Export to:");
+    let default = default_export_path().map(|it| it.display().to_string());
+    if let Some(default) = &default {
+        prompt = prompt.with_default(default);
+    }
+
+    Ok(ConfigChoice::Export(PathBuf::from(prompt.prompt()?)))
+}
+
+/// Contents of an export of `config`, which a later install imports as not installed.
+pub fn export_contents(config: &Config) -> Result<String> {
+    let config = Config {
+        installed: false,
+        ..config.clone()
+    };
+
+    Ok(toml::to_string_pretty(&config)?)
+}
+
+/// Reads an exported configuration.
+pub fn import(path: &Path) -> Result<Config> {
+    let config = migrations::load_file(path)?;
+
+    Ok(Config {
+        installed: false,
+        ..config
+    })
+}
+
+/// Offers to install with the configuration an earlier uninstall kept, or with an export in
+/// the default location. Returns the configuration to install with.
+pub fn offer_import(kept: Config) -> Result<Config> {
+    if !kept.profiles.is_empty() {
+        if inquire::Confirm::new(&format!(
+            "Found the configuration of an earlier installation with {} profiles. Use it? (y/n)",
+            kept.profiles.len()
+        ))
+        .prompt()?
+        {
+            return Ok(kept);
+        }
+
+        return Ok(Config::default());
+    }
+
+    if let Some(path) = default_export_path().filter(|it| it.exists())
+        && inquire::Confirm::new(&format!(
+            "Found an exported configuration at {}. Import it? (y/n)",
+            path.display()
+        ))
+        .prompt()?
+    {
+        return import(&path);
+    }
+
+    Ok(kept)
+}
+
+fn default_export_path() -> Option<PathBuf> {
+    Some(
+        directories::UserDirs::new()?
+            .document_dir()?
+            .join(EXPORT_FILE_NAME),
+    )
+}
@@ -1,5 +1,7 @@
 use anyhow::Result;
 
+use crate::config::DeviceBinding;
+
 use windows::Win32::Devices::FunctionDiscovery::PKEY_Device_FriendlyName;
 use windows::Win32::Media::Audio::{
     DEVICE_STATE_ACTIVE, IMMDeviceEnumerator, MMDeviceEnumerator, eCapture, eRender,
@@ -8,7 +10,7 @@ use windows::Win32::System::Com::{
     CLSCTX_ALL, COINIT_MULTITHREADED, CoCreateInstance, CoInitializeEx, CoUninitialize, STGM_READ,
 };
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum DeviceType {
     Output,
     Input,
@@ -31,12 +33,65 @@ impl Device {
     }
 }
 
+impl std::fmt::Display for DeviceType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DeviceType::Output => write!(f, "output"),
+            DeviceType::Input => write!(f, "input"),
+        }
+    }
+}
+
 impl std::fmt::Display for Device {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", self.name)
     }
 }
 
+/// Binds a profile to `input` and `output`.
+pub fn binding(input: &Device, output: &Device) -> DeviceBinding {
+    DeviceBinding {
+        input_id: input.device_id.clone(),
+        input_name: input.name.clone(),
+        output_id: output.device_id.clone(),
+        output_name: output.name.clone(),
+    }
+}
+
+/// Matches a device name against a pattern where `*` stands for any text and `?` for one
+/// character, ignoring case.
+pub fn matches_pattern(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+    let text: Vec<char> = name.to_lowercase().chars().collect();
+
+    // Position after the last `*` and the text position it was tried at.
+    let mut backtrack = None;
+    let (mut p, mut t) = (0, 0);
+
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p + 1, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                Some((star, tried)) => {
+                    p = star;
+                    t = tried + 1;
+                    backtrack = Some((star, tried + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|it| *it == '*')
+}
+
 pub fn enumerate_devices() -> Result<Vec<Device>> {
     let mut render_endpoints = Vec::<Device>::new();
 
@@ -87,3 +142,65 @@ pub fn enumerate_devices() -> Result<Vec<Device>> {
 
     Ok(render_endpoints)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NAMES: [&str; 3] = [
+        "Speakers (Realtek Audio)",
+        "Headset Earphone (Arctis 7)",
+        "Headset Microphone (Arctis 7)",
+    ];
+
+    fn matching(pattern: &str) -> Vec<&'static str> {
+        NAMES
+            .into_iter()
+            .filter(|name| matches_pattern(pattern, name))
+            .collect()
+    }
+
+    #[test]
+    fn matches_the_exact_name() {
+        assert_eq!(
+            matching("Speakers (Realtek Audio)"),
+            ["Speakers (Realtek Audio)"]
+        );
+        assert!(matching("Speakers").is_empty());
+    }
+
+    #[test]
+    fn matches_a_part_of_the_name_between_wildcards() {
+        assert_eq!(matching("*Realtek*"), ["Speakers (Realtek Audio)"]);
+        assert_eq!(
+            matching("Headset ?arphone*"),
+            ["Headset Earphone (Arctis 7)"]
+        );
+    }
+
+    #[test]
+    fn ignores_case() {
+        assert_eq!(
+            matching("HEADSET MICROPHONE*"),
+            ["Headset Microphone (Arctis 7)"]
+        );
+    }
+
+    #[test]
+    fn matches_nothing_for_an_unknown_device() {
+        assert!(matching("*Monitor*").is_empty());
+        assert!(matching("").is_empty());
+    }
+
+    #[test]
+    fn matches_every_device_a_broad_pattern_covers() {
+        assert_eq!(
+            matching("*Arctis 7)"),
+            [
+                "Headset Earphone (Arctis 7)",
+                "Headset Microphone (Arctis 7)"
+            ]
+        );
+        assert_eq!(matching("*"), NAMES);
+    }
+}
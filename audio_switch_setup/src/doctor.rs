@@ -0,0 +1,235 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{Result, anyhow};
+use sha2::{Digest, Sha256};
+
+use crate::config::Config;
+use crate::paths;
+use crate::tasks::{self, with_com};
+use crate::{migrations, service, version};
+
+/// Something wrong with the installation that [`run`] can fix.
+#[derive(Debug)]
+enum Problem {
+    MissingService,
+    ModifiedService { expected: String, found: String },
+    MissingTask,
+    WrongTask { path: PathBuf, arguments: String },
+    MissingShortcut,
+    WrongShortcut { target: PathBuf },
+    BrokenConfig(String),
+    ServiceStopped,
+}
+
+impl std::fmt::Display for Problem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Problem::MissingService => write!(f, "The service executable is missing."),
+            Problem::ModifiedService { expected, found } => write!(
+                f,
+                "The service executable was modified (SHA-256 {found}, expected {expected})."
+            ),
+            Problem::MissingTask => write!(f, "The autostart task is missing."),
+            Problem::WrongTask { path, arguments } => write!(
+                f,
+                "The autostart task runs \"{} {arguments}\".",
+                path.display()
+            ),
+            Problem::MissingShortcut => write!(f, "The desktop shortcut is missing."),
+            Problem::WrongShortcut { target } => {
+                write!(f, "The desktop shortcut points to {}.", target.display())
+            }
+            Problem::BrokenConfig(err) => write!(f, "The configuration can't be read: {err}"),
+            Problem::ServiceStopped => write!(f, "The service is not running."),
+        }
+    }
+}
+
+impl Problem {
+    fn fix_label(&self) -> &'static str {
+        match self {
+            Problem::MissingService | Problem::ModifiedService { .. } => {
+                "Restore the service executable"
+            }
+            Problem::MissingTask | Problem::WrongTask { .. } => "Recreate the autostart task",
+            Problem::MissingShortcut | Problem::WrongShortcut { .. } => {
+                "Recreate the desktop shortcut"
+            }
+            Problem::BrokenConfig(_) => "Repair the configuration",
+            Problem::ServiceStopped => "Start the service",
+        }
+    }
+}
+
+/// Paths of an installation.
+struct Installation {
+    service: PathBuf,
+    setup: PathBuf,
+    shortcut: PathBuf,
+}
+
+/// Checks the installation in `install_dir` and offers to fix every problem it finds.
+pub fn run(install_dir: &Path, service_bin: &[u8]) -> Result<()> {
+    let installation = Installation {
+        service: install_dir.join("AudioSwitchService.exe"),
+        setup: install_dir.join("AudioSwitchSetup.exe"),
+        shortcut: directories::UserDirs::new()
+            .expect("Failed creating UserDir Instance.")
+            .desktop_dir()
+            .ok_or(anyhow!("Failed to get Desktop path."))?
+            .join("AudioSwitch.exe"),
+    };
+
+    let config = paths::load_config::<Config>();
+    if let Ok(config) = &config
+        && !config.installed
+        && !installation.service.exists()
+    {
+        println!("    Program not installed.");
+        return Ok(());
+    }
+
+    let checks: [(&str, Option<Problem>); 5] = [
+        (
+            "Service executable",
+            check_service(&installation.service, service_bin),
+        ),
+        ("Autostart task", check_task(&installation.service)?),
+        ("Desktop shortcut", check_shortcut(&installation)),
+        (
+            "Configuration",
+            config
+                .err()
+                .map(|err| Problem::BrokenConfig(err.to_string())),
+        ),
+        (
+            "Service",
+            (!service::is_running()?).then_some(Problem::ServiceStopped),
+        ),
+    ];
+
+    let mut problems = vec![];
+    for (name, problem) in checks {
+        match problem {
+            None => println!("    {name}... ✔"),
+            Some(problem) => {
+                println!("    {name}... ✘ {problem}");
+                problems.push(problem);
+            }
+        }
+    }
+
+    if problems.is_empty() {
+        println!("    > No problems found!");
+        return Ok(());
+    }
+
+    // Problems are checked in an order that makes earlier fixes, like restoring the executable,
+    // prerequisites of later ones, like starting the service.
+    for problem in problems {
+        let label = problem.fix_label();
+        if !inquire::Confirm::new(&format!("{label}? (y/n)")).prompt()? {
+            continue;
+        }
+
+        fix(&problem, &installation, service_bin)?;
+        println!("    {label}... Done ✔");
+    }
+
+    Ok(())
+}
+
+fn check_service(path: &Path, service_bin: &[u8]) -> Option<Problem> {
+    let Ok(installed) = std::fs::read(path) else {
+        return Some(Problem::MissingService);
+    };
+
+    // A newer version installed by another setup is not a modification.
+    if let Ok(Some(installed_version)) = version::file_version(path)
+        && installed_version > version::current()
+    {
+        return None;
+    }
+
+    let expected = sha256(service_bin);
+    let found = sha256(&installed);
+
+    (expected != found).then_some(Problem::ModifiedService { expected, found })
+}
+
+fn check_task(service: &Path) -> Result<Option<Problem>> {
+    let Some((path, arguments)) = with_com(tasks::autostart_task)? else {
+        return Ok(Some(Problem::MissingTask));
+    };
+
+    if path != service || arguments != service::task_arguments() {
+        return Ok(Some(Problem::WrongTask { path, arguments }));
+    }
+
+    Ok(None)
+}
+
+fn check_shortcut(installation: &Installation) -> Option<Problem> {
+    match std::fs::read_link(&installation.shortcut) {
+        Ok(target) if target == installation.setup => None,
+        Ok(target) => Some(Problem::WrongShortcut { target }),
+        Err(_) => Some(Problem::MissingShortcut),
+    }
+}
+
+fn fix(problem: &Problem, installation: &Installation, service_bin: &[u8]) -> Result<()> {
+    match problem {
+        Problem::MissingService | Problem::ModifiedService { .. } => {
+            // A running service keeps its executable locked.
+            let was_running = service::stop_service()?;
+            std::fs::write(&installation.service, service_bin)?;
+
+            if was_running {
+                service::start_service(&installation.service)?;
+            }
+        }
+        Problem::MissingTask | Problem::WrongTask { .. } => with_com(|| {
+            tasks::create_autostart_task(&installation.service, &service::task_arguments())
+        })?,
+        Problem::MissingShortcut | Problem::WrongShortcut { .. } => {
+            let _ = std::fs::remove_file(&installation.shortcut);
+            std::os::windows::fs::symlink_file(&installation.setup, &installation.shortcut)?;
+        }
+        Problem::BrokenConfig(_) => repair_config()?,
+        Problem::ServiceStopped => service::start_service(&installation.service)?,
+    }
+
+    Ok(())
+}
+
+/// Runs the migrations, which fix values older versions accepted. If the config still can't be
+/// read, it is moved aside and replaced with an empty one.
+fn repair_config() -> Result<()> {
+    if migrations::migrate().is_ok() && paths::load_config::<Config>().is_ok() {
+        return Ok(());
+    }
+
+    let path = paths::config_path()?;
+    let broken = path.with_extension("toml.broken");
+    std::fs::rename(&path, &broken)?;
+    println!(
+        "    Moved the unreadable configuration to {}.",
+        broken.display()
+    );
+
+    let config = Config {
+        installed: true,
+        config_version: migrations::CONFIG_VERSION,
+        ..Config::default()
+    };
+    config.store()?;
+
+    Ok(())
+}
+
+fn sha256(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
@@ -1,7 +1,9 @@
-use anyhow::{Context, Result};
+use anyhow::{Context, Result, anyhow};
 use inquire::Select;
 use win_hotkey::keys::{ModifiersKey, VirtualKey};
 
+use crate::config::HotkeyConfig;
+
 #[derive(Debug)]
 pub struct Hotkey {
     pub modifier: Option<ModifiersKey>,
@@ -20,136 +22,133 @@ impl std::fmt::Display for Hotkey {
         write!(
             f,
             "{}{}",
-            &self
-                .modifier
-                .map_or_else(|| "".to_string(), |i| format!("{}+", i.to_string())),
-            &self.main_key.to_string()
+            self.modifier
+                .map_or_else(|| "".to_string(), |i| format!("{}+", i)),
+            self.main_key
         )
     }
 }
 
-pub fn create_hotkey() -> Result<Hotkey> {
-    let modifier_keys = vec!["ALT", "CTRL", "SHIFT", "WIN"]
-        .iter()
-        .map(|it| it.to_string())
-        .collect::<Vec<String>>();
+/// Modifiers offered when creating a hotkey.
+pub const MODIFIER_KEYS: [&str; 4] = ["ALT", "CTRL", "SHIFT", "WIN"];
+
+/// Keys other than digits and letters offered when creating a hotkey.
+const SPECIAL_KEYS: &[&str] = &[
+    "BACKSPACE",
+    "CLEAR",
+    "RETURN",
+    "PAUSE",
+    "CAPITAL",
+    "ESC",
+    "SPACE",
+    "PRIOR",
+    "NEXT",
+    "END",
+    "HOME",
+    "TAB",
+    "UP",
+    "LEFT",
+    "RIGHT",
+    "DOWN",
+    "SELECT",
+    "PRINT",
+    "EXECUTE",
+    "SNAPSHOT",
+    "INSERT",
+    "DELETE",
+    "HELP",
+    "APPS",
+    "SLEEP",
+    "NUMPAD0",
+    "NUMPAD1",
+    "NUMPAD2",
+    "NUMPAD3",
+    "NUMPAD4",
+    "NUMPAD5",
+    "NUMPAD6",
+    "NUMPAD7",
+    "NUMPAD8",
+    "NUMPAD9",
+    "NUMPADMULTIPLY",
+    "NUMPADADD",
+    "NUMPADSEPARATOR",
+    "NUMPADSUBTRACT",
+    "NUMPADDECIMAL",
+    "NUMPADDIVIDE",
+    "F1",
+    "F2",
+    "F3",
+    "F4",
+    "F5",
+    "F6",
+    "F7",
+    "F8",
+    "F9",
+    "F10",
+    "F11",
+    "F12",
+    "F13",
+    "F14",
+    "F15",
+    "F16",
+    "F17",
+    "F18",
+    "F19",
+    "F20",
+    "F21",
+    "F22",
+    "F23",
+    "F24",
+    "NUMLOCK",
+    "SCROLL",
+    "BROWSER_BACK",
+    "BROWSER_FORWARD",
+    "BROWSER_REFRESH",
+    "BROWSER_STOP",
+    "BROWSER_SEARCH",
+    "BROWSER_FAVORITES",
+    "BROWSER_HOME",
+    "VOLUME_MUTE",
+    "VOLUME_DOWN",
+    "VOLUME_UP",
+    "MEDIA_NEXT_TRACK",
+    "MEDIA_PREV_TRACK",
+    "MEDIA_STOP",
+    "MEDIA_PLAY_PAUSE",
+    "LAUNCH_MAIL",
+    "LAUNCH_MEDIA_SELECT",
+    "LAUNCH_APP1",
+    "LAUNCH_APP2",
+    ";",
+    "+",
+    ",",
+    "-",
+    ".",
+    "/",
+    "`",
+    "[",
+    "\\",
+    "]",
+    "'",
+    "ATTN",
+    "CRSEL",
+    "EXSEL",
+    "PLAY",
+    "ZOOM",
+];
 
-    let mut num_keys = (0..10).map(|it| it.to_string()).collect::<Vec<String>>();
-    let mut alpha_keys = (65..91)
-        .map(|it| char::from_u32(it).unwrap().to_string())
-        .collect::<Vec<String>>();
+/// Names of the keys offered when creating a hotkey: digits, letters and [`SPECIAL_KEYS`].
+pub fn key_names() -> Vec<String> {
+    let num_keys = (0..10).map(|it| it.to_string());
+    let alpha_keys = ('A'..='Z').map(|it| it.to_string());
+    let special_keys = SPECIAL_KEYS.iter().map(|it| it.to_string());
 
-    let mut special_keys = vec![
-        "BACKSPACE",
-        "CLEAR",
-        "RETURN",
-        "PAUSE",
-        "CAPITAL",
-        "ESC",
-        "SPACE",
-        "PRIOR",
-        "NEXT",
-        "END",
-        "HOME",
-        "TAB",
-        "UP",
-        "LEFT",
-        "RIGHT",
-        "DOWN",
-        "SELECT",
-        "PRINT",
-        "EXECUTE",
-        "SNAPSHOT",
-        "INSERT",
-        "DELETE",
-        "HELP",
-        "APPS",
-        "SLEEP",
-        "NUMPAD0",
-        "NUMPAD1",
-        "NUMPAD2",
-        "NUMPAD3",
-        "NUMPAD4",
-        "NUMPAD5",
-        "NUMPAD6",
-        "NUMPAD7",
-        "NUMPAD8",
-        "NUMPAD9",
-        "NUMPADMULTIPLY",
-        "NUMPADADD",
-        "NUMPADSEPARATOR",
-        "NUMPADSUBTRACT",
-        "NUMPADDECIMAL",
-        "NUMPADDIVIDE",
-        "F1",
-        "F2",
-        "F3",
-        "F4",
-        "F5",
-        "F6",
-        "F7",
-        "F8",
-        "F9",
-        "F10",
-        "F11",
-        "F12",
-        "F13",
-        "F14",
-        "F15",
-        "F16",
-        "F17",
-        "F18",
-        "F19",
-        "F20",
-        "F21",
-        "F22",
-        "F23",
-        "F24",
-        "NUMLOCK",
-        "SCROLL",
-        "BROWSER_BACK",
-        "BROWSER_FORWARD",
-        "BROWSER_REFRESH",
-        "BROWSER_STOP",
-        "BROWSER_SEARCH",
-        "BROWSER_FAVORITES",
-        "BROWSER_HOME",
-        "VOLUME_MUTE",
-        "VOLUME_DOWN",
-        "VOLUME_UP",
-        "MEDIA_NEXT_TRACK",
-        "MEDIA_PREV_TRACK",
-        "MEDIA_STOP",
-        "MEDIA_PLAY_PAUSE",
-        "LAUNCH_MAIL",
-        "LAUNCH_MEDIA_SELECT",
-        "LAUNCH_APP1",
-        "LAUNCH_APP2",
-        ";",
-        "+",
-        ",",
-        "-",
-        ".",
-        "/",
-        "`",
-        "[",
-        "\\",
-        "]",
-        "'",
-        "ATTN",
-        "CRSEL",
-        "EXSEL",
-        "PLAY",
-        "ZOOM",
-    ]
-    .iter()
-    .map(|it| it.to_string())
-    .collect::<Vec<String>>();
+    num_keys.chain(alpha_keys).chain(special_keys).collect()
+}
 
-    let mut keys = Vec::new();
-    keys.append(&mut num_keys);
-    keys.append(&mut alpha_keys);
-    keys.append(&mut special_keys);
+pub fn create_hotkey() -> Result<Hotkey> {
+    let modifier_keys = MODIFIER_KEYS.map(String::from).to_vec();
+    let keys = key_names();
 
     let modifier_key = Select::new("Select optional Modifier:", modifier_keys)
         .with_help_message("Press Esc to select no Modifier key...")
@@ -163,3 +162,27 @@ pub fn create_hotkey() -> Result<Hotkey> {
         VirtualKey::from_keyname(&hotkey).unwrap(),
     ))
 }
+
+/// Parses a key name the way the service does: in any case, with or without `VK_`, or as a hex
+/// code like `0x7B`.
+pub fn parse_key(name: &str) -> Result<VirtualKey> {
+    VirtualKey::from_keyname(name.strip_prefix("VK_").unwrap_or(name))
+        .map_err(|_| anyhow!("Unknown key \"{name}\"."))
+}
+
+/// Parses a modifier name the way the service does, in any case.
+pub fn parse_modifier(name: &str) -> Result<ModifiersKey> {
+    ModifiersKey::from_keyname(name).map_err(|_| anyhow!("Unknown modifier \"{name}\"."))
+}
+
+/// Checks a hand-written hotkey the way the service parses it and returns it in the form the
+/// setup writes, so `F1` and `VK_F1` or `ctrl` and `CONTROL` compare equal.
+pub fn normalize(hotkey: &HotkeyConfig) -> Result<HotkeyConfig> {
+    let key = parse_key(&hotkey.hotkey)?;
+    let modifier = hotkey.modifier.as_deref().map(parse_modifier).transpose()?;
+
+    Ok(HotkeyConfig {
+        modifier: modifier.map(|it| it.to_string()),
+        hotkey: key.to_string(),
+    })
+}
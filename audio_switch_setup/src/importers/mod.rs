@@ -0,0 +1,69 @@
+mod soundswitch;
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, anyhow};
+
+use crate::bundle::{self, Bundle, ImportPolicy};
+
+/// Reads the settings of another audio switcher.
+pub trait Importer {
+    /// Identifies the format on the command line.
+    fn id(&self) -> &'static str;
+
+    /// Name of the program shown to the user.
+    fn name(&self) -> &'static str;
+
+    /// Where the program keeps its settings, if it has a fixed location.
+    fn default_path(&self) -> Option<PathBuf>;
+
+    /// Converts the contents of a settings file.
+    fn convert(&self, content: &str) -> Result<Conversion>;
+}
+
+/// Foreign settings converted to AudioSwitch profiles.
+#[derive(Debug)]
+pub struct Conversion {
+    pub bundle: Bundle,
+    /// Everything that couldn't be converted or was converted with changes, one line each.
+    pub warnings: Vec<String>,
+}
+
+/// All supported formats.
+pub fn all() -> Vec<Box<dyn Importer>> {
+    vec![Box::new(soundswitch::SoundSwitch)]
+}
+
+/// Finds the importer with `id`.
+pub fn find(id: &str) -> Result<Box<dyn Importer>> {
+    all().into_iter().find(|it| it.id() == id).ok_or_else(|| {
+        let ids: Vec<_> = all().iter().map(|it| it.id()).collect();
+        anyhow!(
+            "Unknown format \"{id}\", supported are: {}.",
+            ids.join(", ")
+        )
+    })
+}
+
+/// Converts the settings at `path`, or at the importer's default location, reports what
+/// couldn't be converted and imports the rest like a bundle.
+pub fn import(importer: &dyn Importer, path: Option<&Path>, policy: ImportPolicy) -> Result<()> {
+    let path = match path {
+        Some(path) => path.to_path_buf(),
+        None => importer
+            .default_path()
+            .with_context(|| format!("{} has no default settings location.", importer.name()))?,
+    };
+
+    let content = std::fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read {}.", path.display()))?;
+    let conversion = importer
+        .convert(&content)
+        .with_context(|| format!("Failed to convert {}.", path.display()))?;
+
+    for warning in &conversion.warnings {
+        println!("    ! {warning}");
+    }
+
+    bundle::import_bundle(conversion.bundle, policy)
+}
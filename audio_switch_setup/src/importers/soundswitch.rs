@@ -0,0 +1,633 @@
+use std::path::PathBuf;
+
+use anyhow::{Context, Result, anyhow, bail};
+use serde::Deserialize;
+use serde_json::Value;
+use win_hotkey::keys::{ModifiersKey, VirtualKey};
+
+use super::{Conversion, Importer};
+use crate::bundle::{BUNDLE_VERSION, Bundle, BundleProfile, DeviceFingerprint};
+use crate::config::HotkeyConfig;
+
+/// SoundSwitch, which keeps its settings in `%APPDATA%\SoundSwitch\SoundSwitch.json`.
+///
+/// Profiles are read from the `Profiles` list of SoundSwitch 6 and from the `ProfileSettings`
+/// list of older versions. SoundSwitch writes enums either as names or as numbers depending on
+/// the version, both are accepted.
+pub struct SoundSwitch;
+
+impl Importer for SoundSwitch {
+    fn id(&self) -> &'static str {
+        "soundswitch"
+    }
+
+    fn name(&self) -> &'static str {
+        "SoundSwitch"
+    }
+
+    fn default_path(&self) -> Option<PathBuf> {
+        Some(
+            directories::BaseDirs::new()?
+                .config_dir()
+                .join("SoundSwitch")
+                .join("SoundSwitch.json"),
+        )
+    }
+
+    fn convert(&self, content: &str) -> Result<Conversion> {
+        let settings: Settings =
+            serde_json::from_str(content).context("Not a SoundSwitch settings file.")?;
+
+        let mut warnings = vec![];
+
+        for (hotkey, name) in [
+            (&settings.playback_hot_key, "playback"),
+            (&settings.recording_hot_key, "recording"),
+        ] {
+            if hotkey.as_ref().is_some_and(|it| !it.is_empty()) {
+                warnings.push(format!(
+                    "The hotkey cycling through {name} devices has no AudioSwitch equivalent."
+                ));
+            }
+        }
+
+        let profiles = settings
+            .profiles
+            .into_iter()
+            .chain(settings.profile_settings)
+            .filter_map(|profile| convert_profile(profile, &mut warnings))
+            .collect();
+
+        Ok(Conversion {
+            bundle: Bundle {
+                bundle_version: BUNDLE_VERSION,
+                profiles,
+                next_profile: None,
+                previous_profile: None,
+            },
+            warnings,
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Settings {
+    #[serde(default)]
+    profiles: Vec<SoundSwitchProfile>,
+    /// Profiles of SoundSwitch 5.
+    #[serde(default)]
+    profile_settings: Vec<SoundSwitchProfile>,
+    playback_hot_key: Option<SoundSwitchHotkey>,
+    recording_hot_key: Option<SoundSwitchHotkey>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct SoundSwitchProfile {
+    #[serde(alias = "ProfileName")]
+    name: String,
+    /// Devices with their role, SoundSwitch 6.
+    #[serde(default)]
+    devices: Vec<ProfileDevice>,
+    /// Activation triggers, SoundSwitch 6.
+    #[serde(default)]
+    triggers: Vec<Trigger>,
+    /// SoundSwitch 5.
+    playback: Option<DeviceInfo>,
+    recording: Option<DeviceInfo>,
+    hot_key: Option<SoundSwitchHotkey>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ProfileDevice {
+    device_info: DeviceInfo,
+    #[serde(default)]
+    role: Value,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct DeviceInfo {
+    name: String,
+    id: String,
+    /// Data flow of the endpoint.
+    #[serde(rename = "Type", default)]
+    kind: Value,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Trigger {
+    #[serde(rename = "Type")]
+    kind: Value,
+    hot_key: Option<SoundSwitchHotkey>,
+}
+
+/// A .NET `Keys` value and `ModifierKeys` flags.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct SoundSwitchHotkey {
+    #[serde(default)]
+    keys: Value,
+    #[serde(default)]
+    modifier: Value,
+}
+
+impl SoundSwitchHotkey {
+    fn is_empty(&self) -> bool {
+        match &self.keys {
+            Value::Null => true,
+            Value::Number(it) => it.as_u64() == Some(0),
+            Value::String(it) => it.is_empty() || it.eq_ignore_ascii_case("none"),
+            _ => false,
+        }
+    }
+}
+
+impl std::fmt::Display for SoundSwitchHotkey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} + {}",
+            value_text(&self.modifier),
+            value_text(&self.keys)
+        )
+    }
+}
+
+fn convert_profile(
+    profile: SoundSwitchProfile,
+    warnings: &mut Vec<String>,
+) -> Option<BundleProfile> {
+    let name = profile.name;
+
+    let mut hotkeys = vec![];
+    if let Some(hotkey) = profile.hot_key {
+        hotkeys.push(hotkey);
+    }
+    for trigger in profile.triggers {
+        match trigger.hot_key {
+            Some(hotkey) if is_hotkey_trigger(&trigger.kind) => hotkeys.push(hotkey),
+            _ => warnings.push(format!(
+                "Profile {name}: the {} trigger has no AudioSwitch equivalent.",
+                value_text(&trigger.kind)
+            )),
+        }
+    }
+
+    let mut hotkeys = hotkeys.into_iter().filter(|it| !it.is_empty());
+    let Some(hotkey) = hotkeys.next() else {
+        warnings.push(format!(
+            "Profile {name} was skipped: it has no hotkey, which AudioSwitch profiles need."
+        ));
+        return None;
+    };
+    if hotkeys.next().is_some() {
+        warnings.push(format!(
+            "Profile {name}: only the first hotkey ({hotkey}) was imported."
+        ));
+    }
+
+    let hotkey = match convert_hotkey(&hotkey) {
+        Ok((hotkey, dropped)) => {
+            if !dropped.is_empty() {
+                warnings.push(format!(
+                    "Profile {name}: AudioSwitch hotkeys have one modifier, {} was dropped and \
+                     the hotkey imported as {hotkey}.",
+                    dropped.join(" and ")
+                ));
+            }
+            hotkey
+        }
+        Err(err) => {
+            warnings.push(format!("Profile {name} was skipped: {err}"));
+            return None;
+        }
+    };
+
+    let mut outputs: Vec<_> = profile
+        .playback
+        .map(|it| (it, Value::Null))
+        .into_iter()
+        .collect();
+    let mut inputs: Vec<_> = profile
+        .recording
+        .map(|it| (it, Value::Null))
+        .into_iter()
+        .collect();
+    for device in profile.devices {
+        match data_flow(&device.device_info.kind) {
+            Some(DataFlow::Render) => outputs.push((device.device_info, device.role)),
+            Some(DataFlow::Capture) => inputs.push((device.device_info, device.role)),
+            None => warnings.push(format!(
+                "Profile {name}: device \"{}\" has an unknown type {}.",
+                device.device_info.name,
+                value_text(&device.device_info.kind)
+            )),
+        }
+    }
+
+    let mut pick = |mut devices: Vec<(DeviceInfo, Value)>, kind: &str| {
+        // The default device is what AudioSwitch switches, prefer the ones SoundSwitch set as such.
+        devices.sort_by_key(|(_, role)| is_communications_only(role));
+
+        let mut devices = devices.into_iter();
+        let Some((device, role)) = devices.next() else {
+            warnings.push(format!(
+                "Profile {name} has no {kind} device, one is chosen on import."
+            ));
+            return DeviceFingerprint {
+                name: String::new(),
+                id: String::new(),
+            };
+        };
+
+        let dropped: Vec<_> = devices.map(|(it, _)| format!("\"{}\"", it.name)).collect();
+        if !dropped.is_empty() {
+            warnings.push(format!(
+                "Profile {name}: AudioSwitch profiles have one {kind} device, \"{}\" was \
+                 imported and {} dropped.",
+                device.name,
+                dropped.join(" and ")
+            ));
+        }
+
+        if is_communications_only(&role) {
+            warnings.push(format!(
+                "Profile {name}: \"{}\" was the communications device, AudioSwitch makes it \
+                 the default device.",
+                device.name
+            ));
+        }
+
+        DeviceFingerprint {
+            name: device.name,
+            id: device.id,
+        }
+    };
+
+    let output = pick(outputs, "playback");
+    let input = pick(inputs, "recording");
+
+    Some(BundleProfile {
+        name,
+        color: None,
+        hotkey,
+        input,
+        output,
+    })
+}
+
+enum DataFlow {
+    Render,
+    Capture,
+}
+
+fn data_flow(kind: &Value) -> Option<DataFlow> {
+    match kind {
+        Value::String(it) if ["render", "playback"].contains(&it.to_lowercase().as_str()) => {
+            Some(DataFlow::Render)
+        }
+        Value::String(it) if ["capture", "recording"].contains(&it.to_lowercase().as_str()) => {
+            Some(DataFlow::Capture)
+        }
+        Value::Number(it) if it.as_u64() == Some(0) => Some(DataFlow::Render),
+        Value::Number(it) if it.as_u64() == Some(1) => Some(DataFlow::Capture),
+        _ => None,
+    }
+}
+
+fn is_hotkey_trigger(kind: &Value) -> bool {
+    match kind {
+        Value::String(it) => it.eq_ignore_ascii_case("hotkey"),
+        Value::Number(it) => it.as_u64() == Some(0),
+        _ => false,
+    }
+}
+
+/// Whether a device role only covers communications, `Role` 2 or "Communications".
+fn is_communications_only(role: &Value) -> bool {
+    match role {
+        Value::String(it) => it.eq_ignore_ascii_case("communications"),
+        Value::Number(it) => it.as_u64() == Some(2),
+        _ => false,
+    }
+}
+
+fn value_text(value: &Value) -> String {
+    match value {
+        Value::String(it) => it.clone(),
+        it => it.to_string(),
+    }
+}
+
+/// Converts a SoundSwitch hotkey. AudioSwitch supports one modifier, the names of any further
+/// ones are returned.
+fn convert_hotkey(hotkey: &SoundSwitchHotkey) -> Result<(HotkeyConfig, Vec<String>)> {
+    let mut modifiers = modifiers(&hotkey.modifier)?;
+
+    let key = match &hotkey.keys {
+        // `Keys` carries Shift, Control and Alt as 1, 2 and 4 in its upper bits.
+        Value::Number(it) => {
+            let value = it.as_u64().context("Invalid key code.")?;
+            let bits = (value >> 16) as u32;
+            modifiers.extend(modifier_bits(
+                (bits & 1) << 2 | (bits & 2) | (bits & 4) >> 2,
+            ));
+            format!("0x{:02X}", value & 0xFFFF)
+        }
+        Value::String(it) => {
+            let mut key = None;
+            for part in it.split(',').map(str::trim) {
+                match modifier_name(part) {
+                    Some(modifier) => modifiers.push(modifier),
+                    None if key.is_none() => key = Some(part),
+                    None => bail!("hotkey {hotkey} has more than one key."),
+                }
+            }
+            key_name(key.context("Hotkey without a key.")?)
+                .ok_or_else(|| anyhow!("key \"{it}\" has no AudioSwitch equivalent."))?
+        }
+        _ => bail!("hotkey {hotkey} has no key."),
+    };
+
+    let key = VirtualKey::from_keyname(&key)
+        .map_err(|_| anyhow!("key \"{key}\" has no AudioSwitch equivalent."))?;
+
+    // Control wins over Alt, Shift and Win, regardless of how SoundSwitch listed them.
+    let mut ordered = vec![];
+    for candidate in [
+        ModifiersKey::Ctrl,
+        ModifiersKey::Alt,
+        ModifiersKey::Shift,
+        ModifiersKey::Win,
+    ] {
+        if modifiers.contains(&candidate) {
+            ordered.push(candidate);
+        }
+    }
+
+    let mut ordered = ordered.into_iter();
+    let modifier = ordered.next();
+    let dropped = ordered.map(|it| it.to_string()).collect();
+
+    Ok((
+        HotkeyConfig {
+            modifier: modifier.map(|it| it.to_string()),
+            hotkey: key.to_string(),
+        },
+        dropped,
+    ))
+}
+
+fn modifiers(value: &Value) -> Result<Vec<ModifiersKey>> {
+    match value {
+        Value::Null => Ok(vec![]),
+        Value::Number(it) => Ok(modifier_bits(
+            it.as_u64().context("Invalid modifier flags.")? as u32,
+        )),
+        Value::String(it) => it
+            .split(',')
+            .map(str::trim)
+            .filter(|it| !it.is_empty() && !it.eq_ignore_ascii_case("none"))
+            .map(|it| modifier_name(it).ok_or_else(|| anyhow!("unknown modifier \"{it}\".")))
+            .collect(),
+        it => bail!("unknown modifier {it}."),
+    }
+}
+
+/// `ModifierKeys` flags, which match the `MOD_*` values of `RegisterHotKey`.
+fn modifier_bits(bits: u32) -> Vec<ModifiersKey> {
+    [
+        (1, ModifiersKey::Alt),
+        (2, ModifiersKey::Ctrl),
+        (4, ModifiersKey::Shift),
+        (8, ModifiersKey::Win),
+    ]
+    .into_iter()
+    .filter(|(bit, _)| bits & bit != 0)
+    .map(|(_, modifier)| modifier)
+    .collect()
+}
+
+fn modifier_name(name: &str) -> Option<ModifiersKey> {
+    match name.to_ascii_lowercase().as_str() {
+        "alt" => Some(ModifiersKey::Alt),
+        "control" | "ctrl" => Some(ModifiersKey::Ctrl),
+        "shift" => Some(ModifiersKey::Shift),
+        "win" | "windows" | "lwin" | "rwin" => Some(ModifiersKey::Win),
+        _ => None,
+    }
+}
+
+/// Maps a .NET `Keys` name to a key name `VirtualKey::from_keyname` understands.
+fn key_name(name: &str) -> Option<String> {
+    let upper = name.to_ascii_uppercase();
+
+    if upper.len() == 1 && upper.chars().all(|it| it.is_ascii_uppercase()) {
+        return Some(upper);
+    }
+    if let Some(digit) = upper.strip_prefix('D')
+        && digit.len() == 1
+        && digit.chars().all(|it| it.is_ascii_digit())
+    {
+        return Some(digit.to_string());
+    }
+    if let Some(number) = upper.strip_prefix('F')
+        && number.parse::<u8>().is_ok_and(|it| (1..=24).contains(&it))
+    {
+        return Some(upper);
+    }
+    if let Some(digit) = upper.strip_prefix("NUMPAD")
+        && digit.len() == 1
+    {
+        return Some(upper);
+    }
+
+    let mapped = match upper.as_str() {
+        "BACK" | "TAB" | "CLEAR" | "RETURN" | "PAUSE" | "CAPITAL" | "ESCAPE" | "SPACE"
+        | "PRIOR" | "NEXT" | "END" | "HOME" | "LEFT" | "UP" | "RIGHT" | "DOWN" | "SELECT"
+        | "PRINT" | "EXECUTE" | "SNAPSHOT" | "INSERT" | "DELETE" | "HELP" | "APPS" | "SLEEP"
+        | "NUMLOCK" | "SCROLL" => return Some(upper),
+        "ENTER" => "RETURN",
+        "CAPSLOCK" => "CAPITAL",
+        "PAGEUP" => "PRIOR",
+        "PAGEDOWN" => "NEXT",
+        "PRINTSCREEN" => "SNAPSHOT",
+        "MULTIPLY" => "NUMPADMULTIPLY",
+        "ADD" => "NUMPADADD",
+        "SEPARATOR" => "NUMPADSEPARATOR",
+        "SUBTRACT" => "NUMPADSUBTRACT",
+        "DECIMAL" => "NUMPADDECIMAL",
+        "DIVIDE" => "NUMPADDIVIDE",
+        "BROWSERBACK" => "BROWSER_BACK",
+        "BROWSERFORWARD" => "BROWSER_FORWARD",
+        "BROWSERREFRESH" => "BROWSER_REFRESH",
+        "BROWSERSTOP" => "BROWSER_STOP",
+        "BROWSERSEARCH" => "BROWSER_SEARCH",
+        "BROWSERFAVORITES" => "BROWSER_FAVORITES",
+        "BROWSERHOME" => "BROWSER_HOME",
+        "VOLUMEMUTE" => "VOLUME_MUTE",
+        "VOLUMEDOWN" => "VOLUME_DOWN",
+        "VOLUMEUP" => "VOLUME_UP",
+        "MEDIANEXTTRACK" => "MEDIA_NEXT_TRACK",
+        "MEDIAPREVIOUSTRACK" => "MEDIA_PREV_TRACK",
+        "MEDIASTOP" => "MEDIA_STOP",
+        "MEDIAPLAYPAUSE" => "MEDIA_PLAY_PAUSE",
+        "LAUNCHMAIL" => "LAUNCH_MAIL",
+        "SELECTMEDIA" => "LAUNCH_MEDIA_SELECT",
+        "LAUNCHAPPLICATION1" => "LAUNCH_APP1",
+        "LAUNCHAPPLICATION2" => "LAUNCH_APP2",
+        "OEMSEMICOLON" | "OEM1" => "OEM_1",
+        "OEMPLUS" => "PLUS",
+        "OEMCOMMA" => "COMMA",
+        "OEMMINUS" => "MINUS",
+        "OEMPERIOD" => "PERIOD",
+        "OEMQUESTION" | "OEM2" => "OEM_2",
+        "OEMTILDE" | "OEM3" => "OEM_3",
+        "OEMOPENBRACKETS" | "OEM4" => "OEM_4",
+        "OEMPIPE" | "OEM5" => "OEM_5",
+        "OEMCLOSEBRACKETS" | "OEM6" => "OEM_6",
+        "OEMQUOTES" | "OEM7" => "OEM_7",
+        "OEM8" => "OEM_8",
+        "OEMBACKSLASH" | "OEM102" => "OEM_102",
+        _ => return None,
+    };
+
+    Some(mapped.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn convert(sample: &str) -> Conversion {
+        SoundSwitch.convert(sample).expect("Sample should convert.")
+    }
+
+    fn hotkey(modifier: Option<&str>, key: &str) -> HotkeyConfig {
+        HotkeyConfig {
+            modifier: modifier.map(str::to_string),
+            hotkey: key.to_string(),
+        }
+    }
+
+    fn has_warning(conversion: &Conversion, text: &str) -> bool {
+        conversion.warnings.iter().any(|it| it.contains(text))
+    }
+
+    #[test]
+    fn converts_soundswitch_6_profiles() {
+        let conversion = convert(include_str!("samples/soundswitch-6.json"));
+        let profiles = &conversion.bundle.profiles;
+
+        assert_eq!(
+            profiles
+                .iter()
+                .map(|it| it.name.as_str())
+                .collect::<Vec<_>>(),
+            ["Gaming", "Meeting"]
+        );
+
+        let gaming = &profiles[0];
+        assert_eq!(gaming.hotkey, hotkey(Some("CONTROL"), "VK_F1"));
+        assert_eq!(gaming.output.name, "Headphones (Arctis 7 Game)");
+        assert_eq!(
+            gaming.output.id,
+            "{0.0.0.00000000}.{7f1c2a4e-1d0b-4d5a-9a3e-0c6f1e2b3a41}"
+        );
+        assert_eq!(gaming.input.name, "Microphone (Arctis 7 Chat)");
+
+        let meeting = &profiles[1];
+        assert_eq!(meeting.hotkey, hotkey(Some("ALT"), "M"));
+        assert_eq!(meeting.output.name, "Speakers (Realtek(R) Audio)");
+    }
+
+    #[test]
+    fn reports_what_soundswitch_6_profiles_lose() {
+        let conversion = convert(include_str!("samples/soundswitch-6.json"));
+
+        assert!(has_warning(&conversion, "ALT was dropped"));
+        assert!(has_warning(&conversion, "the Process trigger"));
+        assert!(has_warning(
+            &conversion,
+            "\"Speakers (Realtek(R) Audio)\" was imported and \"Headset Earphone (Jabra)\" dropped"
+        ));
+        assert!(has_warning(
+            &conversion,
+            "Profile Music was skipped: it has no hotkey"
+        ));
+        assert!(has_warning(&conversion, "cycling through playback devices"));
+        assert!(!has_warning(
+            &conversion,
+            "cycling through recording devices"
+        ));
+    }
+
+    #[test]
+    fn converts_numeric_soundswitch_5_profiles() {
+        let conversion = convert(include_str!("samples/soundswitch-5.json"));
+        let profiles = &conversion.bundle.profiles;
+
+        assert_eq!(profiles.len(), 2);
+
+        // Keys 0x20072 is Control + F3, its modifier bits use the `Keys` layout.
+        assert_eq!(profiles[0].name, "Desk");
+        assert_eq!(profiles[0].hotkey, hotkey(Some("CONTROL"), "VK_F3"));
+        assert_eq!(profiles[0].output.name, "Speakers (USB Audio)");
+        assert_eq!(profiles[0].input.name, "Microphone (USB Audio)");
+
+        // Modifier 8 is the Windows key.
+        assert_eq!(profiles[1].name, "Couch");
+        assert_eq!(profiles[1].hotkey, hotkey(Some("WIN"), "VK_NUMPAD1"));
+        assert!(profiles[1].input.name.is_empty());
+        assert!(has_warning(
+            &conversion,
+            "Profile Couch has no recording device"
+        ));
+    }
+
+    #[test]
+    fn skips_keys_without_equivalent() {
+        let conversion = convert(
+            r#"{"Profiles": [{"Name": "Odd", "Triggers": [
+                {"Type": "HotKey", "HotKey": {"Keys": "LButton", "Modifier": "Alt"}}
+            ]}]}"#,
+        );
+
+        assert!(conversion.bundle.profiles.is_empty());
+        assert!(has_warning(
+            &conversion,
+            "key \"LButton\" has no AudioSwitch equivalent"
+        ));
+    }
+
+    #[test]
+    fn imports_communications_device_as_default() {
+        let conversion = convert(
+            r#"{"Profiles": [{"Name": "Call", "Devices": [
+                {"DeviceInfo": {"Name": "Headset (Jabra)", "Id": "1", "Type": 0}, "Role": 2}
+            ], "Triggers": [{"Type": 0, "HotKey": {"Keys": 67, "Modifier": 1}}]}]}"#,
+        );
+
+        let call = &conversion.bundle.profiles[0];
+        assert_eq!(call.hotkey, hotkey(Some("ALT"), "C"));
+        assert_eq!(call.output.name, "Headset (Jabra)");
+        assert!(has_warning(
+            &conversion,
+            "\"Headset (Jabra)\" was the communications device"
+        ));
+        assert!(has_warning(
+            &conversion,
+            "Profile Call has no recording device"
+        ));
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        assert!(SoundSwitch.convert("[1, 2, 3]").is_err());
+        assert!(SoundSwitch.convert("not json").is_err());
+    }
+}
@@ -0,0 +1,46 @@
+use std::path::PathBuf;
+
+use anyhow::Result;
+
+use crate::paths;
+
+/// Number of lines shown from the end of the log.
+const TAIL_LINES: usize = 40;
+
+/// Directory the service writes its daily log files to.
+fn log_dir() -> Result<PathBuf> {
+    Ok(paths::data_dir()?.join("logs"))
+}
+
+/// Prints the last lines of the newest service log file.
+pub fn show_log_tail() -> Result<()> {
+    let dir = log_dir()?;
+
+    // Files are named `service.<date>.log`, so the newest one sorts last.
+    let newest = std::fs::read_dir(&dir)
+        .ok()
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok().map(|it| it.path()))
+        .filter(|path| {
+            path.file_name()
+                .and_then(|it| it.to_str())
+                .is_some_and(|it| it.starts_with("service.") && it.ends_with(".log"))
+        })
+        .max();
+
+    let Some(path) = newest else {
+        println!("    No log file found in {}.", dir.display());
+        return Ok(());
+    };
+
+    let content = std::fs::read_to_string(&path)?;
+    let lines: Vec<&str> = content.lines().collect();
+
+    println!("    {}:", path.display());
+    for line in &lines[lines.len().saturating_sub(TAIL_LINES)..] {
+        println!("{line}");
+    }
+
+    Ok(())
+}
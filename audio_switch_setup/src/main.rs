@@ -1,53 +1,220 @@
+mod apply;
+mod backups;
+mod bundle;
+mod cli;
+#[path = "../../audio_switch_service/src/color.rs"]
+mod color;
 mod config;
+mod config_export;
 mod devices;
+mod doctor;
 mod hotkeys;
+mod importers;
+#[path = "../../audio_switch_service/src/ipc.rs"]
+mod ipc;
+mod logs;
+mod migrations;
+mod notifications;
+#[path = "../../audio_switch_service/src/paths.rs"]
+mod paths;
 mod profiles;
+mod schema;
+mod service;
+#[path = "../../audio_switch_service/src/shared.rs"]
+mod shared;
+mod sync;
 mod tasks;
+#[cfg(test)]
+mod test_util;
+mod transaction;
+mod upgrade;
+mod version;
 
-use std::ffi::OsString;
 use std::fs::DirBuilder;
 use std::io::{self, Write};
-use std::str::FromStr;
-use std::thread::sleep;
-use std::time::Duration;
+use std::path::Path;
 
 use anyhow::{Result, anyhow};
+use bundle::ImportPolicy;
+use clap::Parser;
+use cli::{Cli, CliCommand, ConfigCommand, ProfileCommand};
 use config::Config;
-use inquire::Select;
-use sysinfo::System;
-use windows::Win32::System::Com::{COINIT_MULTITHREADED, CoInitializeEx, CoUninitialize};
+use config_export::ConfigChoice;
+use inquire::{Select, Text};
+use transaction::{Operation, Transaction};
+use windows::Win32::System::Registry::{HKEY_CURRENT_USER, RegDeleteTreeW};
+use windows::core::w;
+
+/// The service executable installed by this setup.
+const SERVICE_BIN: &[u8] = include_bytes!(env!("CARGO_BIN_FILE_AUDIO_SWITCH_SERVICE"));
 
 fn main() -> Result<()> {
+    let cli = Cli::parse();
+
+    if let Some(path) = &cli.config {
+        paths::set_config_path(path)?;
+    }
+
+    // Their output is meant for other tools, and linting must see the file before migrating.
+    match &cli.command {
+        Some(CliCommand::Schema) => return schema::print(),
+        Some(CliCommand::Config(ConfigCommand::Lint { path })) => {
+            return schema::lint(path.as_deref());
+        }
+        _ => {}
+    }
+
     print_hero();
-    main_menu()?;
+
+    let install_dir = paths::data_dir()?;
+    if paths::is_portable() {
+        println!(
+            "    Portable mode, keeping everything in {}.",
+            install_dir.display()
+        );
+    }
+
+    // Everything below reads the config, which only works once it is migrated.
+    for description in migrations::migrate()? {
+        println!("    Migrating Configuration: {description}... Done ✔");
+    }
+
+    let installed_version = version::file_version(&install_dir.join("AudioSwitchService.exe"));
+    if version::is_outdated(&installed_version, version::current()) {
+        println!("    The installed service is older than this setup, install to upgrade it.");
+    }
+
+    match transaction::recover(&install_dir)? {
+        Some(Operation::Install) => install_program(cli.force, None)?,
+        Some(Operation::Uninstall) => {
+            uninstall(None)?;
+            return Ok(());
+        }
+        None => {}
+    }
+
+    match cli.command {
+        Some(CliCommand::Install { import_config }) => {
+            install_program(cli.force, import_config.as_deref())?
+        }
+        Some(CliCommand::Uninstall(args)) => uninstall(Some(args.config_choice()))?,
+        Some(CliCommand::Doctor) => doctor::run(&install_dir, SERVICE_BIN)?,
+        Some(CliCommand::Profile(ProfileCommand::Export { path, profiles })) => {
+            bundle::export(&path, &profiles)?
+        }
+        Some(CliCommand::Profile(ProfileCommand::Import { path, policy })) => {
+            bundle::import(&path, policy.policy())?;
+            config_changed()?;
+        }
+        Some(CliCommand::ImportFrom {
+            format,
+            path,
+            policy,
+        }) => {
+            importers::import(
+                &*importers::find(&format)?,
+                path.as_deref(),
+                policy.policy(),
+            )?;
+            config_changed()?;
+        }
+        Some(CliCommand::Apply { path, dry_run, yes }) => {
+            if apply::run(&path, dry_run, yes)? {
+                config_changed()?;
+            }
+        }
+        Some(CliCommand::Sync { folder, stop }) => {
+            let changed = match folder {
+                Some(folder) => sync::set_folder(Some(folder))?,
+                None if stop => sync::set_folder(None)?,
+                None => sync::run()?,
+            };
+            if changed {
+                restart_service()?;
+            }
+        }
+        Some(CliCommand::Schema | CliCommand::Config(_)) => unreachable!("Handled before."),
+        None => main_menu(cli.force)?,
+    }
 
     Ok(())
 }
 
-fn main_menu() -> Result<()> {
-    let menu = vec![
+fn main_menu(force: bool) -> Result<()> {
+    let menu = [
         Menu::Install,
         Menu::AddProfile,
         Menu::DeleteProfiles,
+        Menu::ExportProfiles,
+        Menu::ImportProfiles,
+        Menu::ImportForeign,
+        Menu::SyncProfiles,
+        Menu::Notifications,
+        Menu::RestoreBackup,
+        Menu::ShowLog,
+        Menu::Doctor,
         Menu::Uninstall,
         Menu::Quit,
     ];
 
+    // Bind after syncing, profiles added on other machines need devices here too.
+    let synced = sync::run()?;
+    if profiles::bind_unbound_profiles()? {
+        config_changed()?;
+    } else if synced {
+        restart_service()?;
+    }
+
     loop {
         match Select::new("AudioSwitch Main Menu:", menu.iter().collect()).prompt() {
             Ok(Menu::AddProfile) => {
                 profiles::new_profile()?;
-                restart_service()?;
+                config_changed()?;
             }
             Ok(Menu::DeleteProfiles) => {
                 profiles::delete_profile()?;
+                config_changed()?;
+            }
+            Ok(Menu::ExportProfiles) => {
+                let path = Text::new("Export to:").prompt()?;
+                bundle::export(Path::new(&path), &[])?;
+            }
+            Ok(Menu::ImportProfiles) => {
+                let path = Text::new("Import from:").prompt()?;
+                bundle::import(Path::new(&path), ImportPolicy::default())?;
+                config_changed()?;
+            }
+            Ok(Menu::ImportForeign) => {
+                import_foreign()?;
+                config_changed()?;
+            }
+            Ok(Menu::SyncProfiles) => {
+                if sync::configure()? {
+                    restart_service()?;
+                }
+            }
+            Ok(Menu::Notifications) => {
+                notifications::configure_notifications()?;
                 restart_service()?;
             }
+            Ok(Menu::RestoreBackup) => {
+                if backups::restore()? {
+                    config_changed()?;
+                }
+            }
+            Ok(Menu::ShowLog) => {
+                logs::show_log_tail()?;
+            }
+            Ok(Menu::Doctor) => {
+                let install_dir = paths::data_dir()?;
+
+                doctor::run(&install_dir, SERVICE_BIN)?;
+            }
             Ok(Menu::Install) => {
-                install_program()?;
+                install_program(force, None)?;
             }
             Ok(Menu::Uninstall) => {
-                uninstall()?;
+                uninstall(None)?;
                 break;
             }
             _ => break,
@@ -71,6 +238,14 @@ enum Menu {
     Install,
     AddProfile,
     DeleteProfiles,
+    ExportProfiles,
+    ImportProfiles,
+    ImportForeign,
+    SyncProfiles,
+    Notifications,
+    RestoreBackup,
+    ShowLog,
+    Doctor,
     Uninstall,
     Quit,
 }
@@ -78,151 +253,201 @@ enum Menu {
 impl std::fmt::Display for Menu {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match *self {
-            Menu::Install => write!(f, "Install AudioSwitch and add to autostart."),
+            Menu::Install => write!(f, "Install or upgrade AudioSwitch and add to autostart."),
             Menu::AddProfile => write!(f, "Add Audio Profile."),
             Menu::DeleteProfiles => write!(f, "Delete existing Profile."),
+            Menu::ExportProfiles => write!(f, "Export Profiles to share them."),
+            Menu::ImportProfiles => write!(f, "Import shared Profiles."),
+            Menu::ImportForeign => write!(f, "Import Profiles from another audio switcher."),
+            Menu::SyncProfiles => write!(f, "Sync Profiles through a shared folder."),
+            Menu::Notifications => write!(f, "Configure notifications."),
+            Menu::RestoreBackup => write!(f, "Restore a backup of the configuration."),
+            Menu::ShowLog => write!(f, "Show the end of the service log."),
+            Menu::Doctor => write!(f, "Check and repair the installation."),
             Menu::Uninstall => write!(f, "Uninstall AudioSwitch and remove from autostart."),
             Menu::Quit => write!(f, "Quit."),
         }
     }
 }
 
-fn install_program() -> Result<()> {
-    let mut cfg: Config = confy::load("AudioSwitch", None)?;
-
-    if cfg.installed {
-        println!("    Program already installed.");
-        return Ok(());
-    }
+/// Installs or upgrades AudioSwitch. A fresh install uses the configuration at `import`, or
+/// offers to use a kept or exported one.
+fn install_program(force: bool, import: Option<&Path>) -> Result<()> {
+    let mut cfg: Config = paths::load_config()?;
 
-    let service_bin = include_bytes!(env!("CARGO_BIN_FILE_AUDIO_SWITCH_SERVICE"));
-    let install_dir = directories::BaseDirs::new()
-        .unwrap()
-        .data_local_dir()
-        .join("AudioSwitch");
+    let install_dir = paths::data_dir()?;
 
     DirBuilder::new().recursive(true).create(&install_dir)?;
 
-    let install_file_path = &install_dir.join("AudioSwitchService.exe");
-    std::fs::write(&install_file_path, service_bin)?;
-
-    let current_exe = std::env::current_exe()?;
-    std::fs::copy(current_exe, &install_dir.join("AudioSwitchSetup.exe"))?;
-
-    println!("    Copied Program Files... Done ✔");
+    let mut tx = Transaction::begin(&install_dir, Operation::Install)?;
 
-    if inquire::Confirm::new("Do you want to create a Desktop shortcut? (y/n)").prompt()? {
-        std::os::windows::fs::symlink_file(
-            &install_dir.join("AudioSwitchSetup.exe"),
-            directories::UserDirs::new()
-                .expect("Failed creating UserDir Instance.")
-                .desktop_dir()
-                .ok_or(anyhow!("Failed to get Desktop path."))?
-                .join("AudioSwitch.exe"),
-        )?;
+    if cfg.installed && !tx.is_resumed() {
+        return upgrade::upgrade_program(&install_dir, SERVICE_BIN, force);
     }
 
-    unsafe {
-        CoInitializeEx(None, COINIT_MULTITHREADED)?;
+    if let Some(path) = import {
+        cfg = config_export::import(path)?;
+    } else if !tx.is_resumed() {
+        cfg = config_export::offer_import(cfg)?;
     }
 
-    tasks::create_autostart_task(&install_file_path)?;
+    let result = (|| {
+        let install_file_path = &install_dir.join("AudioSwitchService.exe");
+        tx.write_file(install_file_path, SERVICE_BIN)?;
 
-    unsafe {
-        CoUninitialize();
-    }
+        // In portable mode the setup may already be the installed one.
+        let setup_file_path = install_dir.join("AudioSwitchSetup.exe");
+        let current_exe = std::env::current_exe()?;
+        if current_exe != setup_file_path {
+            tx.write_file(&setup_file_path, &std::fs::read(current_exe)?)?;
+        }
 
-    println!("    Creating Autostart Entry... Done ✔");
+        println!("    Copied Program Files... Done ✔");
+
+        if inquire::Confirm::new("Do you want to create a Desktop shortcut? (y/n)").prompt()? {
+            tx.create_link(
+                &install_dir.join("AudioSwitchSetup.exe"),
+                &directories::UserDirs::new()
+                    .expect("Failed creating UserDir Instance.")
+                    .desktop_dir()
+                    .ok_or(anyhow!("Failed to get Desktop path."))?
+                    .join("AudioSwitch.exe"),
+            )?;
+        }
 
-    cfg.installed = true;
+        tx.create_task(install_file_path)?;
 
-    confy::store("AudioSwitch", None, cfg)?;
+        println!("    Creating Autostart Entry... Done ✔");
 
-    print!("    Starting Service...");
-    io::stdout().flush().expect("Failed to flush stdout");
+        cfg.installed = true;
+        cfg.config_version = migrations::CONFIG_VERSION;
 
-    std::process::Command::new(&install_file_path).spawn()?;
+        tx.store_config(cfg)?;
 
-    println!(" Done ✔");
-    io::stdout().flush().expect("Failed to flush stdout");
+        tx.start_service(install_file_path)
+    })();
+
+    if let Err(err) = result {
+        eprintln!("    Installation failed: {err:#}");
+        tx.roll_back()?;
+        println!("    Rolling back the installation... Done ✔");
+        return Ok(());
+    }
+
+    tx.commit()?;
 
     println!("    > Program was sucessfully installed!");
 
     Ok(())
 }
 
-fn uninstall() -> Result<()> {
-    let cfg: Config = confy::load("AudioSwitch", None)?;
+/// Uninstalls AudioSwitch. Without a `choice` for the configuration, asks for confirmation and
+/// the choice.
+fn uninstall(choice: Option<ConfigChoice>) -> Result<()> {
+    let cfg: Config = paths::load_config()?;
 
-    if !cfg.installed {
-        println!("    Program not installed.");
-        return Ok(());
-    }
+    let install_dir = paths::data_dir()?;
+
+    let mut tx = Transaction::begin(&install_dir, Operation::Uninstall)?;
 
-    if !inquire::Confirm::new("Are you sure you want to uninstall AudioSwitch? This will remove all user settings and hotkeys! (y/n)").prompt()? {
-        println!("    Uninstaller aborted!");
+    if !cfg.installed && !tx.is_resumed() {
+        println!("    Program not installed.");
         return Ok(());
     }
 
-    let mut sys = System::new_all();
-    sys.refresh_all();
-    let service_name = OsString::from_str("AudioSwitchService.exe")?;
-
-    for p in sys.processes_by_exact_name(&service_name) {
-        print!("    Stopping running Service...");
-        io::stdout().flush().expect("Failed to flush stdout");
-
-        p.kill();
-
-        sleep(Duration::from_secs(5));
-        println!(" Done ✔");
-    }
+    let choice = match choice {
+        Some(choice) => choice,
+        None => {
+            if !inquire::Confirm::new("Are you sure you want to uninstall AudioSwitch? (y/n)")
+                .prompt()?
+            {
+                println!("    Uninstaller aborted!");
+                return Ok(());
+            }
 
-    unsafe {
-        CoInitializeEx(None, COINIT_MULTITHREADED)?;
-    }
+            config_export::ask_choice()?
+        }
+    };
 
-    tasks::delete_task()?;
+    let result = (|| {
+        let service_file_path = install_dir.join("AudioSwitchService.exe");
 
-    println!("    Removing Autostart Entry... Done ✔");
+        tx.stop_service(&service_file_path)?;
 
-    unsafe {
-        CoUninitialize();
-    }
+        tx.delete_task(&service_file_path)?;
 
-    let install_path = directories::BaseDirs::new()
-        .unwrap()
-        .data_local_dir()
-        .join("AudioSwitch")
-        .join("AudioSwitchService.exe");
+        println!("    Removing Autostart Entry... Done ✔");
 
-    match std::fs::remove_file(install_path) {
-        Ok(_) => {
+        if tx.remove_file(&service_file_path)? {
             println!("    Removing Service File... Done ✔")
-        }
-        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+        } else {
             eprintln!("Nothing to delete, program already uninstalled?")
         }
-        Err(e) => eprintln!("Error while uninstalling: {}", e),
-    }
 
-    let desktop_symlink = directories::UserDirs::new()
-        .expect("Failed creating UserDir Instance.")
-        .desktop_dir()
-        .ok_or(anyhow!("Failed to get Desktop path."))?
-        .join("AudioSwitch.exe");
+        let desktop_symlink = directories::UserDirs::new()
+            .expect("Failed creating UserDir Instance.")
+            .desktop_dir()
+            .ok_or(anyhow!("Failed to get Desktop path."))?
+            .join("AudioSwitch.exe");
 
-    match std::fs::remove_file(desktop_symlink) {
-        Ok(_) => {
+        if tx.remove_file(&desktop_symlink)? {
             println!("    Removing Desktop Shortcut... Done ✔")
         }
-        _ => {}
+
+        let cfg_path = paths::config_path()?;
+
+        match &choice {
+            ConfigChoice::Keep => {
+                tx.store_config(Config {
+                    installed: false,
+                    ..cfg.clone()
+                })?;
+
+                println!(
+                    "    Keeping configuration at {}... Done ✔",
+                    cfg_path.display()
+                );
+            }
+            ConfigChoice::Export(path) => {
+                tx.write_file(path, config_export::export_contents(&cfg)?.as_bytes())?;
+                tx.remove_file(&cfg_path)?;
+
+                println!(
+                    "    Exporting configuration to {}... Done ✔",
+                    path.display()
+                );
+            }
+            ConfigChoice::Delete => {
+                tx.remove_file(&cfg_path)?;
+
+                println!("    Cleaning up configuration files... Done ✔");
+            }
+        }
+
+        anyhow::Ok(())
+    })();
+
+    if let Err(err) = result {
+        eprintln!("    Uninstallation failed: {err:#}");
+        tx.roll_back()?;
+        println!("    Rolling back the uninstallation... Done ✔");
+        return Ok(());
     }
 
-    let cfg_path = confy::get_configuration_file_path("AudioSwitch", None)?;
-    std::fs::remove_file(cfg_path)?;
+    tx.commit()?;
+
+    // The service registers these again on its next start, so they are not part of the
+    // transaction.
+    unsafe {
+        let _ = RegDeleteTreeW(
+            HKEY_CURRENT_USER,
+            w!("Software\\Classes\\AppUserModelId\\AudioSwitch"),
+        );
+    }
+    let _ = std::fs::remove_file(install_dir.join("AudioSwitch.ico"));
+    let _ = std::fs::remove_dir_all(install_dir.join("logs"));
 
-    println!("    Cleaning up configuration files... Done ✔");
+    println!("    Removing Notification Registration... Done ✔");
 
     self_replace::self_delete()?;
 
@@ -231,30 +456,36 @@ fn uninstall() -> Result<()> {
     Ok(())
 }
 
-fn restart_service() -> Result<()> {
-    let service_file_path = directories::BaseDirs::new()
-        .unwrap()
-        .data_local_dir()
-        .join("AudioSwitch")
-        .join("AudioSwitchService.exe");
-
-    let mut sys = System::new_all();
-    sys.refresh_all();
-    let service_name = OsString::from_str("AudioSwitchService.exe")?;
+/// Asks for a format and settings file and imports its profiles.
+fn import_foreign() -> Result<()> {
+    let importers = importers::all();
+    let names: Vec<_> = importers.iter().map(|it| it.name()).collect();
+    let name = Select::new("Import from:", names).prompt()?;
+    let importer = importers
+        .iter()
+        .find(|it| it.name() == name)
+        .expect("Selected importer exists.");
+
+    let mut prompt = Text::new("Settings file:");
+    let default = importer.default_path().map(|it| it.display().to_string());
+    if let Some(default) = &default {
+        prompt = prompt.with_default(default);
+    }
+    let path = prompt.prompt()?;
 
-    for p in sys.processes_by_exact_name(&service_name) {
-        print!("    Restarting Service...");
-        io::stdout().flush().expect("Failed to flush stdout");
+    importers::import(&**importer, Some(Path::new(&path)), ImportPolicy::default())
+}
 
-        p.kill();
-        sleep(Duration::from_secs(5));
+/// Shares changed profiles through the sync folder, if one is set, and restarts the service.
+fn config_changed() -> Result<()> {
+    sync::run()?;
+    restart_service()
+}
 
-        std::process::Command::new(&service_file_path).spawn()?;
-        sleep(Duration::from_secs(2));
+fn restart_service() -> Result<()> {
+    let service_file_path = paths::data_dir()?.join("AudioSwitchService.exe");
 
-        println!(" Done ✔");
-    }
-    Ok(())
+    service::restart_service(&service_file_path)
 }
 
 fn print_hero() {
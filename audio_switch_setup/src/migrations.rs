@@ -0,0 +1,232 @@
+use std::path::Path;
+use std::str::FromStr;
+
+use anyhow::{Context, Result};
+use toml::{Table, Value};
+
+use crate::color::Color;
+use crate::config::{self, Config};
+use crate::{backups, paths};
+
+/// A change to the config format. Runs on the raw file, so it can fix values the current
+/// `Config` would refuse to load.
+struct Migration {
+    description: &'static str,
+    apply: fn(&mut Table),
+}
+
+/// Migration `i` upgrades a config of version `i` to version `i + 1`.
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        description: "Remove profile colors that are not valid colors",
+        apply: drop_invalid_colors,
+    },
+    Migration {
+        description: "Move the device IDs of the profiles into the bindings of this machine",
+        apply: split_bindings,
+    },
+];
+
+/// Config version written by this setup.
+pub const CONFIG_VERSION: u32 = MIGRATIONS.len() as u32;
+
+/// Brings the stored config up to [`CONFIG_VERSION`]. Configs from a newer setup are left
+/// alone. Returns the descriptions of the migrations that ran.
+pub fn migrate() -> Result<Vec<&'static str>> {
+    migrate_file(&paths::config_path()?, backups::snapshot)
+}
+
+/// Migrates the config at `path` in place, calling `snapshot` before it is overwritten.
+fn migrate_file(path: &Path, snapshot: impl FnOnce() -> Result<()>) -> Result<Vec<&'static str>> {
+    if !path.exists() {
+        return Ok(vec![]);
+    }
+
+    let mut config = read_table(path)?;
+    let applied = apply(&mut config);
+
+    if !applied.is_empty() {
+        snapshot()?;
+        std::fs::write(path, toml::to_string_pretty(&config)?)?;
+    }
+
+    Ok(applied)
+}
+
+/// Reads a config written by any version, e.g. an export, without changing the file.
+pub fn load_file(path: &Path) -> Result<Config> {
+    let mut config = read_table(path)?;
+    apply(&mut config);
+
+    config
+        .try_into()
+        .with_context(|| format!("Failed to read {}.", path.display()))
+}
+
+fn read_table(path: &Path) -> Result<Table> {
+    std::fs::read_to_string(path)?
+        .parse()
+        .with_context(|| format!("Failed to parse {}.", path.display()))
+}
+
+/// Runs the migrations `config` is missing and returns their descriptions.
+fn apply(config: &mut Table) -> Vec<&'static str> {
+    let version = config
+        .get("config_version")
+        .and_then(Value::as_integer)
+        .unwrap_or(0) as usize;
+
+    let pending = MIGRATIONS.get(version..).unwrap_or_default();
+    if pending.is_empty() {
+        return vec![];
+    }
+
+    for migration in pending {
+        (migration.apply)(config);
+    }
+    config.insert(
+        "config_version".into(),
+        Value::Integer(CONFIG_VERSION.into()),
+    );
+
+    pending.iter().map(|it| it.description).collect()
+}
+
+/// Colors used to be stored as free text, anything the color parser rejects would now fail
+/// loading the whole config.
+fn drop_invalid_colors(config: &mut Table) {
+    let Some(profiles) = config.get_mut("profiles").and_then(Value::as_array_mut) else {
+        return;
+    };
+
+    for profile in profiles.iter_mut().filter_map(Value::as_table_mut) {
+        let valid = match profile.get("color") {
+            Some(Value::String(color)) => Color::from_str(color).is_ok(),
+            Some(_) => false,
+            None => true,
+        };
+
+        if !valid {
+            profile.remove("color");
+        }
+    }
+}
+
+/// Profiles used to hold the endpoint IDs of the machine they were created on. The device names
+/// become the patterns, which match the same devices, and the IDs the bindings of this machine.
+fn split_bindings(config: &mut Table) {
+    let Some(profiles) = config.get_mut("profiles").and_then(Value::as_array_mut) else {
+        return;
+    };
+
+    let mut bindings = Table::new();
+    for profile in profiles.iter_mut().filter_map(Value::as_table_mut) {
+        let mut take = |key: &str| match profile.remove(key) {
+            Some(Value::String(it)) => it,
+            _ => String::new(),
+        };
+        let input_id = take("input_id");
+        let input_name = take("input_name");
+        let output_id = take("output_id");
+        let output_name = take("output_name");
+
+        profile.insert("input_pattern".into(), input_name.clone().into());
+        profile.insert("output_pattern".into(), output_name.clone().into());
+
+        if let Some(Value::String(name)) = profile.get("profile_name")
+            && !input_id.is_empty()
+            && !output_id.is_empty()
+        {
+            let binding: Table = [
+                ("input_id", input_id),
+                ("input_name", input_name),
+                ("output_id", output_id),
+                ("output_name", output_name),
+            ]
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), value.into()))
+            .collect();
+
+            bindings.insert(name.clone(), binding.into());
+        }
+    }
+
+    if !bindings.is_empty() {
+        let mut machines = Table::new();
+        machines.insert(config::hostname(), Value::Table(bindings));
+        config.insert("bindings".into(), Value::Table(machines));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    /// A config as the first release wrote it.
+    const BASELINE: &str = r##"installed = true
+
+[[profiles]]
+profile_id = 1
+profile_name = "Headset"
+input_id = "{0.0.1.00000000}.{input}"
+input_name = "Headset Microphone"
+output_id = "{0.0.0.00000000}.{output}"
+output_name = "Headset Earphone"
+color = "a shade of blue"
+
+[profiles.hotkey]
+modifier = "ALT"
+hotkey = "F1"
+
+[[profiles]]
+profile_id = 2
+profile_name = "Speakers"
+input_id = "{0.0.1.00000000}.{webcam}"
+input_name = "Webcam Microphone"
+output_id = "{0.0.0.00000000}.{speakers}"
+output_name = "Speakers"
+color = "#00FF00"
+
+[profiles.hotkey]
+hotkey = "F2"
+"##;
+
+    #[test]
+    fn migrates_a_baseline_config_so_it_loads() {
+        let dir = TempDir::new("migrations_baseline");
+        let path = dir.0.join("config.toml");
+        std::fs::write(&path, BASELINE).unwrap();
+
+        let mut snapshots = 0;
+        let applied = migrate_file(&path, || {
+            snapshots += 1;
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(applied.len(), MIGRATIONS.len());
+        assert_eq!(snapshots, 1);
+
+        let config = confy::load_path::<Config>(&path).unwrap();
+        assert_eq!(config.config_version, CONFIG_VERSION);
+        assert_eq!(config.profiles[0].input_pattern, "Headset Microphone");
+        assert_eq!(config.profiles[0].output_pattern, "Headset Earphone");
+        assert_eq!(config.profiles[0].color, None);
+        assert_eq!(config.profiles[1].color, Some("#00FF00".parse().unwrap()));
+
+        let binding = &config.bindings[&config::hostname()]["Speakers"];
+        assert_eq!(binding.input_id, "{0.0.1.00000000}.{webcam}");
+        assert_eq!(binding.output_name, "Speakers");
+    }
+
+    #[test]
+    fn leaves_a_current_config_alone() {
+        let mut config = Table::new();
+        config.insert(
+            "config_version".into(),
+            Value::Integer(CONFIG_VERSION.into()),
+        );
+
+        assert!(apply(&mut config).is_empty());
+    }
+}
@@ -0,0 +1,104 @@
+use anyhow::Result;
+use inquire::{Select, Text};
+
+use crate::config::NotificationDuration;
+use crate::paths;
+
+pub fn configure_notifications() -> Result<()> {
+    let mut config: crate::config::Config = paths::load_config()?;
+    let notifications = &mut config.notifications;
+
+    let options = vec![
+        NotifyOn::Always,
+        NotifyOn::Success,
+        NotifyOn::Failure,
+        NotifyOn::Never,
+    ];
+
+    let Some(notify_on) = Select::new("Show a notification after switching:", options)
+        .with_starting_cursor(match (notifications.on_success, notifications.on_failure) {
+            (true, true) => 0,
+            (true, false) => 1,
+            (false, true) => 2,
+            (false, false) => 3,
+        })
+        .prompt_skippable()?
+    else {
+        return Ok(());
+    };
+
+    (notifications.on_success, notifications.on_failure) = match notify_on {
+        NotifyOn::Always => (true, true),
+        NotifyOn::Success => (true, false),
+        NotifyOn::Failure => (false, true),
+        NotifyOn::Never => (false, false),
+    };
+
+    if notify_on != NotifyOn::Never {
+        notifications.duration = Select::new(
+            "Notification duration:",
+            vec![NotificationDuration::Short, NotificationDuration::Long],
+        )
+        .with_starting_cursor(match notifications.duration {
+            NotificationDuration::Short => 0,
+            NotificationDuration::Long => 1,
+        })
+        .prompt()?;
+
+        let help = "Placeholders: {profile}, {input}, {output}";
+
+        if notifications.on_success {
+            notifications.success_title = Text::new("Success title:")
+                .with_initial_value(&notifications.success_title)
+                .with_help_message(help)
+                .prompt()?;
+            notifications.success_body = Text::new("Success message:")
+                .with_initial_value(&notifications.success_body)
+                .with_help_message(help)
+                .prompt()?;
+        }
+
+        if notifications.on_failure {
+            notifications.failure_title = Text::new("Failure title:")
+                .with_initial_value(&notifications.failure_title)
+                .with_help_message(help)
+                .prompt()?;
+            notifications.failure_body = Text::new("Failure message:")
+                .with_initial_value(&notifications.failure_body)
+                .with_help_message(help)
+                .prompt()?;
+        }
+    }
+
+    config.store()?;
+
+    Ok(())
+}
+
+#[derive(Debug, PartialEq)]
+enum NotifyOn {
+    Always,
+    Success,
+    Failure,
+    Never,
+}
+
+impl std::fmt::Display for NotifyOn {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            NotifyOn::Always => write!(f, "Always."),
+            NotifyOn::Success => write!(f, "Only when the switch succeeded."),
+            NotifyOn::Failure => write!(f, "Only when the switch failed."),
+            NotifyOn::Never => write!(f, "Never."),
+        }
+    }
+}
+
+impl std::fmt::Display for NotificationDuration {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            NotificationDuration::Short => write!(f, "Short (7 seconds)."),
+            NotificationDuration::Long => write!(f, "Long (25 seconds)."),
+        }
+    }
+}
@@ -3,51 +3,34 @@ use inquire::validator::Validation;
 use inquire::{Confirm, Select, Text};
 use win_hotkey::keys::VirtualKey;
 
-use crate::devices::{DeviceType, enumerate_devices};
+use crate::color::Color;
+use crate::devices::{self, Device, DeviceType, enumerate_devices};
 use crate::hotkeys::Hotkey;
+use crate::paths;
 
 pub fn new_profile() -> Result<()> {
     let profile_name_validator = |input: &str| {
-        let config: crate::config::Config = confy::load("AudioSwitch", None)?;
+        let config: crate::config::Config = paths::load_config()?;
 
-        if config
-            .profiles
-            .iter()
-            .any(|it| *it.profile_name == input.to_string())
-        {
+        if config.profiles.iter().any(|it| it.profile_name == input) {
             Ok(Validation::Invalid("Profile name already used.".into()))
-        } else if input == "" {
+        } else if input.is_empty() {
             Ok(Validation::Invalid("Profile name can't be empty.".into()))
         } else {
             Ok(Validation::Valid)
         }
     };
 
-    let color_validator = |input: &str| {
-        let hex_chars: [char; 16] = [
-        '0', '1', '2', '3', '4', '5', '6', '7',
-        '8', '9', 'a', 'b', 'c', 'd', 'e', 'f'
-        ];
-
-        if !input.starts_with("#") {
-            Ok(Validation::Invalid(
-                "Invalid color code! Hex code needs to start with a #.".into(),
-            ))
-        } else if input.len() != 7 {
-            Ok(Validation::Invalid(
-                "Invalid color code! Code needs to be 7 characters long.".into(),
-            ))
-        } else {
-            for c in input.trim_start_matches("#").to_ascii_lowercase().chars() {
-                if !hex_chars.contains(&c) {
-                    println!("{}", c);
-                    return Ok(Validation::Invalid(
-                        "Invalid color code! Invalid Hex value, valid characters are: [0-9A-F]".into(),
-                    ));
-                }
-            }
-            Ok(Validation::Valid)
-        }
+    let color_validator = |input: &str| match input.parse::<Color>() {
+        Ok(_) => Ok(Validation::Valid),
+        Err(e) => Ok(Validation::Invalid(
+            format!("Invalid color code! {e}").into(),
+        )),
+    };
+
+    let color_formatter = |input: &str| match input.parse::<Color>() {
+        Ok(color) => format!("{} {}", swatch(color), color),
+        Err(_) => input.to_string(),
     };
 
     let available_devices = enumerate_devices()?;
@@ -57,10 +40,22 @@ pub fn new_profile() -> Result<()> {
         .prompt()
         .context("No profile name specified.")?;
 
-    let color = Text::new("Enter a hex color code (eg. #FFFFFF). The color is assigned to the tray icon when the profile is active.")
-        .with_validator(color_validator)
-        .prompt()
-        .context("No color specified.")?;
+    // The answer is shown with a swatch of the color, which is confirmed before it is used.
+    let mut input = String::new();
+    let color: Color = loop {
+        let answer = Text::new("Enter a color (eg. #FFFFFF). The color is assigned to the tray icon when the profile is active.")
+            .with_help_message("Accepts #RGB, #RRGGBB, #RRGGBBAA, rgb(…), hsl(…) or a CSS color name like 'teal'.")
+            .with_initial_value(&input)
+            .with_validator(color_validator)
+            .with_formatter(&color_formatter)
+            .prompt()
+            .context("No color specified.")?;
+
+        if Confirm::new("Use this color? (y/n)").prompt()? {
+            break answer.parse()?;
+        }
+        input = answer;
+    };
 
     let output_device = Select::new(
         "Select output device:",
@@ -82,7 +77,7 @@ pub fn new_profile() -> Result<()> {
     .prompt()
     .context("No input device selected")?;
 
-    let mut config: crate::config::Config = confy::load("AudioSwitch", None)?;
+    let mut config: crate::config::Config = paths::load_config()?;
 
     #[allow(unused_assignments)]
     let mut hotkey = Hotkey {
@@ -105,28 +100,26 @@ pub fn new_profile() -> Result<()> {
             }
         }
 
-        if let Some(ref h) = config.next_profile {
-            if h.modifier == hotkey.modifier.map(|i| i.to_string())
-                && h.hotkey == hotkey.main_key.to_string()
-            {
-                println!(
-                    "Hotkey: {} already in use for 'next profile' switch.",
-                    hotkey
-                );
-                continue 'outer;
-            }
+        if let Some(ref h) = config.next_profile
+            && h.modifier == hotkey.modifier.map(|i| i.to_string())
+            && h.hotkey == hotkey.main_key.to_string()
+        {
+            println!(
+                "Hotkey: {} already in use for 'next profile' switch.",
+                hotkey
+            );
+            continue 'outer;
         }
 
-        if let Some(ref h) = config.previous_profile {
-            if h.modifier == hotkey.modifier.map(|i| i.to_string())
-                && h.hotkey == hotkey.main_key.to_string()
-            {
-                println!(
-                    "Hotkey: {} already in use for 'previous profile' switch.",
-                    hotkey
-                );
-                continue 'outer;
-            }
+        if let Some(ref h) = config.previous_profile
+            && h.modifier == hotkey.modifier.map(|i| i.to_string())
+            && h.hotkey == hotkey.main_key.to_string()
+        {
+            println!(
+                "Hotkey: {} already in use for 'previous profile' switch.",
+                hotkey
+            );
+            continue 'outer;
         }
 
         break;
@@ -135,8 +128,8 @@ pub fn new_profile() -> Result<()> {
     let profile = crate::config::Profile::default()
         .set_profile_id(0)
         .set_profile_name(&profile_name)
-        .set_input_device(&input_device.device_id, &input_device.name)
-        .set_output_device(&output_device.device_id, &output_device.name)
+        .set_input_pattern(&input_device.name)
+        .set_output_pattern(&output_device.name)
         .set_hotkey(
             hotkey.modifier.map(|i| i.to_string()),
             hotkey.main_key.to_string(),
@@ -144,14 +137,15 @@ pub fn new_profile() -> Result<()> {
         .set_profile_color(color);
 
     config.profiles.push(profile);
+    config.bind(&profile_name, devices::binding(input_device, output_device));
 
-    confy::store("AudioSwitch", None, config)?;
+    config.store()?;
 
     Ok(())
 }
 
 pub fn delete_profile() -> Result<()> {
-    let mut config: crate::config::Config = confy::load("AudioSwitch", None)?;
+    let mut config: crate::config::Config = paths::load_config()?;
 
     let mut profiles = Vec::new();
 
@@ -167,8 +161,7 @@ pub fn delete_profile() -> Result<()> {
             config
                 .profiles
                 .iter()
-                .filter(|i| i.profile_name == profile_to_delete)
-                .last()
+                .rfind(|i| i.profile_name == profile_to_delete)
                 .expect("Profile does not exist.")
         );
 
@@ -178,15 +171,98 @@ pub fn delete_profile() -> Result<()> {
         ))
         .prompt()?
         {
-            config.profiles = config
-                .profiles
-                .into_iter()
-                .filter(|it| it.profile_name != profile_to_delete)
-                .collect();
+            config.remove_profile(&profile_to_delete);
         }
 
-        confy::store("AudioSwitch", None, config)?;
+        config.store()?;
     };
 
     Ok(())
 }
+
+/// Asks for the devices of the profiles that have none on this machine yet, like profiles
+/// created on another machine. Returns whether a profile was bound.
+pub fn bind_unbound_profiles() -> Result<bool> {
+    let mut config: crate::config::Config = paths::load_config()?;
+
+    let unbound: Vec<_> = config
+        .profiles
+        .iter()
+        .filter(|it| config.binding(&it.profile_name).is_none())
+        .cloned()
+        .collect();
+
+    if unbound.is_empty()
+        || !Confirm::new(&format!(
+            "{} profiles have no devices on this machine yet. Choose them now? (y/n)",
+            unbound.len()
+        ))
+        .prompt()?
+    {
+        return Ok(false);
+    }
+
+    let available_devices = enumerate_devices()?;
+    let mut bound = false;
+
+    for profile in unbound {
+        println!("{profile}");
+
+        let Some(output) = select_device(
+            &profile.output_pattern,
+            DeviceType::Output,
+            &available_devices,
+        )?
+        else {
+            continue;
+        };
+        let Some(input) = select_device(
+            &profile.input_pattern,
+            DeviceType::Input,
+            &available_devices,
+        )?
+        else {
+            continue;
+        };
+
+        config.bind(&profile.profile_name, devices::binding(input, output));
+        bound = true;
+    }
+
+    if bound {
+        config.store()?;
+    }
+
+    Ok(bound)
+}
+
+/// Lets the user pick a device of `device_type`, the ones matching `pattern` are listed first.
+fn select_device<'a>(
+    pattern: &str,
+    device_type: DeviceType,
+    available_devices: &'a [Device],
+) -> Result<Option<&'a Device>> {
+    let (mut candidates, others): (Vec<&Device>, Vec<&Device>) = available_devices
+        .iter()
+        .filter(|it| it.device_type == device_type)
+        .partition(|it| devices::matches_pattern(pattern, &it.name));
+    candidates.extend(others);
+
+    if candidates.is_empty() {
+        return Ok(None);
+    }
+
+    Ok(
+        Select::new(&format!("Select the {device_type} device:"), candidates)
+            .with_help_message("Press Esc to leave the profile unbound...")
+            .prompt_skippable()?,
+    )
+}
+
+/// Renders `color` as a small block using 24-bit terminal colors.
+fn swatch(color: Color) -> String {
+    format!(
+        "\x1b[48;2;{};{};{}m      \x1b[0m",
+        color.r, color.g, color.b
+    )
+}
@@ -0,0 +1,500 @@
+use std::borrow::Cow;
+use std::collections::BTreeSet;
+use std::ops::Range;
+use std::path::Path;
+
+use anyhow::{Result, bail};
+use jsonschema::error::ValidationErrorKind;
+use schemars::transform::{RecursiveTransform, Transform};
+use schemars::{JsonSchema, Schema, SchemaGenerator, json_schema};
+use serde::Deserialize;
+use serde::de::{self, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde_json::Value;
+use toml::Spanned;
+use win_hotkey::keys::VirtualKey;
+
+use crate::color::{Color, NAMED_COLORS};
+use crate::config::Config;
+use crate::{hotkeys, paths};
+
+/// Modifier names the service understands: all spellings of [`hotkeys::MODIFIER_KEYS`], and
+/// `NO_REPEAT` and `NON` which add no modifier key.
+const MODIFIER_NAMES: [&str; 10] = [
+    "ALT",
+    "CTRL",
+    "CONTROL",
+    "SHIFT",
+    "WIN",
+    "WINDOWS",
+    "SUPER",
+    "NOREPEAT",
+    "NO_REPEAT",
+    "NON",
+];
+
+/// The name of a hotkey's key, in the schema only.
+pub struct KeyName;
+
+impl JsonSchema for KeyName {
+    fn inline_schema() -> bool {
+        true
+    }
+
+    fn schema_name() -> Cow<'static, str> {
+        "KeyName".into()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        // The setup writes keys as `VirtualKey` displays them, e.g. `VK_F1` or `0x7` for codes
+        // without a name, the service also accepts the names they are picked by.
+        let names: BTreeSet<_> = hotkeys::key_names()
+            .into_iter()
+            .chain((1..=0xFE).map(|code| VirtualKey::CustomKeyCode(code).to_string()))
+            .collect();
+
+        json_schema!({
+            "type": "string",
+            "description": "A digit, letter, key name like VK_F1 or VK_SPACE, or key code like 0x7b.",
+            "enum": names,
+        })
+    }
+}
+
+/// The name of a hotkey's modifier, in the schema only.
+pub struct ModifierName;
+
+impl JsonSchema for ModifierName {
+    fn inline_schema() -> bool {
+        true
+    }
+
+    fn schema_name() -> Cow<'static, str> {
+        "ModifierName".into()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        json_schema!({
+            "type": "string",
+            "enum": MODIFIER_NAMES,
+        })
+    }
+}
+
+impl JsonSchema for Color {
+    fn inline_schema() -> bool {
+        true
+    }
+
+    fn schema_name() -> Cow<'static, str> {
+        "Color".into()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        let names: Vec<_> = NAMED_COLORS.iter().map(|(name, _)| *name).collect();
+        let pattern = format!(
+            r"^(#([0-9a-fA-F]{{3,4}}|[0-9a-fA-F]{{6}}|[0-9a-fA-F]{{8}})|(rgba?|hsla?)\([^()]*\)|{})$",
+            names.join("|")
+        );
+
+        json_schema!({
+            "type": "string",
+            "description": "A color like #ff8800, #f80, rgb(255, 136, 0), hsl(32, 100%, 50%) or orange.",
+            "pattern": pattern,
+        })
+    }
+}
+
+/// JSON Schema of the config file.
+pub fn schema() -> Schema {
+    let mut schema = schemars::schema_for!(Config);
+    schema.insert("title".into(), "AudioSwitch configuration".into());
+    RecursiveTransform(remove_null).transform(&mut schema);
+    schema
+}
+
+/// Drops `null` from the allowed values, TOML has none and writes `None` by leaving it out.
+fn remove_null(schema: &mut Schema) {
+    let Some(object) = schema.as_object_mut() else {
+        return;
+    };
+
+    if let Some(Value::Array(types)) = object.get_mut("type") {
+        types.retain(|it| it != "null");
+        if let [single] = types.as_slice() {
+            let single = single.clone();
+            object.insert("type".into(), single);
+        }
+    }
+    if let Some(Value::Array(values)) = object.get_mut("enum") {
+        values.retain(|it| !it.is_null());
+    }
+    if object.get("default").is_some_and(Value::is_null) {
+        object.remove("default");
+    }
+
+    // `Option<T>` of a referenced `T` is `anyOf` the reference and null.
+    if let Some(Value::Array(variants)) = object.get("anyOf") {
+        let rest: Vec<_> = variants
+            .iter()
+            .filter(|it| it.get("type").is_none_or(|it| it != "null"))
+            .cloned()
+            .collect();
+        if let [Value::Object(single)] = rest.as_slice() {
+            object.remove("anyOf");
+            for (key, value) in single {
+                object.entry(key.clone()).or_insert(value.clone());
+            }
+        }
+    }
+}
+
+/// Prints the JSON Schema of the config file.
+pub fn print() -> Result<()> {
+    println!("{}", serde_json::to_string_pretty(&schema())?);
+    Ok(())
+}
+
+/// Checks the config file at `path`, or the current one, against the schema and prints every
+/// problem with its line and column. Fails if there are any.
+pub fn lint(path: Option<&Path>) -> Result<()> {
+    let path = match path {
+        Some(path) => path.to_path_buf(),
+        None => paths::config_path()?,
+    };
+    let text = std::fs::read_to_string(&path)?;
+
+    let problems = problems(&text)?;
+    for (span, message) in &problems {
+        let (line, column) = line_column(&text, span.start);
+        println!("{}:{line}:{column}: {message}", path.display());
+    }
+
+    if !problems.is_empty() {
+        let plural = if problems.len() == 1 { "" } else { "s" };
+        bail!(
+            "Found {} problem{plural} in {}.",
+            problems.len(),
+            path.display()
+        );
+    }
+
+    println!("    {} is valid.", path.display());
+    Ok(())
+}
+
+/// Where in `text` it breaks the schema, and how.
+fn problems(text: &str) -> Result<Vec<(Range<usize>, String)>> {
+    let document = match toml::from_str::<Spanned<Node>>(text) {
+        Ok(document) => document,
+        Err(err) => return Ok(vec![(err.span().unwrap_or_default(), err.message().into())]),
+    };
+    let mut instance = serde_json::to_value(toml::from_str::<toml::Table>(text)?)?;
+    normalize_hotkeys(&mut instance);
+
+    let validator = jsonschema::validator_for(&serde_json::to_value(schema())?)?;
+
+    let mut problems: Vec<_> = validator
+        .iter_errors(&instance)
+        .map(|error| {
+            let mut pointer: Vec<_> = error
+                .instance_path
+                .as_str()
+                .split('/')
+                .skip(1)
+                .map(|it| it.replace("~1", "/").replace("~0", "~"))
+                .collect();
+
+            let message = match &error.kind {
+                ValidationErrorKind::AdditionalProperties { unexpected } => {
+                    pointer.extend(unexpected.first().cloned());
+                    format!("Unknown setting \"{}\".", unexpected.join("\", \""))
+                }
+                ValidationErrorKind::Enum { options } => match options.as_array() {
+                    Some(options) if options.len() <= MODIFIER_NAMES.len() => {
+                        format!("{} is not one of {}.", error.instance, join(options))
+                    }
+                    _ => format!("{} is not a known key name.", error.instance),
+                },
+                // Colors are the only values with a pattern, which is too long to show.
+                ValidationErrorKind::Pattern { .. } => {
+                    format!("{} is not a color.", error.instance)
+                }
+                _ => format!("{error}."),
+            };
+
+            (locate(&document, &pointer), message)
+        })
+        .collect();
+
+    problems.sort_by_key(|(span, _)| span.start);
+    Ok(problems)
+}
+
+/// Rewrites the hotkeys the way the service reads them, e.g. `ctrl` to `CONTROL`, `f1` to
+/// `VK_F1` and `0x7B` to `VK_F12`, so the schema needs to list every key only once. Names the
+/// service doesn't know are left for the schema to report.
+fn normalize_hotkeys(instance: &mut Value) {
+    let Some(config) = instance.as_object_mut() else {
+        return;
+    };
+
+    for (setting, value) in config.iter_mut() {
+        match setting.as_str() {
+            "next_profile" | "previous_profile" => normalize_hotkey(value),
+            "profiles" => {
+                let hotkeys = value.as_array_mut().into_iter().flatten();
+                for hotkey in hotkeys.filter_map(|it| it.get_mut("hotkey")) {
+                    normalize_hotkey(hotkey);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn normalize_hotkey(hotkey: &mut Value) {
+    if let Some(Value::String(name)) = hotkey.get_mut("hotkey")
+        && let Ok(key) = hotkeys::parse_key(name)
+    {
+        *name = key.to_string();
+    }
+    if let Some(Value::String(name)) = hotkey.get_mut("modifier")
+        && let Ok(modifier) = hotkeys::parse_modifier(name)
+    {
+        *name = modifier.to_string();
+    }
+}
+
+fn join(values: &[Value]) -> String {
+    values
+        .iter()
+        .map(|it| it.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// A TOML value that remembers where its parts are.
+enum Node {
+    Table(Vec<(Spanned<String>, Spanned<Node>)>),
+    Array(Vec<Spanned<Node>>),
+    Value,
+}
+
+impl<'de> Deserialize<'de> for Node {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct NodeVisitor;
+
+        impl<'de> Visitor<'de> for NodeVisitor {
+            type Value = Node;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("a TOML value")
+            }
+
+            fn visit_bool<E: de::Error>(self, _: bool) -> Result<Node, E> {
+                Ok(Node::Value)
+            }
+
+            fn visit_i64<E: de::Error>(self, _: i64) -> Result<Node, E> {
+                Ok(Node::Value)
+            }
+
+            fn visit_u64<E: de::Error>(self, _: u64) -> Result<Node, E> {
+                Ok(Node::Value)
+            }
+
+            fn visit_f64<E: de::Error>(self, _: f64) -> Result<Node, E> {
+                Ok(Node::Value)
+            }
+
+            fn visit_str<E: de::Error>(self, _: &str) -> Result<Node, E> {
+                Ok(Node::Value)
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Node, A::Error> {
+                let mut items = vec![];
+                while let Some(item) = seq.next_element()? {
+                    items.push(item);
+                }
+                Ok(Node::Array(items))
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Node, A::Error> {
+                let mut entries = vec![];
+                while let Some(entry) = map.next_entry()? {
+                    entries.push(entry);
+                }
+                Ok(Node::Table(entries))
+            }
+        }
+
+        deserializer.deserialize_any(NodeVisitor)
+    }
+}
+
+/// Span of the value at `pointer`, or of its key if it has one. Stops at the deepest part that
+/// exists, e.g. the table a required setting is missing from.
+fn locate(document: &Spanned<Node>, pointer: &[String]) -> Range<usize> {
+    let mut node = document;
+    let mut span = document.span();
+
+    for segment in pointer {
+        let next = match node.get_ref() {
+            Node::Table(entries) => entries
+                .iter()
+                .find(|(key, _)| key.get_ref() == segment)
+                .map(|(key, value)| (key.span(), value)),
+            Node::Array(items) => segment
+                .parse::<usize>()
+                .ok()
+                .and_then(|index| items.get(index))
+                .map(|item| (item.span(), item)),
+            Node::Value => None,
+        };
+
+        let Some((next_span, next)) = next else {
+            break;
+        };
+        span = next_span;
+        node = next;
+    }
+
+    span
+}
+
+/// 1-based line and column of byte `offset` in `text`.
+fn line_column(text: &str, offset: usize) -> (usize, usize) {
+    let before = &text[..offset.min(text.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before
+        .rsplit('\n')
+        .next()
+        .unwrap_or_default()
+        .chars()
+        .count()
+        + 1;
+
+    (line, column)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VALID: &str = r##"installed = true
+
+[next_profile]
+modifier = "ctrl"
+hotkey = "f1"
+
+[previous_profile]
+hotkey = "0x7B"
+
+[[profiles]]
+profile_id = 1
+profile_name = "Speakers"
+input_pattern = "Microphone"
+output_pattern = "Speakers"
+hotkey = { modifier = "Shift", hotkey = "VK_A" }
+color = "orange"
+"##;
+
+    fn messages(text: &str) -> Vec<(usize, usize, String)> {
+        problems(text)
+            .unwrap()
+            .into_iter()
+            .map(|(span, message)| {
+                let (line, column) = line_column(text, span.start);
+                (line, column, message)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn accepts_every_spelling_the_service_reads() {
+        assert_eq!(messages(VALID), vec![]);
+    }
+
+    #[test]
+    fn reports_unknown_keys_and_modifiers() {
+        let text = VALID
+            .replace(r#"modifier = "ctrl""#, r#"modifier = "hyper""#)
+            .replace(r#"hotkey = "0x7B""#, r#"hotkey = "F99""#);
+
+        assert_eq!(
+            messages(&text),
+            vec![
+                (
+                    4,
+                    1,
+                    format!(
+                        "\"hyper\" is not one of {}.",
+                        MODIFIER_NAMES.map(|it| format!("\"{it}\"")).join(", ")
+                    )
+                ),
+                (8, 1, "\"F99\" is not a known key name.".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_unknown_settings_and_colors() {
+        let text = VALID
+            .replace(r#"color = "orange""#, r##"color = "#12""##)
+            .replace("installed = true", "installed = true\ncolour = \"red\"");
+
+        assert_eq!(
+            messages(&text),
+            vec![
+                (2, 1, "Unknown setting \"colour\".".into()),
+                (17, 1, "\"#12\" is not a color.".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_syntax_errors() {
+        let problems = messages("installed = true\nprofiles = [\n");
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].0, 3);
+    }
+
+    fn located<'a>(text: &'a str, pointer: &[&str]) -> &'a str {
+        let document = toml::from_str::<Spanned<Node>>(text).unwrap();
+        let pointer: Vec<_> = pointer.iter().map(|it| it.to_string()).collect();
+        &text[locate(&document, &pointer)]
+    }
+
+    #[test]
+    fn locates_the_key_of_a_setting() {
+        let text = "[[profiles]]\nprofile_name = \"a\"\n\n[[profiles]]\nprofile_name = \"b\"\n";
+        let span = {
+            let document = toml::from_str::<Spanned<Node>>(text).unwrap();
+            locate(
+                &document,
+                &["profiles".into(), "1".into(), "profile_name".into()],
+            )
+        };
+
+        assert_eq!(&text[span.clone()], "profile_name");
+        assert_eq!(line_column(text, span.start), (5, 1));
+    }
+
+    #[test]
+    fn locates_the_deepest_existing_part() {
+        let text = "installed = true\n\n[next_profile]\nmodifier = \"ALT\"\n";
+
+        assert_eq!(located(text, &["next_profile", "hotkey"]), "next_profile");
+        assert_eq!(located(text, &["installed", "nested"]), "installed");
+    }
+
+    #[test]
+    fn counts_lines_and_characters() {
+        let text = "a = 1\nb = \"é\"x\n";
+
+        assert_eq!(line_column(text, 0), (1, 1));
+        assert_eq!(line_column(text, 6), (2, 1));
+        assert_eq!(line_column(text, text.find('x').unwrap()), (2, 8));
+        assert_eq!(line_column(text, text.len() + 10), (3, 1));
+    }
+}
@@ -0,0 +1,120 @@
+use std::ffi::OsString;
+use std::io::{self, Write};
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+
+use anyhow::Result;
+use sysinfo::System;
+use windows::Win32::Foundation::{CloseHandle, WAIT_OBJECT_0};
+use windows::Win32::System::Threading::{OpenProcess, PROCESS_SYNCHRONIZE, WaitForSingleObject};
+
+use crate::{ipc, paths};
+
+/// Starts the service under its supervisor, which restarts it after crashes.
+pub const SUPERVISE_ARG: &str = "--supervise";
+
+/// Arguments of the autostart task. A config file given to the setup is passed on, so the
+/// service uses it too.
+pub fn task_arguments() -> String {
+    match paths::config_override() {
+        Some(path) => format!(
+            "{SUPERVISE_ARG} {} \"{}\"",
+            paths::CONFIG_ARG,
+            path.display()
+        ),
+        None => SUPERVISE_ARG.to_string(),
+    }
+}
+
+/// How long the service gets to unregister its hotkeys and remove the tray icon.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long to wait for a killed service to disappear.
+const KILL_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Asks the running service to shut down and waits for it and its supervisor to exit. A
+/// process that doesn't answer or exit in time, e.g. a service without the shutdown request, is
+/// killed. Returns whether a service was running.
+pub fn stop_service() -> Result<bool> {
+    let mut sys = System::new_all();
+    sys.refresh_all();
+    let service_name = OsString::from_str("AudioSwitchService.exe")?;
+
+    let mut processes: Vec<_> = sys.processes_by_exact_name(&service_name).collect();
+    if processes.is_empty() {
+        return Ok(false);
+    }
+
+    // The supervisor exits once the service shut down cleanly. If it has to be killed, that
+    // must happen first, or it restarts the killed service.
+    processes.sort_by_key(|p| !p.cmd().iter().any(|it| it == SUPERVISE_ARG));
+
+    print!("    Stopping running Service...");
+    io::stdout().flush().expect("Failed to flush stdout");
+
+    let requested = ipc::send(&ipc::Request::Shutdown).is_ok();
+
+    for p in processes {
+        let pid = p.pid().as_u32();
+
+        if !(requested && wait_for_exit(pid, SHUTDOWN_TIMEOUT)) {
+            p.kill();
+            wait_for_exit(pid, KILL_TIMEOUT);
+        }
+    }
+
+    println!(" Done ✔");
+
+    Ok(true)
+}
+
+/// Whether a service process is running.
+pub fn is_running() -> Result<bool> {
+    let mut sys = System::new_all();
+    sys.refresh_all();
+    let service_name = OsString::from_str("AudioSwitchService.exe")?;
+
+    Ok(sys.processes_by_exact_name(&service_name).next().is_some())
+}
+
+/// Stops the running service, if there is one, and starts it again from `service_file_path`.
+pub fn restart_service(service_file_path: &Path) -> Result<()> {
+    if stop_service()? {
+        start_service(service_file_path)?;
+    }
+
+    Ok(())
+}
+
+/// Starts the service at `service_file_path` under its supervisor.
+pub fn start_service(service_file_path: &Path) -> Result<()> {
+    print!("    Starting Service...");
+    io::stdout().flush().expect("Failed to flush stdout");
+
+    let mut command = std::process::Command::new(service_file_path);
+    command.arg(SUPERVISE_ARG);
+    if let Some(path) = paths::config_override() {
+        command.arg(paths::CONFIG_ARG).arg(path);
+    }
+    command.spawn()?;
+
+    println!(" Done ✔");
+
+    Ok(())
+}
+
+/// Waits until the process with `pid` has exited. Returns `false` on timeout.
+fn wait_for_exit(pid: u32, timeout: Duration) -> bool {
+    unsafe {
+        // The process is already gone if it can't be opened.
+        let Ok(process) = OpenProcess(PROCESS_SYNCHRONIZE, false, pid) else {
+            return true;
+        };
+
+        let result = WaitForSingleObject(process, timeout.as_millis() as u32);
+        let _ = CloseHandle(process);
+
+        result == WAIT_OBJECT_0
+    }
+}
@@ -0,0 +1,622 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Result, bail};
+use inquire::{Select, Text};
+
+use crate::config::{self, Config, DeviceBinding, HotkeyConfig, Profile};
+use crate::migrations;
+use crate::paths;
+use crate::shared::{self, Changed, Portable, SHARED_FILE, Shared};
+
+/// One entry of the shared profiles, the unit changes are merged in.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum Key {
+    Profile(String),
+    Next,
+    Previous,
+    Binding { host: String, profile: String },
+}
+
+impl std::fmt::Display for Key {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Key::Profile(name) => write!(f, "Profile \"{name}\""),
+            Key::Next => write!(f, "The next profile hotkey"),
+            Key::Previous => write!(f, "The previous profile hotkey"),
+            Key::Binding { host, profile } => {
+                write!(f, "The devices of profile \"{profile}\" on {host}")
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Item {
+    Profile(Profile),
+    Hotkey(HotkeyConfig),
+    Binding(DeviceBinding),
+}
+
+impl std::fmt::Display for Item {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Item::Profile(profile) => write!(
+                f,
+                "hotkey {}, output \"{}\", input \"{}\"",
+                profile.hotkey, profile.output_pattern, profile.input_pattern
+            ),
+            Item::Hotkey(hotkey) => write!(f, "{hotkey}"),
+            Item::Binding(binding) => write!(f, "{} / {}", binding.output_name, binding.input_name),
+        }
+    }
+}
+
+fn entries(portable: &Portable) -> BTreeMap<Key, Item> {
+    let mut entries = BTreeMap::new();
+
+    for profile in &portable.profiles {
+        entries.insert(
+            Key::Profile(profile.profile_name.clone()),
+            Item::Profile(profile.clone()),
+        );
+    }
+    if let Some(hotkey) = &portable.next_profile {
+        entries.insert(Key::Next, Item::Hotkey(hotkey.clone()));
+    }
+    if let Some(hotkey) = &portable.previous_profile {
+        entries.insert(Key::Previous, Item::Hotkey(hotkey.clone()));
+    }
+    for (host, bindings) in &portable.bindings {
+        for (profile, binding) in bindings {
+            entries.insert(
+                Key::Binding {
+                    host: host.clone(),
+                    profile: profile.clone(),
+                },
+                Item::Binding(binding.clone()),
+            );
+        }
+    }
+
+    entries
+}
+
+/// Puts merged entries back together. Profiles keep the order of `ours`, profiles only
+/// `theirs` has follow in their order.
+fn portable(mut entries: BTreeMap<Key, Item>, ours: &Portable, theirs: &Portable) -> Portable {
+    let mut portable = Portable::default();
+
+    for profile in ours.profiles.iter().chain(&theirs.profiles) {
+        if let Some(Item::Profile(profile)) =
+            entries.remove(&Key::Profile(profile.profile_name.clone()))
+        {
+            portable.profiles.push(profile);
+        }
+    }
+
+    for (key, item) in entries {
+        match (key, item) {
+            (Key::Next, Item::Hotkey(hotkey)) => portable.next_profile = Some(hotkey),
+            (Key::Previous, Item::Hotkey(hotkey)) => portable.previous_profile = Some(hotkey),
+            (Key::Binding { host, profile }, Item::Binding(binding)) => {
+                portable
+                    .bindings
+                    .entry(host)
+                    .or_default()
+                    .insert(profile, binding);
+            }
+            _ => {}
+        }
+    }
+
+    portable
+}
+
+impl Changed {
+    fn get(&self, key: &Key) -> u64 {
+        match key {
+            Key::Profile(name) => self.profiles.get(name).copied().unwrap_or_default(),
+            Key::Next => self.next_profile,
+            Key::Previous => self.previous_profile,
+            Key::Binding { host, profile } => self
+                .bindings
+                .get(host)
+                .and_then(|it| it.get(profile))
+                .copied()
+                .unwrap_or_default(),
+        }
+    }
+
+    fn set(&mut self, key: &Key, revision: u64) {
+        match key {
+            Key::Profile(name) => {
+                self.profiles.insert(name.clone(), revision);
+            }
+            Key::Next => self.next_profile = revision,
+            Key::Previous => self.previous_profile = revision,
+            Key::Binding { host, profile } => {
+                self.bindings
+                    .entry(host.clone())
+                    .or_default()
+                    .insert(profile.clone(), revision);
+            }
+        }
+    }
+}
+
+/// An entry both sides changed in different ways.
+struct Conflict {
+    key: Key,
+    ours: Option<Item>,
+    theirs: Option<Item>,
+}
+
+/// Combines two versions entry by entry. `changed` tells whether a side changed an entry since
+/// their common ancestor, `true` for ours and `false` for theirs.
+fn merge(
+    ours: &Portable,
+    theirs: &Portable,
+    changed: impl Fn(&Key, bool) -> bool,
+) -> (BTreeMap<Key, Item>, Vec<Conflict>) {
+    let mut ours_entries = entries(ours);
+    let mut theirs_entries = entries(theirs);
+    let keys: BTreeSet<Key> = ours_entries
+        .keys()
+        .chain(theirs_entries.keys())
+        .cloned()
+        .collect();
+
+    let mut merged = BTreeMap::new();
+    let mut conflicts = vec![];
+
+    for key in keys {
+        let ours = ours_entries.remove(&key);
+        let theirs = theirs_entries.remove(&key);
+
+        let pick = if ours == theirs {
+            ours
+        } else {
+            match (changed(&key, true), changed(&key, false)) {
+                (true, false) => ours,
+                (false, true) => theirs,
+                (true, true) => {
+                    conflicts.push(Conflict { key, ours, theirs });
+                    continue;
+                }
+                // Neither side knows about the other's entry, e.g. one that predates syncing.
+                (false, false) => ours.or(theirs),
+            }
+        };
+
+        if let Some(item) = pick {
+            merged.insert(key, item);
+        }
+    }
+
+    (merged, conflicts)
+}
+
+/// Lets the user pick a side for every conflict and adds the choices to `merged`.
+fn resolve(
+    conflicts: Vec<Conflict>,
+    merged: &mut BTreeMap<Key, Item>,
+    ours_label: &str,
+    theirs_label: &str,
+) -> Result<()> {
+    let describe = |item: &Option<Item>| {
+        item.as_ref()
+            .map_or("removed".to_string(), |it| it.to_string())
+    };
+
+    for conflict in conflicts {
+        let keep_ours = format!("Keep {ours_label}'s version: {}", describe(&conflict.ours));
+        let keep_theirs = format!(
+            "Keep {theirs_label}'s version: {}",
+            describe(&conflict.theirs)
+        );
+
+        let choice = Select::new(
+            &format!(
+                "{} was changed on {ours_label} and on {theirs_label}:",
+                conflict.key
+            ),
+            vec![keep_ours.clone(), keep_theirs],
+        )
+        .prompt()?;
+
+        let pick = if choice == keep_ours {
+            conflict.ours
+        } else {
+            conflict.theirs
+        };
+        if let Some(item) = pick {
+            merged.insert(conflict.key, item);
+        }
+    }
+
+    Ok(())
+}
+
+/// Brings the profiles of this machine and the sync folder together. Changes made on either
+/// side are merged, entries changed on both sides are shown to the user to decide. Returns
+/// whether the profiles of this machine changed.
+pub fn run() -> Result<bool> {
+    let mut config: Config = paths::load_config()?;
+    let Some(dir) = config.sync_dir.clone() else {
+        return Ok(false);
+    };
+    let path = dir.join(SHARED_FILE);
+
+    loop {
+        let remote = shared::read(&path)?.unwrap_or_default();
+        if remote.config_version > migrations::CONFIG_VERSION {
+            bail!(
+                "The profiles in {} were written by a newer AudioSwitch, update this machine \
+                 first.",
+                dir.display()
+            );
+        }
+
+        let copies = shared::conflict_copies(&dir)?;
+        let theirs = merge_copies(&remote, &copies)?;
+
+        let base = shared::read(&shared::base_path()?)?.unwrap_or_default();
+        let ours = Portable::of(&config);
+        let (mut merged, conflicts) = merge_with_base(&ours, &theirs, &base.portable);
+
+        let written_by = if remote.written_by.is_empty() {
+            "the sync folder"
+        } else {
+            &remote.written_by
+        };
+        resolve(conflicts, &mut merged, "this machine", written_by)?;
+        let merged = portable(merged, &ours, &theirs);
+
+        let synced = if merged != remote.portable || !copies.is_empty() {
+            let Some(written) = write(&path, &remote, merged.clone())? else {
+                println!(
+                    "    The profiles were changed by another machine meanwhile, merging again."
+                );
+                continue;
+            };
+
+            for copy in &copies {
+                std::fs::remove_file(copy)?;
+            }
+            written
+        } else {
+            remote
+        };
+
+        std::fs::write(shared::base_path()?, toml::to_string_pretty(&synced)?)?;
+
+        let changed = merged != ours;
+        if changed {
+            merged.apply_to(&mut config);
+            config.store()?;
+        }
+
+        println!(
+            "    Syncing Profiles with {} (revision {})... Done ✔",
+            dir.display(),
+            synced.revision
+        );
+
+        return Ok(changed);
+    }
+}
+
+/// Three-way merge of what this machine and the sync folder changed since `base`, the sync
+/// folder's profiles as this machine last synced them.
+fn merge_with_base(
+    ours: &Portable,
+    theirs: &Portable,
+    base: &Portable,
+) -> (BTreeMap<Key, Item>, Vec<Conflict>) {
+    let base_entries = entries(base);
+    let (ours_entries, theirs_entries) = (entries(ours), entries(theirs));
+
+    merge(ours, theirs, |key, is_ours| {
+        let side = if is_ours {
+            &ours_entries
+        } else {
+            &theirs_entries
+        };
+        side.get(key) != base_entries.get(key)
+    })
+}
+
+/// Merges the conflicting copies a sync tool left into the profiles of `remote`.
+fn merge_copies(remote: &Shared, copies: &[PathBuf]) -> Result<Portable> {
+    let mut merged = remote.portable.clone();
+
+    for path in copies {
+        let Some(copy) = shared::read(path)? else {
+            continue;
+        };
+
+        let (mut entries, conflicts) = merge_copy(&merged, remote, &copy);
+
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+        println!("    Merging the conflicting copy {file_name}...");
+        resolve(
+            conflicts,
+            &mut entries,
+            "the sync folder",
+            &format!("{} ({file_name})", copy.written_by),
+        )?;
+
+        merged = portable(entries, &merged, &copy.portable);
+    }
+
+    Ok(merged)
+}
+
+/// Merges `copy` into `merged`, the profiles of `remote` with the copies before it merged in.
+/// Both descend from the older of the revisions they were based on, so whatever changed after
+/// it is a change.
+fn merge_copy(
+    merged: &Portable,
+    remote: &Shared,
+    copy: &Shared,
+) -> (BTreeMap<Key, Item>, Vec<Conflict>) {
+    let ancestor = remote.parent.min(copy.parent);
+
+    merge(merged, &copy.portable, |key, is_ours| {
+        let changed = if is_ours {
+            &remote.changed
+        } else {
+            &copy.changed
+        };
+        changed.get(key) > ancestor
+    })
+}
+
+/// Writes `portable` as the revision after `remote`. The file is written next to the target
+/// and renamed over it, so other machines never see half of it. Returns `None` without writing
+/// if another machine wrote a new revision in the meantime.
+fn write(path: &Path, remote: &Shared, portable: Portable) -> Result<Option<Shared>> {
+    let revision = remote.revision + 1;
+
+    let mut changed = remote.changed.clone();
+    let (before, after) = (entries(&remote.portable), entries(&portable));
+    for key in before.keys().chain(after.keys()) {
+        if before.get(key) != after.get(key) {
+            changed.set(key, revision);
+        }
+    }
+
+    let shared = Shared {
+        revision,
+        parent: remote.revision,
+        written_by: config::hostname(),
+        config_version: migrations::CONFIG_VERSION,
+        portable,
+        changed,
+    };
+
+    // Named so it is not mistaken for a conflicting copy.
+    let staging = path.with_file_name(format!(".{SHARED_FILE}.{}.tmp", config::hostname()));
+    std::fs::write(&staging, toml::to_string_pretty(&shared)?)?;
+
+    let current = shared::read(path)?.map_or(0, |it| it.revision);
+    if current != remote.revision {
+        std::fs::remove_file(&staging)?;
+        return Ok(None);
+    }
+
+    std::fs::rename(&staging, path)?;
+
+    Ok(Some(shared))
+}
+
+/// Asks for the folder to sync the profiles through and syncs them. Returns whether the
+/// profiles of this machine changed.
+pub fn configure() -> Result<bool> {
+    let config: Config = paths::load_config()?;
+
+    let current = config
+        .sync_dir
+        .as_ref()
+        .map(|it| it.display().to_string())
+        .unwrap_or_default();
+    let folder = Text::new("Folder to sync the profiles through:")
+        .with_initial_value(&current)
+        .with_help_message("E.g. a folder in OneDrive or Syncthing. Leave empty to stop syncing.")
+        .prompt()?;
+
+    set_folder((!folder.trim().is_empty()).then(|| PathBuf::from(folder.trim())))
+}
+
+/// Syncs the profiles through `folder` from now on, or stops syncing. Returns whether the
+/// profiles of this machine changed.
+pub fn set_folder(folder: Option<PathBuf>) -> Result<bool> {
+    let mut config: Config = paths::load_config()?;
+
+    if let Some(folder) = &folder {
+        std::fs::create_dir_all(folder)?;
+    }
+
+    // A new folder has nothing in common with what was synced before.
+    if config.sync_dir != folder {
+        let base = shared::base_path()?;
+        if base.exists() {
+            std::fs::remove_file(base)?;
+        }
+    }
+
+    config.sync_dir = folder;
+    let syncing = config.sync_dir.is_some();
+    config.store()?;
+
+    if !syncing {
+        println!("    Stopped syncing the profiles.");
+        return Ok(false);
+    }
+
+    run()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    fn profile(name: &str, hotkey: &str) -> Profile {
+        Profile {
+            profile_name: name.to_string(),
+            hotkey: HotkeyConfig {
+                modifier: None,
+                hotkey: hotkey.to_string(),
+            },
+            ..Profile::default()
+        }
+    }
+
+    fn with_profiles(profiles: &[Profile]) -> Portable {
+        Portable {
+            profiles: profiles.to_vec(),
+            ..Portable::default()
+        }
+    }
+
+    fn item(profile: &Profile) -> Item {
+        Item::Profile(profile.clone())
+    }
+
+    fn key(name: &str) -> Key {
+        Key::Profile(name.to_string())
+    }
+
+    #[test]
+    fn takes_changes_made_on_one_side() {
+        let base = with_profiles(&[profile("a", "A"), profile("b", "B")]);
+        let ours = with_profiles(&[profile("a", "F1"), profile("b", "B")]);
+        let theirs = with_profiles(&[profile("a", "A"), profile("b", "F2")]);
+
+        let (merged, conflicts) = merge_with_base(&ours, &theirs, &base);
+
+        assert!(conflicts.is_empty());
+        assert_eq!(merged[&key("a")], item(&profile("a", "F1")));
+        assert_eq!(merged[&key("b")], item(&profile("b", "F2")));
+    }
+
+    #[test]
+    fn takes_the_same_change_on_both_sides_once() {
+        let base = with_profiles(&[profile("a", "A")]);
+        let changed = with_profiles(&[profile("a", "F1"), profile("c", "C")]);
+
+        let (merged, conflicts) = merge_with_base(&changed, &changed, &base);
+
+        assert!(conflicts.is_empty());
+        assert_eq!(merged, entries(&changed));
+    }
+
+    #[test]
+    fn delete_against_edit_is_a_conflict() {
+        let base = with_profiles(&[profile("a", "A"), profile("b", "B")]);
+        let ours = with_profiles(&[profile("b", "B")]);
+        let theirs = with_profiles(&[profile("a", "F1"), profile("b", "B")]);
+
+        let (merged, conflicts) = merge_with_base(&ours, &theirs, &base);
+
+        assert!(!merged.contains_key(&key("a")));
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].key, key("a"));
+        assert_eq!(conflicts[0].ours, None);
+        assert_eq!(conflicts[0].theirs, Some(item(&profile("a", "F1"))));
+    }
+
+    #[test]
+    fn delete_on_one_side_is_kept() {
+        let base = with_profiles(&[profile("a", "A"), profile("b", "B")]);
+        let ours = with_profiles(&[profile("b", "B")]);
+
+        let (merged, conflicts) = merge_with_base(&ours, &base, &base);
+
+        assert!(conflicts.is_empty());
+        assert!(!merged.contains_key(&key("a")));
+    }
+
+    /// Revision `revision` written on top of `parent`, with each named profile last changed at
+    /// the given revision.
+    fn revision(
+        revision: u64,
+        parent: u64,
+        profiles: &[Profile],
+        changed: &[(&str, u64)],
+    ) -> Shared {
+        let mut stamps = Changed::default();
+        for (name, at) in changed {
+            stamps.set(&key(name), *at);
+        }
+
+        Shared {
+            revision,
+            parent,
+            portable: with_profiles(profiles),
+            changed: stamps,
+            ..Shared::default()
+        }
+    }
+
+    #[test]
+    fn folds_a_copy_against_the_older_parent() {
+        // This machine wrote revision 4 on top of 3 and then revision 5. Another machine wrote
+        // its own revision 4 on top of 3, which the sync tool kept as a copy.
+        let remote = revision(
+            5,
+            4,
+            &[profile("a", "F1"), profile("b", "B"), profile("c", "F3")],
+            &[("a", 4), ("b", 1), ("c", 5)],
+        );
+        let copy = revision(
+            4,
+            3,
+            &[profile("a", "F9"), profile("b", "F2"), profile("c", "C")],
+            &[("a", 4), ("b", 4), ("c", 2)],
+        );
+
+        let (merged, conflicts) = merge_copy(&remote.portable, &remote, &copy);
+
+        // Both changed "a" after revision 3, even though the remote's parent is 4.
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].key, key("a"));
+        assert_eq!(merged[&key("b")], item(&profile("b", "F2")));
+        assert_eq!(merged[&key("c")], item(&profile("c", "F3")));
+    }
+
+    #[test]
+    fn writes_the_next_revision() {
+        let dir = TempDir::new("sync_write");
+        let path = dir.0.join(SHARED_FILE);
+        let remote = revision(2, 1, &[profile("a", "A")], &[("a", 1)]);
+        std::fs::write(&path, toml::to_string_pretty(&remote).unwrap()).unwrap();
+
+        let written = write(&path, &remote, with_profiles(&[profile("a", "F1")]))
+            .unwrap()
+            .unwrap();
+
+        assert_eq!((written.revision, written.parent), (3, 2));
+        assert_eq!(written.changed.get(&key("a")), 3);
+        assert_eq!(shared::read(&path).unwrap().unwrap().revision, 3);
+    }
+
+    #[test]
+    fn does_not_write_over_a_newer_revision() {
+        let dir = TempDir::new("sync_write_race");
+        let path = dir.0.join(SHARED_FILE);
+        let newer = revision(3, 2, &[profile("a", "F7")], &[("a", 3)]);
+        std::fs::write(&path, toml::to_string_pretty(&newer).unwrap()).unwrap();
+
+        let remote = revision(2, 1, &[profile("a", "A")], &[("a", 1)]);
+        let written = write(&path, &remote, with_profiles(&[profile("a", "F1")])).unwrap();
+
+        assert!(written.is_none());
+        let on_disk = shared::read(&path).unwrap().unwrap();
+        assert_eq!(on_disk.revision, 3);
+        assert_eq!(on_disk.portable, newer.portable);
+        assert_eq!(std::fs::read_dir(&dir.0).unwrap().count(), 1);
+    }
+}
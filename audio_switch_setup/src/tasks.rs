@@ -1,8 +1,10 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use anyhow::Result;
 use windows::Win32::Foundation::{VARIANT_FALSE, VARIANT_TRUE};
-use windows::Win32::System::Com::{CLSCTX_INPROC_SERVER, CoCreateInstance};
+use windows::Win32::System::Com::{
+    CLSCTX_INPROC_SERVER, COINIT_MULTITHREADED, CoCreateInstance, CoInitializeEx, CoUninitialize,
+};
 use windows::Win32::System::TaskScheduler::{
     IExecAction, ITaskDefinition, ITaskFolder, ITaskService, ITaskSettings, TASK_ACTION_EXEC,
     TASK_CREATE_OR_UPDATE, TASK_LOGON_INTERACTIVE_TOKEN, TASK_RUNLEVEL_HIGHEST, TASK_TRIGGER_LOGON,
@@ -11,7 +13,7 @@ use windows::Win32::System::TaskScheduler::{
 use windows::Win32::System::Variant::VARIANT;
 use windows::core::{BSTR, ComInterface};
 
-pub fn create_autostart_task(executable_file_path: &PathBuf) -> Result<()> {
+pub fn create_autostart_task(executable_file_path: &Path, arguments: &str) -> Result<()> {
     unsafe {
         // Create an instance of the Task Scheduler service.
         let task_service: ITaskService =
@@ -59,6 +61,7 @@ pub fn create_autostart_task(executable_file_path: &PathBuf) -> Result<()> {
                 .to_str()
                 .expect("Invalid executable path."),
         ))?;
+        exec_action.SetArguments(&BSTR::from(arguments))?;
 
         let task_settings: ITaskSettings = task_definition.Settings()?;
 
@@ -101,3 +104,56 @@ pub fn delete_task() -> Result<()> {
     }
     Ok(())
 }
+
+/// Program and arguments the autostart task runs, `None` if there is no such task.
+pub fn autostart_task() -> Result<Option<(PathBuf, String)>> {
+    unsafe {
+        // Create an instance of the Task Scheduler service.
+        let task_service: ITaskService =
+            CoCreateInstance(&TaskScheduler, None, CLSCTX_INPROC_SERVER)?;
+
+        // Connect to the task service.
+        task_service.Connect(
+            VARIANT::default(),
+            VARIANT::default(),
+            VARIANT::default(),
+            VARIANT::default(),
+        )?;
+
+        let Ok(folder) = task_service.GetFolder(&BSTR::from("\\AudioSwitch")) else {
+            return Ok(None);
+        };
+        let Ok(task) = folder.GetTask(&BSTR::from("AudioSwitchAutoStart")) else {
+            return Ok(None);
+        };
+
+        // Action collections are indexed from 1.
+        let action = task.Definition()?.Actions()?.get_Item(1)?;
+        let exec_action: IExecAction = action.cast()?;
+
+        let mut path = BSTR::new();
+        exec_action.Path(&mut path)?;
+        let mut arguments = BSTR::new();
+        exec_action.Arguments(&mut arguments)?;
+
+        Ok(Some((
+            PathBuf::from(path.to_string()),
+            arguments.to_string(),
+        )))
+    }
+}
+
+/// Runs `f` with COM initialized for the task scheduler.
+pub fn with_com<T>(f: impl FnOnce() -> Result<T>) -> Result<T> {
+    unsafe {
+        CoInitializeEx(None, COINIT_MULTITHREADED)?;
+    }
+
+    let result = f();
+
+    unsafe {
+        CoUninitialize();
+    }
+
+    result
+}
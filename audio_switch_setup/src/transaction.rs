@@ -0,0 +1,532 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail};
+use inquire::Select;
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+use crate::service;
+use crate::tasks::{self, with_com};
+use crate::{backups, paths};
+
+/// Journal of the running transaction, inside the install directory.
+const JOURNAL_FILE: &str = "transaction.toml";
+
+/// Replaced and removed files are kept here until the transaction is committed.
+const BACKUP_DIR: &str = "rollback";
+
+/// What a transaction does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Operation {
+    Install,
+    Uninstall,
+}
+
+impl std::fmt::Display for Operation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Operation::Install => write!(f, "installation"),
+            Operation::Uninstall => write!(f, "uninstallation"),
+        }
+    }
+}
+
+/// A step and what is needed to undo it. Undoing also works if the step never happened or
+/// only partly, as it is journaled before it is performed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+enum Action {
+    /// Wrote `path`, which didn't exist.
+    CreateFile {
+        path: PathBuf,
+    },
+    /// Moved `path` to `backup` instead of deleting it.
+    RemoveFile {
+        path: PathBuf,
+        backup: PathBuf,
+    },
+    CreateLink {
+        link: PathBuf,
+    },
+    CreateTask {
+        executable: PathBuf,
+    },
+    DeleteTask {
+        executable: PathBuf,
+    },
+    StartService {
+        executable: PathBuf,
+    },
+    StopService {
+        executable: PathBuf,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Journal {
+    operation: Operation,
+    actions: Vec<Action>,
+    /// Whether the last action may not have completed.
+    #[serde(default)]
+    pending: bool,
+}
+
+/// Runs the side effects of an install or uninstall as reversible actions.
+///
+/// Every action is recorded in a journal file before it is performed. If an action fails, it
+/// and the ones before are rolled back. If the setup dies halfway, the journal is left behind
+/// and the next run can finish the operation, skipping what was already done, or roll it back.
+pub struct Transaction {
+    dir: PathBuf,
+    journal: Journal,
+    /// Position in the journal of the next action this run performs. Actions before it in the
+    /// journal were done by an interrupted run.
+    next: usize,
+}
+
+impl Transaction {
+    /// Starts `operation` in `dir`, or continues it if an interrupted run left a journal.
+    pub fn begin(dir: &Path, operation: Operation) -> Result<Self> {
+        match Self::load(dir)? {
+            Some(tx) if tx.journal.operation == operation => Ok(tx),
+            Some(tx) => bail!(
+                "An interrupted {} must be finished or rolled back first.",
+                tx.journal.operation
+            ),
+            None => Ok(Self {
+                dir: dir.to_path_buf(),
+                journal: Journal {
+                    operation,
+                    actions: vec![],
+                    pending: false,
+                },
+                next: 0,
+            }),
+        }
+    }
+
+    /// Loads the journal an interrupted run left in `dir`.
+    fn load(dir: &Path) -> Result<Option<Self>> {
+        let path = dir.join(JOURNAL_FILE);
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let content = std::fs::read_to_string(&path)?;
+        let journal = toml::from_str(&content)
+            .with_context(|| format!("Failed to read the journal {}.", path.display()))?;
+
+        Ok(Some(Self {
+            dir: dir.to_path_buf(),
+            journal,
+            next: 0,
+        }))
+    }
+
+    /// Whether this continues a run that was interrupted.
+    pub fn is_resumed(&self) -> bool {
+        !self.journal.actions.is_empty()
+    }
+
+    /// Writes `contents` to `path`.
+    pub fn write_file(&mut self, path: &Path, contents: &[u8]) -> Result<()> {
+        self.replace(path, |path| Ok(std::fs::write(path, contents)?))
+    }
+
+    /// Stores `config` with confy.
+    pub fn store_config(&mut self, config: Config) -> Result<()> {
+        let path = paths::config_path()?;
+        // Taken before the file is moved aside, the transaction's copy is gone once committed.
+        backups::snapshot()?;
+        self.replace(&path, |path| config.write_to(path))
+    }
+
+    /// Removes `path`. A missing file is not an error, returns whether there was one.
+    pub fn remove_file(&mut self, path: &Path) -> Result<bool> {
+        let backup = self.backup_path(path)?;
+        let mut existed = false;
+
+        self.run(
+            Action::RemoveFile {
+                path: path.to_path_buf(),
+                backup: backup.clone(),
+            },
+            || {
+                existed = path.symlink_metadata().is_ok();
+                if existed {
+                    move_file(path, &backup)?;
+                }
+                Ok(())
+            },
+        )?;
+
+        Ok(existed)
+    }
+
+    /// Creates a symlink at `link` pointing to `original`.
+    pub fn create_link(&mut self, original: &Path, link: &Path) -> Result<()> {
+        self.run(
+            Action::CreateLink {
+                link: link.to_path_buf(),
+            },
+            || Ok(std::os::windows::fs::symlink_file(original, link)?),
+        )
+    }
+
+    /// Registers the autostart task for the service at `executable`.
+    pub fn create_task(&mut self, executable: &Path) -> Result<()> {
+        self.run(
+            Action::CreateTask {
+                executable: executable.to_path_buf(),
+            },
+            || with_com(|| tasks::create_autostart_task(executable, &service::task_arguments())),
+        )
+    }
+
+    /// Deletes the autostart task, which starts the service at `executable`.
+    pub fn delete_task(&mut self, executable: &Path) -> Result<()> {
+        self.run(
+            Action::DeleteTask {
+                executable: executable.to_path_buf(),
+            },
+            || with_com(tasks::delete_task),
+        )
+    }
+
+    /// Starts the service at `executable` under its supervisor.
+    pub fn start_service(&mut self, executable: &Path) -> Result<()> {
+        self.run(
+            Action::StartService {
+                executable: executable.to_path_buf(),
+            },
+            || service::start_service(executable),
+        )
+    }
+
+    /// Stops the running service, which was started from `executable`.
+    pub fn stop_service(&mut self, executable: &Path) -> Result<()> {
+        self.run(
+            Action::StopService {
+                executable: executable.to_path_buf(),
+            },
+            || service::stop_service().map(|_| ()),
+        )
+    }
+
+    /// Keeps all changes and removes the journal and the backups.
+    pub fn commit(self) -> Result<()> {
+        let _ = std::fs::remove_dir_all(self.dir.join(BACKUP_DIR));
+        remove_if_exists(&self.dir.join(JOURNAL_FILE))
+    }
+
+    /// Undoes the journaled actions, newest first. An action that can't be undone stays in the
+    /// journal, so a later run can try again.
+    pub fn roll_back(mut self) -> Result<()> {
+        while let Some(action) = self.journal.actions.pop() {
+            if let Err(err) = undo(&action) {
+                self.journal.actions.push(action);
+                self.save()?;
+                return Err(err.context("Rollback is incomplete, run the setup again to retry."));
+            }
+            self.journal.pending = false;
+            self.save()?;
+        }
+
+        let _ = std::fs::remove_dir_all(self.dir.join(BACKUP_DIR));
+        remove_if_exists(&self.dir.join(JOURNAL_FILE))
+    }
+
+    /// Replaces `path` by calling `write`, the previous file is kept as backup.
+    fn replace(&mut self, path: &Path, write: impl FnOnce(&Path) -> Result<()>) -> Result<()> {
+        self.remove_file(path)?;
+        self.run(
+            Action::CreateFile {
+                path: path.to_path_buf(),
+            },
+            || write(path),
+        )
+    }
+
+    /// Journals `action`, then performs it unless an interrupted run already did at this point.
+    ///
+    /// Actions are matched by their position in the journal, so doing the same thing twice,
+    /// like writing a file and later removing it, is done twice. The action the interrupted
+    /// run was performing is undone and performed again, since it may have stopped halfway.
+    fn run(&mut self, action: Action, perform: impl FnOnce() -> Result<()>) -> Result<()> {
+        if let Some(journaled) = self.journal.actions.get(self.next) {
+            if *journaled != action {
+                bail!(
+                    "The interrupted {} went differently from here, roll it back instead.",
+                    self.journal.operation
+                );
+            }
+
+            let is_last = self.next + 1 == self.journal.actions.len();
+            if !(is_last && self.journal.pending) {
+                self.next += 1;
+                return Ok(());
+            }
+
+            undo(&action)?;
+            self.journal.actions.pop();
+        }
+
+        self.journal.actions.push(action);
+        self.journal.pending = true;
+        self.save()?;
+
+        perform()?;
+
+        self.next += 1;
+        self.journal.pending = false;
+        self.save()
+    }
+
+    /// Where the next action keeps the file at `path`. Prefixed with the action's position, so
+    /// files of the same name in different directories don't overwrite each other.
+    fn backup_path(&self, path: &Path) -> Result<PathBuf> {
+        let dir = self.dir.join(BACKUP_DIR);
+        std::fs::create_dir_all(&dir)?;
+
+        let name = path.file_name().context("Path has no file name.")?;
+        Ok(dir.join(format!("{}-{}", self.next, name.to_string_lossy())))
+    }
+
+    /// Writes the journal to a temporary file first, so a crash never leaves half of it, and
+    /// flushes it to disk before an action relies on it.
+    fn save(&self) -> Result<()> {
+        let path = self.dir.join(JOURNAL_FILE);
+        let staging = path.with_extension("toml.new");
+
+        let mut file = std::fs::File::create(&staging)?;
+        file.write_all(toml::to_string_pretty(&self.journal)?.as_bytes())?;
+        file.sync_all()?;
+        std::fs::rename(&staging, &path)?;
+
+        Ok(())
+    }
+}
+
+/// Offers to finish or roll back an operation an earlier run didn't complete. Returns the
+/// operation to finish.
+pub fn recover(dir: &Path) -> Result<Option<Operation>> {
+    let Some(tx) = Transaction::load(dir)? else {
+        return Ok(None);
+    };
+
+    let operation = tx.journal.operation;
+    let finish = format!("Finish the {operation}.");
+    let roll_back = format!("Roll back the {operation}.");
+
+    let choice = Select::new(
+        &format!("An earlier {operation} was interrupted:"),
+        vec![finish.clone(), roll_back],
+    )
+    .prompt()?;
+
+    if choice == finish {
+        return Ok(Some(operation));
+    }
+
+    tx.roll_back()?;
+    println!("    Rolling back the interrupted {operation}... Done ✔");
+
+    Ok(None)
+}
+
+fn undo(action: &Action) -> Result<()> {
+    match action {
+        Action::CreateFile { path } => remove_if_exists(path),
+        Action::RemoveFile { path, backup } => {
+            if backup.symlink_metadata().is_ok() {
+                move_file(backup, path)?;
+            }
+            Ok(())
+        }
+        Action::CreateLink { link } => remove_if_exists(link),
+        Action::CreateTask { .. } => with_com(|| {
+            if tasks::autostart_task()?.is_some() {
+                tasks::delete_task()?;
+            }
+            Ok(())
+        }),
+        Action::DeleteTask { executable } => {
+            with_com(|| tasks::create_autostart_task(executable, &service::task_arguments()))
+        }
+        Action::StartService { .. } => service::stop_service().map(|_| ()),
+        Action::StopService { executable } => {
+            if executable.exists() && !service::is_running()? {
+                service::start_service(executable)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Moves a file, falling back to copying it when `to` is on another volume.
+fn move_file(from: &Path, to: &Path) -> Result<()> {
+    if std::fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+
+    std::fs::copy(from, to)
+        .with_context(|| format!("Failed to move {} to {}.", from.display(), to.display()))?;
+    std::fs::remove_file(from)?;
+
+    Ok(())
+}
+
+fn remove_if_exists(path: &Path) -> Result<()> {
+    match std::fs::remove_file(path) {
+        Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err.into()),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    fn read(path: &Path) -> String {
+        std::fs::read_to_string(path).unwrap()
+    }
+
+    #[test]
+    fn rollback_restores_a_replaced_file() {
+        let dir = TempDir::new("transaction_replace");
+        let file = dir.0.join("config.toml");
+        std::fs::write(&file, "old").unwrap();
+
+        let mut tx = Transaction::begin(&dir.0, Operation::Install).unwrap();
+        tx.write_file(&file, b"new").unwrap();
+        assert_eq!(read(&file), "new");
+
+        tx.roll_back().unwrap();
+        assert_eq!(read(&file), "old");
+        assert!(!dir.0.join(JOURNAL_FILE).exists());
+    }
+
+    #[test]
+    fn rollback_removes_a_new_file() {
+        let dir = TempDir::new("transaction_create");
+        let file = dir.0.join("AudioSwitchService.exe");
+
+        let mut tx = Transaction::begin(&dir.0, Operation::Install).unwrap();
+        tx.write_file(&file, b"new").unwrap();
+
+        tx.roll_back().unwrap();
+        assert!(!file.exists());
+    }
+
+    #[test]
+    fn rollback_restores_files_of_the_same_name() {
+        let dir = TempDir::new("transaction_same_name");
+        let (a, b) = (dir.0.join("a"), dir.0.join("b"));
+        std::fs::create_dir_all(&a).unwrap();
+        std::fs::create_dir_all(&b).unwrap();
+        let (export, config) = (a.join("config.toml"), b.join("config.toml"));
+        std::fs::write(&export, "older export").unwrap();
+        std::fs::write(&config, "config").unwrap();
+
+        let mut tx = Transaction::begin(&dir.0, Operation::Uninstall).unwrap();
+        tx.write_file(&export, b"export").unwrap();
+        assert!(tx.remove_file(&config).unwrap());
+        assert!(!config.exists());
+
+        tx.roll_back().unwrap();
+        assert_eq!(read(&export), "older export");
+        assert_eq!(read(&config), "config");
+    }
+
+    #[test]
+    fn repeated_writes_to_one_file_are_all_done_and_undone() {
+        let dir = TempDir::new("transaction_repeated");
+        let file = dir.0.join("config.toml");
+        std::fs::write(&file, "first").unwrap();
+
+        let mut tx = Transaction::begin(&dir.0, Operation::Install).unwrap();
+        tx.write_file(&file, b"second").unwrap();
+        tx.write_file(&file, b"third").unwrap();
+        assert_eq!(read(&file), "third");
+
+        tx.roll_back().unwrap();
+        assert_eq!(read(&file), "first");
+    }
+
+    /// Leaves `dir` as a crash right after writing `file`, before the journal was updated.
+    fn crash_after_writing(dir: &Path, file: &Path) {
+        let mut tx = Transaction::begin(dir, Operation::Install).unwrap();
+        let result = tx.replace(file, |path| {
+            std::fs::write(path, "new")?;
+            bail!("Crashed.")
+        });
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn rollback_after_a_crash_undoes_the_unfinished_action() {
+        let dir = TempDir::new("transaction_crash_rollback");
+        let file = dir.0.join("config.toml");
+        std::fs::write(&file, "old").unwrap();
+
+        crash_after_writing(&dir.0, &file);
+        assert_eq!(read(&file), "new");
+
+        let tx = Transaction::load(&dir.0).unwrap().unwrap();
+        tx.roll_back().unwrap();
+        assert_eq!(read(&file), "old");
+    }
+
+    #[test]
+    fn rollback_after_a_crash_removes_a_new_file() {
+        let dir = TempDir::new("transaction_crash_create");
+        let file = dir.0.join("AudioSwitchService.exe");
+
+        crash_after_writing(&dir.0, &file);
+
+        let tx = Transaction::load(&dir.0).unwrap().unwrap();
+        tx.roll_back().unwrap();
+        assert!(!file.exists());
+    }
+
+    #[test]
+    fn resumed_run_after_a_crash_performs_the_unfinished_action_again() {
+        let dir = TempDir::new("transaction_crash_resume");
+        let file = dir.0.join("config.toml");
+        std::fs::write(&file, "old").unwrap();
+
+        crash_after_writing(&dir.0, &file);
+
+        let mut tx = Transaction::begin(&dir.0, Operation::Install).unwrap();
+        assert!(tx.is_resumed());
+        tx.write_file(&file, b"resumed").unwrap();
+        assert_eq!(read(&file), "resumed");
+
+        tx.roll_back().unwrap();
+        assert_eq!(read(&file), "old");
+    }
+
+    #[test]
+    fn resumed_run_skips_what_was_done() {
+        let dir = TempDir::new("transaction_resume");
+        let file = dir.0.join("config.toml");
+        std::fs::write(&file, "first").unwrap();
+
+        let mut tx = Transaction::begin(&dir.0, Operation::Install).unwrap();
+        tx.write_file(&file, b"second").unwrap();
+        drop(tx);
+
+        let mut tx = Transaction::begin(&dir.0, Operation::Install).unwrap();
+        assert!(tx.is_resumed());
+        tx.write_file(&file, b"ignored").unwrap();
+        assert_eq!(read(&file), "second");
+        tx.write_file(&file, b"third").unwrap();
+        assert_eq!(read(&file), "third");
+
+        tx.roll_back().unwrap();
+        assert_eq!(read(&file), "first");
+    }
+}
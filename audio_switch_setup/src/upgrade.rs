@@ -0,0 +1,147 @@
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+use crate::tasks::{self, with_com};
+use crate::version::{self, Version};
+use crate::{migrations, service};
+
+/// Replaces an installed AudioSwitch with the version of this setup. The config is kept and
+/// migrated. Refuses to install an older version unless `force` is set.
+pub fn upgrade_program(install_dir: &Path, service_bin: &[u8], force: bool) -> Result<()> {
+    let service_path = install_dir.join("AudioSwitchService.exe");
+    let setup_path = install_dir.join("AudioSwitchSetup.exe");
+
+    let new = version::current();
+    let installed = version::file_version(&service_path).unwrap_or(None);
+
+    match installed {
+        Some(installed) if installed > new && !force => {
+            println!(
+                "    AudioSwitch {installed} is installed, this setup would downgrade it to {new}."
+            );
+            println!("    Run the setup with --force to downgrade anyway.");
+            return Ok(());
+        }
+        Some(installed)
+            if installed == new
+                && !inquire::Confirm::new(&format!(
+                    "AudioSwitch {installed} is already installed. Reinstall it? (y/n)"
+                ))
+                .prompt()? =>
+        {
+            return Ok(());
+        }
+        _ => {}
+    }
+
+    println!(
+        "    Upgrading AudioSwitch from {} to {new}...",
+        installed.map_or_else(
+            || "an older version".to_string(),
+            |it: Version| it.to_string()
+        )
+    );
+
+    service::stop_service()?;
+
+    let setup_bin = std::fs::read(std::env::current_exe()?)?;
+    replace_files(&[
+        (service_path.clone(), service_bin),
+        (setup_path, &setup_bin),
+    ])?;
+
+    println!("    Replacing Program Files... Done ✔");
+
+    for description in migrations::migrate()? {
+        println!("    Migrating Configuration: {description}... Done ✔");
+    }
+
+    // Older versions registered the task with other arguments.
+    with_com(|| tasks::create_autostart_task(&service_path, &service::task_arguments()))?;
+
+    println!("    Updating Autostart Entry... Done ✔");
+
+    service::start_service(&service_path)?;
+
+    println!("    > Program was sucessfully upgraded to {new}!");
+
+    Ok(())
+}
+
+/// Writes new contents for all `files`, replacing either all of them or, on failure, none.
+///
+/// The contents are staged next to their targets first, so only renames within the install
+/// directory remain once the old files are touched. Renaming works even for the running setup,
+/// whose old file is left behind and removed by the next upgrade.
+fn replace_files(files: &[(PathBuf, &[u8])]) -> Result<()> {
+    let mut staged = vec![];
+
+    for (target, contents) in files {
+        let staging = target.with_extension("exe.new");
+        let _ = std::fs::remove_file(target.with_extension("exe.old"));
+
+        let written = File::create(&staging).and_then(|mut file| {
+            file.write_all(contents)?;
+            file.sync_all()
+        });
+
+        if let Err(err) = written {
+            remove_staged(&staged);
+            let _ = std::fs::remove_file(&staging);
+            return Err(err).with_context(|| format!("Failed to write {}.", staging.display()));
+        }
+
+        staged.push(staging);
+    }
+
+    let mut replaced: Vec<(&Path, bool)> = vec![];
+
+    for ((target, _), staging) in files.iter().zip(&staged) {
+        let backup = target.with_extension("exe.old");
+        let existed = target.exists();
+
+        let result = (|| {
+            if existed {
+                std::fs::rename(target, &backup)?;
+            }
+            std::fs::rename(staging, target)
+        })();
+
+        if let Err(err) = result {
+            if existed && !target.exists() {
+                let _ = std::fs::rename(&backup, target);
+            }
+            roll_back(&replaced);
+            remove_staged(&staged);
+            return Err(err).with_context(|| format!("Failed to replace {}.", target.display()));
+        }
+
+        replaced.push((target, existed));
+    }
+
+    for (target, _) in replaced {
+        let _ = std::fs::remove_file(target.with_extension("exe.old"));
+    }
+
+    Ok(())
+}
+
+/// Restores the backups of already replaced files.
+fn roll_back(replaced: &[(&Path, bool)]) {
+    for &(target, existed) in replaced.iter().rev() {
+        if existed {
+            let _ = std::fs::rename(target.with_extension("exe.old"), target);
+        } else {
+            let _ = std::fs::remove_file(target);
+        }
+    }
+}
+
+fn remove_staged(staged: &[PathBuf]) {
+    for path in staged {
+        let _ = std::fs::remove_file(path);
+    }
+}
@@ -0,0 +1,125 @@
+use std::path::Path;
+use std::str::FromStr;
+
+use anyhow::{Context, Result, anyhow};
+use windows::Win32::Storage::FileSystem::{
+    GetFileVersionInfoSizeW, GetFileVersionInfoW, VS_FIXEDFILEINFO, VerQueryValueW,
+};
+use windows::core::{HSTRING, w};
+
+/// A `major.minor.patch` program version, ordered by its parts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Version {
+    pub major: u16,
+    pub minor: u16,
+    pub patch: u16,
+}
+
+impl std::fmt::Display for Version {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+impl FromStr for Version {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut parts = s.splitn(3, '.').map(|it| {
+            it.parse::<u16>()
+                .with_context(|| format!("Invalid version \"{s}\"."))
+        });
+        let mut next = || parts.next().unwrap_or(Ok(0));
+
+        Ok(Self {
+            major: next()?,
+            minor: next()?,
+            patch: next()?,
+        })
+    }
+}
+
+/// Version of this setup and of the service embedded in it.
+pub fn current() -> Version {
+    env!("CARGO_PKG_VERSION")
+        .parse()
+        .expect("Package version is not a valid version.")
+}
+
+/// Reads the version resource of the executable at `path`. Returns `None` for files without
+/// one, which includes services installed before the resource was added.
+pub fn file_version(path: &Path) -> Result<Option<Version>> {
+    if !path.exists() {
+        return Err(anyhow!("{} does not exist.", path.display()));
+    }
+
+    let path = HSTRING::from(path);
+
+    unsafe {
+        let size = GetFileVersionInfoSizeW(&path, None);
+        if size == 0 {
+            return Ok(None);
+        }
+
+        let mut data = vec![0u8; size as usize];
+        GetFileVersionInfoW(&path, 0, size, data.as_mut_ptr().cast())?;
+
+        let mut info = std::ptr::null_mut();
+        let mut len = 0;
+        if !VerQueryValueW(data.as_ptr().cast(), w!("\\"), &mut info, &mut len).as_bool()
+            || (len as usize) < size_of::<VS_FIXEDFILEINFO>()
+        {
+            return Ok(None);
+        }
+
+        let info = &*(info as *const VS_FIXEDFILEINFO);
+
+        Ok(Some(Version {
+            major: (info.dwFileVersionMS >> 16) as u16,
+            minor: info.dwFileVersionMS as u16,
+            patch: (info.dwFileVersionLS >> 16) as u16,
+        }))
+    }
+}
+
+/// Whether the installed service is older than `current`, given its version as read by
+/// [`file_version`]. A service so old it has no version resource is older too. It can't read the
+/// migrated config and has to be upgraded.
+pub fn is_outdated(installed: &Result<Option<Version>>, current: Version) -> bool {
+    match installed {
+        Err(_) => false,
+        Ok(None) => true,
+        Ok(Some(installed)) => *installed < current,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn version(s: &str) -> Version {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn nothing_is_outdated_without_an_installed_service() {
+        let missing = Err(anyhow!("AudioSwitchService.exe does not exist."));
+        assert!(!is_outdated(&missing, version("1.2.0")));
+    }
+
+    #[test]
+    fn a_service_without_version_is_outdated() {
+        assert!(is_outdated(&Ok(None), version("1.2.0")));
+    }
+
+    #[test]
+    fn an_older_service_is_outdated() {
+        assert!(is_outdated(&Ok(Some(version("1.1.9"))), version("1.2.0")));
+    }
+
+    #[test]
+    fn a_current_or_newer_service_is_not_outdated() {
+        assert!(!is_outdated(&Ok(Some(version("1.2.0"))), version("1.2.0")));
+        assert!(!is_outdated(&Ok(Some(version("2.0"))), version("1.2.0")));
+    }
+}